@@ -24,6 +24,13 @@ enum Commands {
         #[clap(short)]
         yes: bool,
     },
+    /// Move expired sessions from the database at `DATABASE_URL` into
+    /// `sessions_deleted` once.
+    SweepSessions {
+        /// Number of rows to delete per statement.
+        #[clap(long, env = "SESSION_SWEEP_BATCH_SIZE")]
+        batch_size: Option<u32>,
+    },
 }
 
 fn main() -> Result<(), AnyErr> {
@@ -38,7 +45,7 @@ fn main() -> Result<(), AnyErr> {
         Commands::Test { args } => {
             if is_nextest_avail() {
                 assert!(
-                    show_cmd(cargo_cmd().args(&[
+                    show_cmd(cargo_cmd().args([
                         "nextest",
                         "run",
                         if let Some(ref args) = args {
@@ -54,7 +61,7 @@ fn main() -> Result<(), AnyErr> {
                 );
             } else {
                 assert!(
-                    show_cmd(cargo_cmd().args(&["test"]))
+                    show_cmd(cargo_cmd().args(["test"]))
                         .status()
                         .unwrap()
                         .success(),
@@ -64,14 +71,14 @@ fn main() -> Result<(), AnyErr> {
         }
         Commands::PreCommit {} => {
             assert!(
-                show_cmd(cargo_cmd().args(&["fmt",]))
+                show_cmd(cargo_cmd().args(["fmt",]))
                     .status()
                     .unwrap()
                     .success(),
                 "failed to cargo fmt"
             );
             assert!(
-                show_cmd(cargo_cmd().args(&["sqlx", "prepare", "--", "--lib",]))
+                show_cmd(cargo_cmd().args(["sqlx", "prepare", "--", "--lib",]))
                     .status()
                     .unwrap()
                     .success(),
@@ -80,7 +87,7 @@ fn main() -> Result<(), AnyErr> {
             assert!(
                 show_cmd(
                     cargo_cmd()
-                        .args(&["run", "--bin", "print_oas",])
+                        .args(["run", "--bin", "print_oas",])
                         .stdout(std::fs::File::create("api.oas3.json")?)
                 )
                 .status()
@@ -91,7 +98,7 @@ fn main() -> Result<(), AnyErr> {
         }
         Commands::ResetDb { yes: no_confirm } => {
             let mut sqlx_cmd = cargo_cmd();
-            sqlx_cmd.args(&["sqlx", "database", "reset"]);
+            sqlx_cmd.args(["sqlx", "database", "reset"]);
             if no_confirm {
                 sqlx_cmd.arg("-y");
             }
@@ -100,7 +107,7 @@ fn main() -> Result<(), AnyErr> {
                 "failed to reset database"
             );
             assert!(
-                show_cmd(Command::new("podman").args(&[
+                show_cmd(Command::new("podman").args([
                     "exec",
                     "-i",
                     "postgres-server-dev", // FIXME: read this name from the compose file
@@ -116,6 +123,17 @@ fn main() -> Result<(), AnyErr> {
                 "failed to repopulate database"
             );
         }
+        Commands::SweepSessions { batch_size } => {
+            let mut cmd = cargo_cmd();
+            cmd.args(["run", "--bin", "sweep_sessions"]);
+            if let Some(batch_size) = batch_size {
+                cmd.env("SESSION_SWEEP_BATCH_SIZE", batch_size.to_string());
+            }
+            assert!(
+                show_cmd(&mut cmd).status().unwrap().success(),
+                "failed to sweep sessions"
+            );
+        }
     }
     Ok(())
 }
@@ -7,12 +7,15 @@ DO $body$
         le_user    users;
     BEGIN
         INSERT INTO users (
-            id, username, email, pic_url
+            id, username, email, pic_url, created_at, updated_at, email_verified_at
         ) VALUES (
             'add83cdf-2ab3-443f-84dd-476d7984cf75'::uuid,
             'sabrina',
             'hex.queen@teen.dj',
-            'https://obj.teen.dj/d78xas'
+            'https://obj.teen.dj/d78xas',
+            CURRENT_TIMESTAMP - interval '4 days',
+            CURRENT_TIMESTAMP - interval '4 days',
+            CURRENT_TIMESTAMP - interval '4 days'
         ) RETURNING * INTO le_user;
         INSERT INTO credentials (
             user_id, pass_hash
@@ -27,6 +30,14 @@ DO $body$
             le_user.id,
             CURRENT_TIMESTAMP + interval '7 days'
         );
+        INSERT INTO sessions (
+            id, token, user_id, expires_at
+        ) VALUES (
+            '0f6e9a43-7c1d-4b8e-a2f5-3d9c6b1e8a70',
+            '5a7c8f3e-0d4b-4c1e-9f6a-2b3d4e5f6a7b',
+            le_user.id,
+            CURRENT_TIMESTAMP + interval '7 days'
+        );
     END;
 $body$ LANGUAGE PLpgSQL;
 
@@ -35,12 +46,15 @@ DO $body$
         le_user    users;
     BEGIN
         INSERT INTO users (
-            id, username, email, pic_url
+            id, username, email, pic_url, created_at, updated_at, email_verified_at
         ) VALUES (
             'ce4fe993-04d6-462e-af1d-d734fcc9639d'::uuid,
             'archie',
             'archie1941@poetry.ybn',
-            'https://pictu.res/01'
+            'https://pictu.res/01',
+            CURRENT_TIMESTAMP - interval '3 days',
+            CURRENT_TIMESTAMP - interval '3 days',
+            CURRENT_TIMESTAMP - interval '3 days'
         ) RETURNING * INTO le_user;
         INSERT INTO credentials (
             user_id, pass_hash
@@ -56,12 +70,15 @@ DO $body$
         le_user    users;
     BEGIN
         INSERT INTO users (
-            id, username, email, pic_url
+            id, username, email, pic_url, created_at, updated_at, email_verified_at
         ) VALUES (
             'd437e73f-4610-462c-ab22-f94b76bba83a'::uuid,
             'betty',
             'pInXy@melt.shake',
-            null
+            null,
+            CURRENT_TIMESTAMP - interval '2 days',
+            CURRENT_TIMESTAMP - interval '2 days',
+            NULL
         ) RETURNING * INTO le_user;
         INSERT INTO credentials (
             user_id, pass_hash
@@ -77,12 +94,15 @@ DO $body$
         le_user    users;
     BEGIN
         INSERT INTO users (
-            id, username, email, pic_url
+            id, username, email, pic_url, created_at, updated_at, email_verified_at
         ) VALUES (
             '68cf4d43-62d2-4202-8c50-c79a5f4dd1cc'::uuid,
             'veronica',
             'trekkiegirl@ln.pi',
-            'ipns://goatsie'
+            'ipns://goatsie',
+            CURRENT_TIMESTAMP - interval '1 days',
+            CURRENT_TIMESTAMP - interval '1 days',
+            CURRENT_TIMESTAMP - interval '1 days'
         ) RETURNING * INTO le_user;
         INSERT INTO credentials (
             user_id, pass_hash
@@ -91,12 +111,19 @@ DO $body$
             '$argon2i$v=19$m=4096,t=3,p=1$c29tZXNhbHQ$iWh06vD8Fy27wf9npn6FXWiCX4K6pW6Ue1Bnzz07Z8A'
         );
         INSERT INTO sessions (
-            token, user_id, expires_at
+            id, token, user_id, expires_at
         ) VALUES (
+            '6c2b9d1e-4a7f-4e3c-8b5d-1f0a9e7c3d24',
             'ebd3b465-be17-4077-bc4a-add9f76b5028',
             le_user.id,
             CURRENT_TIMESTAMP + interval '7 days'
         );
+        INSERT INTO user_roles (
+            user_id, role
+        ) VALUES (
+            le_user.id,
+            'superAdmin'
+        );
     END;
 $body$ LANGUAGE PLpgSQL;
 
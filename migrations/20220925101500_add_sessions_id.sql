@@ -0,0 +1,59 @@
+-- A non-secret handle for sessions so they can be listed and revoked without
+-- exposing the bearer token. Backfilled for existing rows, archived included.
+ALTER TABLE __sessions_core
+    ADD COLUMN id   UUID    NOT NULL    DEFAULT uuid_generate_v4();
+
+CREATE UNIQUE INDEX ON sessions (id);
+
+-- Columns added to `__sessions_core` are appended after `deleted_at` on
+-- `sessions_deleted` which means `SELECT *` based moves no longer line up.
+-- Archive rows by name instead whenever they're deleted from `sessions`.
+CREATE FUNCTION archive_deleted_session()
+RETURNS TRIGGER
+AS $body$
+    BEGIN
+        INSERT INTO sessions_deleted
+        SELECT * FROM jsonb_populate_record(
+            NULL::sessions_deleted,
+            to_jsonb(OLD) || jsonb_build_object('deleted_at', CURRENT_TIMESTAMP)
+        );
+        RETURN OLD;
+    END;
+$body$ LANGUAGE PLpgSQL;
+
+CREATE TRIGGER archive_deleted_sessions
+    AFTER DELETE
+    ON sessions
+    FOR EACH ROW
+    EXECUTE PROCEDURE archive_deleted_session();
+
+CREATE OR REPLACE FUNCTION delete_user(target_id UUID) RETURNS BOOLEAN
+AS $body$
+    BEGIN
+        IF NOT (EXISTS (SELECT id FROM users WHERE id = target_id)) THEN
+          RETURN FALSE;
+        END IF;
+
+        -- delete foreign keys that refer to users first to avoid referential
+        -- integrity errors
+        WITH deleted AS (
+          DELETE FROM credentials
+          WHERE user_id = target_id
+          RETURNING *
+        )
+        INSERT INTO credentials_deleted SELECT * FROM deleted;
+
+        -- archived by the `archive_deleted_sessions` trigger
+        DELETE FROM sessions
+        WHERE user_id = target_id;
+
+        WITH deleted AS (
+          DELETE FROM users
+          WHERE id = target_id
+          RETURNING *
+        )
+        INSERT INTO users_deleted SELECT * FROM deleted;
+
+        RETURN TRUE;
+    END;
+$body$ LANGUAGE PLpgSQL;
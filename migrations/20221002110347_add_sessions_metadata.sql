@@ -0,0 +1,5 @@
+ALTER TABLE __sessions_core
+    ADD COLUMN user_agent      TEXT,
+    ADD COLUMN ip_addr         TEXT,
+    ADD COLUMN device_label    TEXT,
+    ADD COLUMN last_seen_at    TIMESTAMPTZ     NOT NULL    DEFAULT CURRENT_TIMESTAMP;
@@ -0,0 +1,16 @@
+-- Sessions minted from the same authentication share a `family_id`. Each
+-- refresh rotates the session, the replaced row is archived in
+-- `sessions_deleted` with `rotated_at` set which is how reuse of a spent
+-- refresh token is told apart from sessions that were merely revoked or
+-- swept.
+ALTER TABLE __sessions_core
+    ADD COLUMN family_id            UUID            NOT NULL    DEFAULT gen_random_uuid(),
+    ADD COLUMN refresh_token        TEXT,
+    ADD COLUMN refresh_expires_at   TIMESTAMPTZ;
+
+ALTER TABLE sessions_deleted
+    ADD COLUMN rotated_at           TIMESTAMPTZ;
+
+CREATE UNIQUE INDEX ON sessions (refresh_token);
+CREATE INDEX ON sessions (family_id);
+CREATE INDEX ON sessions_deleted (refresh_token);
@@ -0,0 +1,81 @@
+CREATE TABLE __user_roles_core (
+    user_id     UUID    NOT NULL,
+    role        TEXT    NOT NULL
+) INHERITS (__common);
+
+CREATE TABLE user_roles (
+    PRIMARY KEY(user_id, role),
+    CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id)
+) INHERITS (__user_roles_core);
+
+CREATE TABLE user_roles_deleted (
+    deleted_at  TIMESTAMPTZ NOT NULL    DEFAULT CURRENT_TIMESTAMP
+) INHERITS (__user_roles_core);
+
+-- Maps (role, resource, action) to a decision. A user is allowed to act if
+-- any of their roles allows it and none deny it.
+CREATE TABLE auth_policies (
+    role        TEXT        NOT NULL,
+    resource    TEXT        NOT NULL,
+    action      TEXT        NOT NULL,
+    allow       BOOLEAN     NOT NULL,
+    PRIMARY KEY(role, resource, action)
+) INHERITS (__common);
+
+CREATE TRIGGER maintain_updated_at_auth_policies
+    BEFORE UPDATE
+    ON auth_policies
+    FOR EACH ROW
+    EXECUTE PROCEDURE maintain_updated_at();
+
+INSERT INTO auth_policies (role, resource, action, allow)
+VALUES
+    ('superAdmin', 'user', 'read', TRUE),
+    ('superAdmin', 'user', 'write', TRUE),
+    ('superAdmin', 'user', 'delete', TRUE),
+    ('superAdmin', 'users', 'read', TRUE),
+    ('superAdmin', 'users', 'write', TRUE),
+    ('superAdmin', 'users', 'delete', TRUE),
+    ('superAdmin', 'session', 'read', TRUE),
+    ('superAdmin', 'session', 'write', TRUE),
+    ('superAdmin', 'session', 'delete', TRUE),
+    ('admin', 'user', 'read', TRUE),
+    ('admin', 'users', 'read', TRUE);
+
+CREATE OR REPLACE FUNCTION delete_user(target_id UUID) RETURNS BOOLEAN
+AS $body$
+    BEGIN
+        IF NOT (EXISTS (SELECT id FROM users WHERE id = target_id)) THEN
+          RETURN FALSE;
+        END IF;
+
+        -- delete foreign keys that refer to users first to avoid referential
+        -- integrity errors
+        WITH deleted AS (
+          DELETE FROM credentials
+          WHERE user_id = target_id
+          RETURNING *
+        )
+        INSERT INTO credentials_deleted SELECT * FROM deleted;
+
+        -- archived by the `archive_deleted_sessions` trigger
+        DELETE FROM sessions
+        WHERE user_id = target_id;
+
+        WITH deleted AS (
+          DELETE FROM user_roles
+          WHERE user_id = target_id
+          RETURNING *
+        )
+        INSERT INTO user_roles_deleted SELECT * FROM deleted;
+
+        WITH deleted AS (
+          DELETE FROM users
+          WHERE id = target_id
+          RETURNING *
+        )
+        INSERT INTO users_deleted SELECT * FROM deleted;
+
+        RETURN TRUE;
+    END;
+$body$ LANGUAGE PLpgSQL;
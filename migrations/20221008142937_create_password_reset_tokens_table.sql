@@ -0,0 +1,9 @@
+CREATE TABLE password_reset_tokens (
+    token_hash      TEXT            NOT NULL,
+    user_id         UUID            NOT NULL,
+    expires_at      TIMESTAMPTZ     NOT NULL,
+    PRIMARY KEY(token_hash),
+    CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) INHERITS (__common);
+
+CREATE INDEX ON password_reset_tokens (user_id);
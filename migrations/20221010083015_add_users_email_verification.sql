@@ -0,0 +1,98 @@
+ALTER TABLE __users_core
+    ADD COLUMN email_verified_at    TIMESTAMPTZ;
+
+CREATE TABLE email_verification_tokens (
+    token_hash      TEXT            NOT NULL,
+    user_id         UUID            NOT NULL,
+    -- the address the token was sent to, it only verifies that one
+    email           CITEXT          NOT NULL,
+    expires_at      TIMESTAMPTZ     NOT NULL,
+    PRIMARY KEY(token_hash),
+    CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) INHERITS (__common);
+
+CREATE INDEX ON email_verification_tokens (user_id);
+
+-- changing the email invalidates its verification
+CREATE OR REPLACE FUNCTION update_user(
+  user_id UUID,
+  new_username CITEXT,
+  new_email CITEXT,
+  new_pic_url TEXT,
+  new_pass_hash TEXT
+)
+RETURNS SETOF users -- use SETOF to allow return of 0 rows
+AS $body$
+    DECLARE
+        le_user    users;
+    BEGIN
+        UPDATE users
+        SET
+            username = COALESCE(new_username, username),
+            email = COALESCE(new_email, email),
+            pic_url = COALESCE(new_pic_url, pic_url),
+            email_verified_at = CASE
+                WHEN new_email IS NULL OR new_email = email THEN email_verified_at
+                ELSE NULL
+            END
+        WHERE id = user_id
+        RETURNING * INTO le_user;
+
+        IF NOT FOUND THEN
+          RETURN;
+        END IF;
+
+        IF new_pass_hash != NULL THEN
+            UPDATE credentials
+            SET pass_hash = new_pass_hash
+            WHERE user_id = user_id;
+        END IF;
+        RETURN NEXT le_user;
+    END;
+$body$ LANGUAGE PLpgSQL;
+
+-- Columns added to `__users_core` are appended after `deleted_at` on
+-- `users_deleted` so `SELECT *` based moves no longer line up, archive by
+-- name instead.
+CREATE OR REPLACE FUNCTION delete_user(target_id UUID) RETURNS BOOLEAN
+AS $body$
+    BEGIN
+        IF NOT (EXISTS (SELECT id FROM users WHERE id = target_id)) THEN
+          RETURN FALSE;
+        END IF;
+
+        -- delete foreign keys that refer to users first to avoid referential
+        -- integrity errors
+        WITH deleted AS (
+          DELETE FROM credentials
+          WHERE user_id = target_id
+          RETURNING *
+        )
+        INSERT INTO credentials_deleted SELECT * FROM deleted;
+
+        -- archived by the `archive_deleted_sessions` trigger
+        DELETE FROM sessions
+        WHERE user_id = target_id;
+
+        WITH deleted AS (
+          DELETE FROM user_roles
+          WHERE user_id = target_id
+          RETURNING *
+        )
+        INSERT INTO user_roles_deleted SELECT * FROM deleted;
+
+        WITH deleted AS (
+          DELETE FROM users
+          WHERE id = target_id
+          RETURNING *
+        )
+        INSERT INTO users_deleted
+        SELECT (jsonb_populate_record(
+            NULL::users_deleted,
+            to_jsonb(deleted) || jsonb_build_object('deleted_at', CURRENT_TIMESTAMP)
+        )).*
+        FROM deleted;
+
+        RETURN TRUE;
+    END;
+$body$ LANGUAGE PLpgSQL;
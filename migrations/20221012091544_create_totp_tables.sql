@@ -0,0 +1,42 @@
+CREATE TABLE totp_secrets (
+    user_id         UUID            NOT NULL,
+    -- base32 encoded, needed in the clear to compute codes
+    secret          TEXT            NOT NULL,
+    -- two-factor authentication is only enforced once confirmed
+    confirmed_at    TIMESTAMPTZ,
+    -- the time step of the last accepted code, codes can't be replayed
+    last_used_step  BIGINT,
+    PRIMARY KEY(user_id),
+    CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) INHERITS (__common);
+
+CREATE TRIGGER maintain_updated_at_totp_secrets
+    BEFORE UPDATE
+    ON totp_secrets
+    FOR EACH ROW
+        EXECUTE PROCEDURE maintain_updated_at();
+
+CREATE TABLE totp_recovery_codes (
+    code_hash       TEXT            NOT NULL,
+    user_id         UUID            NOT NULL,
+    PRIMARY KEY(code_hash),
+    CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) INHERITS (__common);
+
+CREATE INDEX ON totp_recovery_codes (user_id);
+
+-- handed out by authenticate in place of a session to users with 2FA
+CREATE TABLE totp_challenges (
+    token_hash      TEXT            NOT NULL,
+    user_id         UUID            NOT NULL,
+    expires_at      TIMESTAMPTZ     NOT NULL,
+    attempts        INTEGER         NOT NULL    DEFAULT 0,
+    -- carried over to the session once the challenge is passed
+    user_agent      TEXT,
+    ip_addr         TEXT,
+    device_label    TEXT,
+    PRIMARY KEY(token_hash),
+    CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) INHERITS (__common);
+
+CREATE INDEX ON totp_challenges (user_id);
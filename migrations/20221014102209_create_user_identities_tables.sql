@@ -0,0 +1,32 @@
+-- identities at OpenID Connect providers linked to users
+CREATE TABLE user_identities (
+    provider        TEXT            NOT NULL,
+    -- the `sub` claim, unique per provider
+    subject         TEXT            NOT NULL,
+    user_id         UUID            NOT NULL,
+    -- as reported by the provider, informational only
+    email           CITEXT,
+    PRIMARY KEY(provider, subject),
+    CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) INHERITS (__common);
+
+CREATE INDEX ON user_identities (user_id);
+
+CREATE TRIGGER maintain_updated_at_user_identities
+    BEFORE UPDATE
+    ON user_identities
+    FOR EACH ROW
+        EXECUTE PROCEDURE maintain_updated_at();
+
+-- pending authorization requests, spent by the callback
+CREATE TABLE oidc_states (
+    state_hash      TEXT            NOT NULL,
+    provider        TEXT            NOT NULL,
+    pkce_verifier   TEXT            NOT NULL,
+    nonce           TEXT            NOT NULL,
+    -- set when linking an identity rather than authenticating
+    link_user_id    UUID,
+    expires_at      TIMESTAMPTZ     NOT NULL,
+    PRIMARY KEY(state_hash),
+    CONSTRAINT fk_link_user_id  FOREIGN KEY(link_user_id) REFERENCES users(id) ON DELETE CASCADE
+) INHERITS (__common);
@@ -0,0 +1,24 @@
+CREATE TABLE api_keys (
+    id              UUID            NOT NULL    DEFAULT gen_random_uuid(),
+    user_id         UUID            NOT NULL,
+    name            TEXT            NOT NULL,
+    -- the start of the key, the whole key is only stored hashed
+    prefix          TEXT            NOT NULL,
+    key_hash        TEXT            NOT NULL,
+    -- names of the actions the key may be used for
+    scopes          TEXT[]          NOT NULL,
+    -- never expires if NULL
+    expires_at      TIMESTAMPTZ,
+    last_used_at    TIMESTAMPTZ,
+    PRIMARY KEY(id),
+    CONSTRAINT unique_api_keys_key_hash UNIQUE(key_hash),
+    CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+) INHERITS (__common);
+
+CREATE INDEX ON api_keys (user_id);
+
+CREATE TRIGGER maintain_updated_at_api_keys
+    BEFORE UPDATE
+    ON api_keys
+    FOR EACH ROW
+        EXECUTE PROCEDURE maintain_updated_at();
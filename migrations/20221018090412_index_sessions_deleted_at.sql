@@ -0,0 +1 @@
+CREATE INDEX ON sessions_deleted (deleted_at);
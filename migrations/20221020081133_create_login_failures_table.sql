@@ -0,0 +1,16 @@
+-- failed authentication attempts, keyed by identifier and by client address
+CREATE TABLE login_failures (
+    key             TEXT            NOT NULL,
+    failures        INTEGER         NOT NULL,
+    last_failed_at  TIMESTAMPTZ     NOT NULL,
+    locked_until    TIMESTAMPTZ,
+    PRIMARY KEY(key)
+) INHERITS (__common);
+
+CREATE INDEX ON login_failures (last_failed_at);
+
+CREATE TRIGGER maintain_updated_at_login_failures
+    BEFORE UPDATE
+    ON login_failures
+    FOR EACH ROW
+        EXECUTE PROCEDURE maintain_updated_at();
@@ -0,0 +1,33 @@
+-- passwords are changed through their own endpoint which checks the current
+-- one first
+DROP FUNCTION update_user(UUID, CITEXT, CITEXT, TEXT, TEXT);
+
+CREATE FUNCTION update_user(
+  user_id UUID,
+  new_username CITEXT,
+  new_email CITEXT,
+  new_pic_url TEXT
+)
+RETURNS SETOF users -- use SETOF to allow return of 0 rows
+AS $body$
+    DECLARE
+        le_user    users;
+    BEGIN
+        UPDATE users
+        SET
+            username = COALESCE(new_username, username),
+            email = COALESCE(new_email, email),
+            pic_url = COALESCE(new_pic_url, pic_url),
+            email_verified_at = CASE
+                WHEN new_email IS NULL OR new_email = email THEN email_verified_at
+                ELSE NULL
+            END
+        WHERE id = user_id
+        RETURNING * INTO le_user;
+
+        IF NOT FOUND THEN
+          RETURN;
+        END IF;
+        RETURN NEXT le_user;
+    END;
+$body$ LANGUAGE PLpgSQL;
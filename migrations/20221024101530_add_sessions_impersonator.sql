@@ -0,0 +1,11 @@
+-- Set on sessions opened by an admin to act as the user. Archived along with
+-- the rest of the row to keep a trail of impersonations.
+ALTER TABLE __sessions_core
+    ADD COLUMN impersonator_id  UUID;
+
+CREATE INDEX ON sessions_deleted (impersonator_id);
+
+INSERT INTO auth_policies (role, resource, action, allow)
+VALUES
+    ('superAdmin', 'impersonation', 'write', TRUE),
+    ('admin', 'impersonation', 'write', TRUE);
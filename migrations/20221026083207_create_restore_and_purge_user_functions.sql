@@ -0,0 +1,88 @@
+-- Moves the latest archived user and credentials rows back. Returns nothing
+-- if there's nothing archived at the id. Raises a unique violation if the
+-- username or email has been taken since.
+--
+-- Sessions and roles stay archived, roles have to be granted anew.
+CREATE FUNCTION restore_user(target_id UUID)
+RETURNS SETOF users -- use SETOF to allow return of 0 rows
+AS $body$
+    DECLARE
+        le_user    users;
+    BEGIN
+        INSERT INTO users
+        SELECT (jsonb_populate_record(NULL::users, to_jsonb(archived))).*
+        FROM (
+            SELECT *
+            FROM users_deleted
+            WHERE id = target_id
+            ORDER BY deleted_at DESC
+            LIMIT 1
+        ) archived
+        RETURNING * INTO le_user;
+
+        IF NOT FOUND THEN
+          RETURN;
+        END IF;
+
+        DELETE FROM users_deleted
+        WHERE id = target_id;
+
+        INSERT INTO credentials
+        SELECT (jsonb_populate_record(NULL::credentials, to_jsonb(archived))).*
+        FROM (
+            SELECT *
+            FROM credentials_deleted
+            WHERE user_id = target_id
+            ORDER BY deleted_at DESC
+            LIMIT 1
+        ) archived;
+
+        DELETE FROM credentials_deleted
+        WHERE user_id = target_id;
+
+        RETURN NEXT le_user;
+    END;
+$body$ LANGUAGE PLpgSQL;
+
+-- Erases every trace of users deleted before `cutoff`. Returns the number of
+-- users erased.
+CREATE FUNCTION purge_deleted_users(cutoff TIMESTAMPTZ) RETURNS BIGINT
+AS $body$
+    DECLARE
+        purged    UUID[];
+    BEGIN
+        WITH deleted AS (
+          DELETE FROM users_deleted
+          WHERE deleted_at < cutoff
+          RETURNING id
+        )
+        SELECT array_agg(DISTINCT id) INTO purged FROM deleted;
+
+        IF purged IS NULL THEN
+          RETURN 0;
+        END IF;
+
+        DELETE FROM credentials_deleted
+        WHERE user_id = ANY(purged);
+
+        DELETE FROM user_roles_deleted
+        WHERE user_id = ANY(purged);
+
+        DELETE FROM sessions_deleted
+        WHERE user_id = ANY(purged);
+
+        RETURN cardinality(purged);
+    END;
+$body$ LANGUAGE PLpgSQL;
+
+CREATE INDEX ON users_deleted (id);
+CREATE INDEX ON users_deleted (deleted_at);
+CREATE INDEX ON credentials_deleted (user_id);
+CREATE INDEX ON user_roles_deleted (user_id);
+CREATE INDEX ON sessions_deleted (user_id);
+
+INSERT INTO auth_policies (role, resource, action, allow)
+VALUES
+    ('superAdmin', 'deletedUsers', 'read', TRUE),
+    ('superAdmin', 'deletedUsers', 'write', TRUE),
+    ('admin', 'deletedUsers', 'read', TRUE);
@@ -0,0 +1,245 @@
+-- Two-factor secrets, api keys and linked identities used to be cascaded
+-- away on delete, leaving restored users without them. Archive them along
+-- with the rest of the user instead.
+CREATE TABLE totp_secrets_deleted (
+    LIKE totp_secrets,
+    deleted_at  TIMESTAMPTZ NOT NULL    DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE totp_recovery_codes_deleted (
+    LIKE totp_recovery_codes,
+    deleted_at  TIMESTAMPTZ NOT NULL    DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE api_keys_deleted (
+    LIKE api_keys,
+    deleted_at  TIMESTAMPTZ NOT NULL    DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE user_identities_deleted (
+    LIKE user_identities,
+    deleted_at  TIMESTAMPTZ NOT NULL    DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX ON totp_secrets_deleted (user_id);
+CREATE INDEX ON totp_recovery_codes_deleted (user_id);
+CREATE INDEX ON api_keys_deleted (user_id);
+CREATE INDEX ON user_identities_deleted (user_id);
+
+-- archived explicitly by `delete_user` from now on, make sure nothing slips
+-- through a cascade
+ALTER TABLE totp_secrets
+    DROP CONSTRAINT fk_user_id,
+    ADD CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id);
+ALTER TABLE totp_recovery_codes
+    DROP CONSTRAINT fk_user_id,
+    ADD CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id);
+ALTER TABLE api_keys
+    DROP CONSTRAINT fk_user_id,
+    ADD CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id);
+ALTER TABLE user_identities
+    DROP CONSTRAINT fk_user_id,
+    ADD CONSTRAINT fk_user_id  FOREIGN KEY(user_id) REFERENCES users(id);
+
+CREATE OR REPLACE FUNCTION delete_user(target_id UUID) RETURNS BOOLEAN
+AS $body$
+    BEGIN
+        IF NOT (EXISTS (SELECT id FROM users WHERE id = target_id)) THEN
+          RETURN FALSE;
+        END IF;
+
+        -- delete foreign keys that refer to users first to avoid referential
+        -- integrity errors
+        WITH deleted AS (
+          DELETE FROM credentials
+          WHERE user_id = target_id
+          RETURNING *
+        )
+        INSERT INTO credentials_deleted SELECT * FROM deleted;
+
+        -- archived by the `archive_deleted_sessions` trigger
+        DELETE FROM sessions
+        WHERE user_id = target_id;
+
+        WITH deleted AS (
+          DELETE FROM user_roles
+          WHERE user_id = target_id
+          RETURNING *
+        )
+        INSERT INTO user_roles_deleted SELECT * FROM deleted;
+
+        WITH deleted AS (
+          DELETE FROM totp_secrets
+          WHERE user_id = target_id
+          RETURNING *
+        )
+        INSERT INTO totp_secrets_deleted SELECT * FROM deleted;
+
+        WITH deleted AS (
+          DELETE FROM totp_recovery_codes
+          WHERE user_id = target_id
+          RETURNING *
+        )
+        INSERT INTO totp_recovery_codes_deleted SELECT * FROM deleted;
+
+        WITH deleted AS (
+          DELETE FROM api_keys
+          WHERE user_id = target_id
+          RETURNING *
+        )
+        INSERT INTO api_keys_deleted SELECT * FROM deleted;
+
+        WITH deleted AS (
+          DELETE FROM user_identities
+          WHERE user_id = target_id
+          RETURNING *
+        )
+        INSERT INTO user_identities_deleted SELECT * FROM deleted;
+
+        WITH deleted AS (
+          DELETE FROM users
+          WHERE id = target_id
+          RETURNING *
+        )
+        INSERT INTO users_deleted
+        SELECT (jsonb_populate_record(
+            NULL::users_deleted,
+            to_jsonb(deleted) || jsonb_build_object('deleted_at', CURRENT_TIMESTAMP)
+        )).*
+        FROM deleted;
+
+        RETURN TRUE;
+    END;
+$body$ LANGUAGE PLpgSQL;
+
+-- Moves the latest archived user back along with their credentials,
+-- two-factor secrets, api keys and linked identities. Returns nothing if
+-- there's nothing archived at the id. Raises a unique violation if the
+-- username or email has been taken since.
+--
+-- Identities linked to another user since stay archived. Sessions and roles
+-- stay archived too, roles have to be granted anew.
+CREATE OR REPLACE FUNCTION restore_user(target_id UUID)
+RETURNS SETOF users -- use SETOF to allow return of 0 rows
+AS $body$
+    DECLARE
+        le_user    users;
+    BEGIN
+        INSERT INTO users
+        SELECT (jsonb_populate_record(NULL::users, to_jsonb(archived))).*
+        FROM (
+            SELECT *
+            FROM users_deleted
+            WHERE id = target_id
+            ORDER BY deleted_at DESC
+            LIMIT 1
+        ) archived
+        RETURNING * INTO le_user;
+
+        IF NOT FOUND THEN
+          RETURN;
+        END IF;
+
+        DELETE FROM users_deleted
+        WHERE id = target_id;
+
+        INSERT INTO credentials
+        SELECT (jsonb_populate_record(NULL::credentials, to_jsonb(archived))).*
+        FROM (
+            SELECT *
+            FROM credentials_deleted
+            WHERE user_id = target_id
+            ORDER BY deleted_at DESC
+            LIMIT 1
+        ) archived;
+
+        DELETE FROM credentials_deleted
+        WHERE user_id = target_id;
+
+        INSERT INTO totp_secrets
+        SELECT (jsonb_populate_record(NULL::totp_secrets, to_jsonb(archived))).*
+        FROM (
+            SELECT *
+            FROM totp_secrets_deleted
+            WHERE user_id = target_id
+            ORDER BY deleted_at DESC
+            LIMIT 1
+        ) archived;
+
+        DELETE FROM totp_secrets_deleted
+        WHERE user_id = target_id;
+
+        INSERT INTO totp_recovery_codes
+        SELECT (jsonb_populate_record(NULL::totp_recovery_codes, to_jsonb(archived))).*
+        FROM totp_recovery_codes_deleted archived
+        WHERE user_id = target_id;
+
+        DELETE FROM totp_recovery_codes_deleted
+        WHERE user_id = target_id;
+
+        INSERT INTO api_keys
+        SELECT (jsonb_populate_record(NULL::api_keys, to_jsonb(archived))).*
+        FROM api_keys_deleted archived
+        WHERE user_id = target_id;
+
+        DELETE FROM api_keys_deleted
+        WHERE user_id = target_id;
+
+        WITH restored AS (
+            INSERT INTO user_identities
+            SELECT (jsonb_populate_record(NULL::user_identities, to_jsonb(archived))).*
+            FROM user_identities_deleted archived
+            WHERE user_id = target_id
+            ON CONFLICT (provider, subject) DO NOTHING
+            RETURNING provider, subject
+        )
+        DELETE FROM user_identities_deleted archived
+        USING restored
+        WHERE archived.user_id = target_id
+            AND archived.provider = restored.provider
+            AND archived.subject = restored.subject;
+
+        RETURN NEXT le_user;
+    END;
+$body$ LANGUAGE PLpgSQL;
+
+CREATE OR REPLACE FUNCTION purge_deleted_users(cutoff TIMESTAMPTZ) RETURNS BIGINT
+AS $body$
+    DECLARE
+        purged    UUID[];
+    BEGIN
+        WITH deleted AS (
+          DELETE FROM users_deleted
+          WHERE deleted_at < cutoff
+          RETURNING id
+        )
+        SELECT array_agg(DISTINCT id) INTO purged FROM deleted;
+
+        IF purged IS NULL THEN
+          RETURN 0;
+        END IF;
+
+        DELETE FROM credentials_deleted
+        WHERE user_id = ANY(purged);
+
+        DELETE FROM user_roles_deleted
+        WHERE user_id = ANY(purged);
+
+        DELETE FROM sessions_deleted
+        WHERE user_id = ANY(purged);
+
+        DELETE FROM totp_secrets_deleted
+        WHERE user_id = ANY(purged);
+
+        DELETE FROM totp_recovery_codes_deleted
+        WHERE user_id = ANY(purged);
+
+        DELETE FROM api_keys_deleted
+        WHERE user_id = ANY(purged);
+
+        DELETE FROM user_identities_deleted
+        WHERE user_id = ANY(purged);
+
+        RETURN cardinality(purged);
+    END;
+$body$ LANGUAGE PLpgSQL;
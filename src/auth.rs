@@ -2,31 +2,214 @@ use deps::*;
 
 use crate::{DocumentedEndpoint, EndpointWrapper, HttpEndpoint};
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
 pub struct Session {
-    pub token: String,
+    /// Identifies the session without revealing its token.
+    pub id: uuid::Uuid,
     pub user_id: uuid::Uuid,
+    /// In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
     pub expires_at: time::OffsetDateTime,
+    /// In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
+    pub created_at: time::OffsetDateTime,
+    /// In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
+    pub updated_at: time::OffsetDateTime,
+    /// The `User-Agent` of the client at authentication.
+    pub user_agent: Option<String>,
+    /// The address of the client at authentication.
+    pub ip_addr: Option<String>,
+    /// Label provided by the client at authentication.
+    #[schema(example = "Work laptop")]
+    pub device_label: Option<String>,
+    /// Last time the session was used. In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
+    pub last_seen_at: time::OffsetDateTime,
+    /// The admin that opened the session to act as the user.
+    pub impersonator_id: Option<uuid::Uuid>,
+}
+
+/// A long-lived credential for machine clients. The key itself is only
+/// shown once at creation.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct ApiKey {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    #[schema(example = "CI deploy bot")]
+    pub name: String,
+    /// The start of the key, to help tell keys apart.
+    #[schema(example = "wak_3f9c21ab")]
+    pub prefix: String,
+    /// The actions the key may be used for.
+    pub scopes: Vec<Action>,
+    /// In seconds since unix epoch in UTC. Never expires if not set.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp::option")]
+    pub expires_at: Option<time::OffsetDateTime>,
+    /// In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp::option")]
+    pub last_used_at: Option<time::OffsetDateTime>,
+    /// In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
     pub created_at: time::OffsetDateTime,
+    /// In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
     pub updated_at: time::OffsetDateTime,
 }
 
+/// [`ApiKey`] as stored, with the scopes as names.
+#[derive(Debug)]
+pub(crate) struct ApiKeyRow {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<time::OffsetDateTime>,
+    pub last_used_at: Option<time::OffsetDateTime>,
+    pub created_at: time::OffsetDateTime,
+    pub updated_at: time::OffsetDateTime,
+}
+
+impl From<ApiKeyRow> for ApiKey {
+    fn from(row: ApiKeyRow) -> Self {
+        Self {
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            prefix: row.prefix,
+            scopes: row
+                .scopes
+                .into_iter()
+                .filter_map(|scope| {
+                    scope
+                        .parse()
+                        .map_err(|err| tracing::warn!(%err, "ignoring scope"))
+                        .ok()
+                })
+                .collect(),
+            expires_at: row.expires_at,
+            last_used_at: row.last_used_at,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+pub use list_sessions::SessionSortingField;
+
 #[derive(Debug, Clone)]
 pub enum Resource {
-    User { id: uuid::Uuid },
+    User {
+        id: uuid::Uuid,
+    },
     Users,
+    /// The archive of deleted users.
+    DeletedUsers,
+    Session {
+        /// `None` for the session of the token in use.
+        id: Option<uuid::Uuid>,
+    },
+    /// All the sessions of the user.
+    Sessions {
+        user_id: uuid::Uuid,
+    },
+    ApiKeys {
+        user_id: uuid::Uuid,
+    },
+    /// Opening sessions as the user.
+    Impersonation {
+        user_id: uuid::Uuid,
+    },
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Resource {
+    /// Name used for the resource in the policy table.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::User { .. } => "user",
+            Self::Users => "users",
+            Self::DeletedUsers => "deletedUsers",
+            // listing sessions is reading them
+            Self::Session { .. } | Self::Sessions { .. } => "session",
+            Self::ApiKeys { .. } => "apiKeys",
+            Self::Impersonation { .. } => "impersonation",
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema,
+)]
+#[serde(crate = "serde", rename_all = "camelCase")]
 pub enum Action {
     Read,
     Write,
     Delete,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Action {
+    /// Name used for the action in the policy table.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+impl std::str::FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            "delete" => Ok(Self::Delete),
+            _ => Err(format!("unknown action: {s}")),
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, utoipa::ToSchema,
+)]
+#[serde(crate = "serde", rename_all = "camelCase")]
 pub enum Role {
     SuperAdmin,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SuperAdmin => "superAdmin",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "superAdmin" => Ok(Self::SuperAdmin),
+            "admin" => Ok(Self::Admin),
+            _ => Err(format!("unknown role: {s}")),
+        }
+    }
 }
 
 pub const TAG: crate::Tag = crate::Tag {
@@ -35,27 +218,227 @@ pub const TAG: crate::Tag = crate::Tag {
 };
 
 pub mod authenticate;
+pub mod authenticate_totp;
 pub mod authorize;
+pub mod begin_identity_link;
+pub mod begin_oidc_login;
+pub mod complete_identity_link;
+pub mod complete_oidc_login;
+pub mod confirm_password_reset;
+pub mod confirm_totp;
+pub mod create_api_key;
+pub mod delete_api_key;
+pub mod enroll_totp;
+pub mod get_api_key;
+pub mod get_jwks;
+pub mod impersonate;
+pub mod jwt;
+pub mod list_api_keys;
+pub mod list_sessions;
+pub mod lockout;
+pub mod logout;
+pub mod oidc;
+pub mod password;
+pub mod password_policy;
+pub mod policy;
+pub mod refresh;
+pub mod request_password_reset;
+pub mod revoke_other_sessions;
+pub mod revoke_session;
+pub mod session_cache;
+pub mod sweeper;
+pub mod tokens;
+pub mod totp;
+pub mod update_api_key;
+pub mod verify_email;
 
 pub fn router() -> axum::Router {
-    axum::Router::new().merge(EndpointWrapper::new(authenticate::Authenticate))
+    axum::Router::new()
+        .merge(EndpointWrapper::new(authenticate::Authenticate))
+        .merge(EndpointWrapper::new(authenticate_totp::AuthenticateTotp))
+        .merge(EndpointWrapper::new(refresh::Refresh))
+        .merge(EndpointWrapper::new(get_jwks::GetJwks))
+        .merge(EndpointWrapper::new(
+            request_password_reset::RequestPasswordReset,
+        ))
+        .merge(EndpointWrapper::new(
+            confirm_password_reset::ConfirmPasswordReset,
+        ))
+        .merge(EndpointWrapper::new(verify_email::VerifyEmail))
+        .merge(EndpointWrapper::new(begin_oidc_login::BeginOidcLogin))
+        .merge(EndpointWrapper::new(complete_oidc_login::CompleteOidcLogin))
+        .merge(EndpointWrapper::new(logout::Logout))
+        .merge(EndpointWrapper::new(list_sessions::ListSessions))
+        .merge(EndpointWrapper::new(enroll_totp::EnrollTotp))
+        .merge(EndpointWrapper::new(confirm_totp::ConfirmTotp))
+        .merge(EndpointWrapper::new(begin_identity_link::BeginIdentityLink))
+        .merge(EndpointWrapper::new(
+            complete_identity_link::CompleteIdentityLink,
+        ))
+        .merge(EndpointWrapper::new(create_api_key::CreateApiKey))
+        .merge(EndpointWrapper::new(list_api_keys::ListApiKeys))
+        .merge(EndpointWrapper::new(get_api_key::GetApiKey))
+        .merge(EndpointWrapper::new(update_api_key::UpdateApiKey))
+        .merge(EndpointWrapper::new(delete_api_key::DeleteApiKey))
+        .merge(EndpointWrapper::new(revoke_session::RevokeSession))
+        .merge(EndpointWrapper::new(
+            revoke_other_sessions::RevokeOtherSessions,
+        ))
+        .merge(EndpointWrapper::new(impersonate::Impersonate))
 }
 
 pub fn components(
     builder: utoipa::openapi::ComponentsBuilder,
 ) -> utoipa::openapi::ComponentsBuilder {
     let builder = authenticate::Authenticate::components(builder);
+    let builder = authenticate_totp::AuthenticateTotp::components(builder);
+    let builder = refresh::Refresh::components(builder);
+    let builder = get_jwks::GetJwks::components(builder);
+    let builder = request_password_reset::RequestPasswordReset::components(builder);
+    let builder = confirm_password_reset::ConfirmPasswordReset::components(builder);
+    let builder = verify_email::VerifyEmail::components(builder);
+    let builder = begin_oidc_login::BeginOidcLogin::components(builder);
+    let builder = complete_oidc_login::CompleteOidcLogin::components(builder);
+    let builder = logout::Logout::components(builder);
+    let builder = revoke_session::RevokeSession::components(builder);
+    let builder = revoke_other_sessions::RevokeOtherSessions::components(builder);
+    let builder = list_sessions::ListSessions::components(builder);
+    let builder = enroll_totp::EnrollTotp::components(builder);
+    let builder = confirm_totp::ConfirmTotp::components(builder);
+    let builder = begin_identity_link::BeginIdentityLink::components(builder);
+    let builder = complete_identity_link::CompleteIdentityLink::components(builder);
+    let builder = create_api_key::CreateApiKey::components(builder);
+    let builder = list_api_keys::ListApiKeys::components(builder);
+    let builder = get_api_key::GetApiKey::components(builder);
+    let builder = update_api_key::UpdateApiKey::components(builder);
+    let builder = delete_api_key::DeleteApiKey::components(builder);
+    let builder = impersonate::Impersonate::components(builder);
     builder
+        .schema("Session", <Session as utoipa::ToSchema>::schema())
+        .schema("ApiKey", <ApiKey as utoipa::ToSchema>::schema())
+        .schema("Action", <Action as utoipa::ToSchema>::schema())
+        .schema("Jwk", <jwt::Jwk as utoipa::ToSchema>::schema())
+        .schema(
+            crate::utils::type_name_raw::<SessionSortingField>(),
+            <SessionSortingField as utoipa::ToSchema>::schema(),
+        )
+        .schemas_from_iter(<list_sessions::ListSessionsRequest as utoipa::ToSchema>::aliases())
+        .schemas_from_iter(<list_sessions::ListSessionsResponse as utoipa::ToSchema>::aliases())
 }
 
 pub fn paths(builder: utoipa::openapi::PathsBuilder) -> utoipa::openapi::PathsBuilder {
-    builder.path(
-        crate::axum_path_str_to_openapi(authenticate::Authenticate::PATH),
-        authenticate::Authenticate::path_item(),
-    )
+    builder
+        .path(
+            crate::axum_path_str_to_openapi(authenticate::Authenticate::PATH),
+            authenticate::Authenticate::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(authenticate_totp::AuthenticateTotp::PATH),
+            authenticate_totp::AuthenticateTotp::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(refresh::Refresh::PATH),
+            refresh::Refresh::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(get_jwks::GetJwks::PATH),
+            get_jwks::GetJwks::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(request_password_reset::RequestPasswordReset::PATH),
+            request_password_reset::RequestPasswordReset::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(confirm_password_reset::ConfirmPasswordReset::PATH),
+            confirm_password_reset::ConfirmPasswordReset::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(verify_email::VerifyEmail::PATH),
+            verify_email::VerifyEmail::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(begin_oidc_login::BeginOidcLogin::PATH),
+            begin_oidc_login::BeginOidcLogin::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(complete_oidc_login::CompleteOidcLogin::PATH),
+            complete_oidc_login::CompleteOidcLogin::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(logout::Logout::PATH),
+            logout::Logout::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(revoke_session::RevokeSession::PATH),
+            revoke_session::RevokeSession::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(revoke_other_sessions::RevokeOtherSessions::PATH),
+            revoke_other_sessions::RevokeOtherSessions::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(list_sessions::ListSessions::PATH),
+            list_sessions::ListSessions::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(enroll_totp::EnrollTotp::PATH),
+            enroll_totp::EnrollTotp::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(confirm_totp::ConfirmTotp::PATH),
+            confirm_totp::ConfirmTotp::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(begin_identity_link::BeginIdentityLink::PATH),
+            begin_identity_link::BeginIdentityLink::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(complete_identity_link::CompleteIdentityLink::PATH),
+            complete_identity_link::CompleteIdentityLink::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(create_api_key::CreateApiKey::PATH),
+            create_api_key::CreateApiKey::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(list_api_keys::ListApiKeys::PATH),
+            list_api_keys::ListApiKeys::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(get_api_key::GetApiKey::PATH),
+            get_api_key::GetApiKey::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(update_api_key::UpdateApiKey::PATH),
+            update_api_key::UpdateApiKey::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(delete_api_key::DeleteApiKey::PATH),
+            delete_api_key::DeleteApiKey::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(impersonate::Impersonate::PATH),
+            impersonate::Impersonate::path_item(),
+        )
 }
 
 pub mod testing {
+    use deps::*;
+
     pub const USER_01_SESSION: &str = "9d827d5c-15bd-413c-9431-39ff96155d7b";
+    pub const USER_01_SESSION_2: &str = "5a7c8f3e-0d4b-4c1e-9f6a-2b3d4e5f6a7b";
+    pub const USER_01_SESSION_2_ID: uuid::Uuid =
+        uuid::uuid!("0f6e9a43-7c1d-4b8e-a2f5-3d9c6b1e8a70");
+    /// Belongs to `USER_04` who is a [`super::Role::SuperAdmin`].
     pub const USER_04_SESSION: &str = "ebd3b465-be17-4077-bc4a-add9f76b5028";
+    pub const USER_04_SESSION_ID: uuid::Uuid = uuid::uuid!("6c2b9d1e-4a7f-4e3c-8b5d-1f0a9e7c3d24");
+
+    /// For calling [`crate::AuthenticatedEndpoint::handle`] directly.
+    pub fn accessing(user_id: uuid::Uuid) -> super::authorize::Response {
+        super::authorize::Response {
+            user_id,
+            roles: vec![],
+            impersonator_id: None,
+        }
+    }
 }
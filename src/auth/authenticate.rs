@@ -7,23 +7,73 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone)]
 pub struct Authenticate;
 
-#[derive(Debug, Deserialize, utoipa::ToSchema)]
+#[derive(Debug, Deserialize, validator::Validate, utoipa::ToSchema)]
 #[serde(crate = "serde", rename_all = "camelCase")]
 pub struct Request {
     pub identifier: String,
     pub password: String,
+    /// A name for the session to help the user tell it apart from the others.
+    #[validate(length(min = 1, max = 64))]
+    #[schema(example = "Work laptop")]
+    pub device_label: Option<String>,
+    #[serde(skip)]
+    pub client: Option<ClientInfo>,
 }
 
-/// `token` currently appears to be a UUID but don't rely one this as this may
-/// change in the future.
+/// `token` and `refreshToken` currently appear to be UUIDs but don't rely one
+/// this as this may change in the future.
 #[derive(Debug, Serialize, utoipa::ToSchema)]
 #[serde(crate = "serde", rename_all = "camelCase")]
-pub struct Response {
+pub struct Tokens {
     pub user_id: uuid::Uuid,
     pub token: String,
     #[schema(example = 1234567)]
     #[serde(with = "time::serde::timestamp")]
     pub expires_at: time::OffsetDateTime,
+    /// Single use token to be exchanged for a new `token` at
+    /// `/authenticate/refresh`.
+    pub refresh_token: String,
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
+    pub refresh_expires_at: time::OffsetDateTime,
+}
+
+/// Given to users with two-factor authentication instead of [`Tokens`].
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct TotpChallenge {
+    /// To be exchanged along with a TOTP or recovery code for [`Tokens`] at
+    /// `/authenticate/totp`.
+    pub challenge_token: String,
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
+    pub challenge_expires_at: time::OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "serde", untagged)]
+pub enum Response {
+    Session(Tokens),
+    TotpRequired(TotpChallenge),
+}
+
+impl Response {
+    /// `None` if two-factor authentication is required.
+    pub fn into_tokens(self) -> Option<Tokens> {
+        match self {
+            Self::Session(tokens) => Some(tokens),
+            Self::TotpRequired(_) => None,
+        }
+    }
+}
+
+impl utoipa::ToSchema for Response {
+    fn schema() -> utoipa::openapi::schema::Schema {
+        utoipa::openapi::schema::OneOfBuilder::new()
+            .item(<Tokens as utoipa::ToSchema>::schema())
+            .item(<TotpChallenge as utoipa::ToSchema>::schema())
+            .into()
+    }
 }
 
 #[derive(Debug, Serialize, thiserror::Error, utoipa::ToSchema)]
@@ -31,10 +81,158 @@ pub struct Response {
 pub enum Error {
     #[error("credentials rejected")]
     CredentialsRejected,
+    #[error("email not verified")]
+    EmailNotVerified,
+    #[error("too many failed attempts, retry after {retry_after}s")]
+    #[serde(rename_all = "camelCase")]
+    TooManyAttempts {
+        /// In seconds.
+        retry_after: u64,
+    },
+    #[error("invalid input: {issues:?}")]
+    InvalidInput {
+        #[from]
+        issues: ValidationErrors,
+    },
     #[error("internal server error: {message:?}")]
     Internal { message: String },
 }
 
+/// A signed JWT when [`jwt`](crate::Config::jwt) is configured, an opaque
+/// token otherwise.
+pub(crate) async fn access_token(
+    ctx: &crate::Context,
+    user_id: uuid::Uuid,
+    impersonator_id: Option<uuid::Uuid>,
+    issued_at: time::OffsetDateTime,
+    expires_at: time::OffsetDateTime,
+) -> Result<String, sqlx::Error> {
+    let jwt = if let Some(jwt) = &ctx.config.jwt {
+        jwt
+    } else {
+        return Ok(uuid::Uuid::new_v4().to_string());
+    };
+    let roles = super::authorize::user_roles(ctx, user_id).await?;
+    Ok(jwt
+        .issue(user_id, roles, impersonator_id, issued_at, expires_at)
+        .unwrap_or_log())
+}
+
+/// Shared with the endpoints that complete an authentication.
+pub(crate) async fn create_session(
+    ctx: &crate::Context,
+    user_id: uuid::Uuid,
+    client: ClientInfo,
+    device_label: Option<&str>,
+) -> Result<Tokens, sqlx::Error> {
+    let now = ctx.clock.now();
+    let expires_at = now.saturating_add(ctx.config.access_token_lifespan);
+    let refresh_expires_at = now.saturating_add(ctx.config.refresh_token_lifespan);
+    let token = access_token(ctx, user_id, None, now, expires_at).await?;
+    let refresh_token = uuid::Uuid::new_v4().to_string();
+    sqlx::query!(
+        r#"
+INSERT INTO sessions (
+    token, user_id, expires_at, refresh_token, refresh_expires_at,
+    user_agent, ip_addr, device_label
+)
+VALUES (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5,
+    $6,
+    $7,
+    $8
+)
+        "#,
+        &token,
+        &user_id,
+        &expires_at,
+        &refresh_token,
+        &refresh_expires_at,
+        client.user_agent.as_ref(),
+        client.ip_addr.map(|addr| addr.to_string()),
+        device_label,
+    )
+    .execute(&ctx.db_pool)
+    .await?;
+    Ok(Tokens {
+        user_id,
+        expires_at,
+        token,
+        refresh_token,
+        refresh_expires_at,
+    })
+}
+
+/// What [`sign_in`] needs to know of the user.
+pub(crate) struct Account {
+    pub user_id: uuid::Uuid,
+    pub created_at: time::OffsetDateTime,
+    pub email_verified_at: Option<time::OffsetDateTime>,
+    pub totp_enabled: bool,
+}
+
+/// The checks left once the user has proven who they are, shared with
+/// [`CompleteOidcLogin`](super::complete_oidc_login::CompleteOidcLogin).
+/// Hands out a TOTP challenge in place of a session if the user has
+/// two-factor authentication enabled.
+pub(crate) async fn sign_in(
+    ctx: &crate::Context,
+    account: Account,
+    client: ClientInfo,
+    device_label: Option<&str>,
+) -> Result<Response, Error> {
+    if let Some(grace_period) = ctx.config.unverified_account_grace_period {
+        if account.email_verified_at.is_none()
+            && account.created_at + grace_period < ctx.clock.now()
+        {
+            return Err(Error::EmailNotVerified);
+        }
+    }
+
+    let user_id = account.user_id;
+    if account.totp_enabled {
+        let challenge_token = super::tokens::generate_token();
+        let challenge_expires_at = ctx
+            .clock
+            .now()
+            .saturating_add(ctx.config.totp_challenge_lifespan);
+        sqlx::query!(
+            r#"
+INSERT INTO totp_challenges (
+    token_hash, user_id, expires_at, user_agent, ip_addr, device_label
+)
+VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            super::tokens::hash_token(&challenge_token),
+            &user_id,
+            &challenge_expires_at,
+            client.user_agent.as_ref(),
+            client.ip_addr.map(|addr| addr.to_string()),
+            device_label,
+        )
+        .execute(&ctx.db_pool)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
+        return Ok(Response::TotpRequired(TotpChallenge {
+            challenge_token,
+            challenge_expires_at,
+        }));
+    }
+
+    create_session(ctx, user_id, client, device_label)
+        .await
+        .map(Response::Session)
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })
+}
+
 #[async_trait::async_trait]
 impl Endpoint for Authenticate {
     type Request = Request;
@@ -46,60 +244,90 @@ impl Endpoint for Authenticate {
         ctx: &crate::Context,
         request: Self::Request,
     ) -> Result<Self::Response, Self::Error> {
+        validator::Validate::validate(&request).map_err(utils::ValidationErrors::from)?;
+        let db_err = |err| Error::Internal {
+            message: format!("db error: {err}"),
+        };
+        let client = request.client.unwrap_or_default();
         let result = sqlx::query!(
             r#"
-SELECT user_id, pass_hash
+SELECT
+    credentials.user_id,
+    pass_hash,
+    users.created_at,
+    users.email_verified_at,
+    totp_secrets.confirmed_at IS NOT NULL as "totp_enabled!"
 FROM credentials
-WHERE user_id = (
-    SELECT id
-    FROM users
-    WHERE email = $1::TEXT::CITEXT OR username = $1::TEXT::CITEXT
-)
+JOIN users ON users.id = credentials.user_id
+LEFT JOIN totp_secrets ON totp_secrets.user_id = credentials.user_id
+WHERE email = $1::TEXT::CITEXT OR username = $1::TEXT::CITEXT
         "#,
             &request.identifier,
         )
-        .fetch_one(&ctx.db_pool)
+        .fetch_optional(&ctx.db_pool)
         .await
-        .map_err(|err| match &err {
-            sqlx::Error::RowNotFound => Error::CredentialsRejected,
-            _ => Error::Internal {
-                message: format!("db error: {err}"),
-            },
-        })?;
-        let pass_valid =
-            argon2::verify_encoded(&result.pass_hash[..], request.password.as_bytes()).unwrap();
-        if !pass_valid {
-            return Err(Error::CredentialsRejected);
+        .map_err(db_err)?;
+        let lockout_keys = match &result {
+            Some(result) => super::lockout::Keys::for_user(result.user_id, client.ip_addr),
+            None => super::lockout::Keys::for_identifier(&request.identifier, client.ip_addr),
+        };
+        // counted before the password is checked so that correct guesses
+        // don't get through either
+        let attempted_at = match super::lockout::record_attempt(ctx, &lockout_keys)
+            .await
+            .map_err(db_err)?
+        {
+            super::lockout::Attempt::Counted { at } => at,
+            super::lockout::Attempt::Locked { remaining } => {
+                return Err(Error::TooManyAttempts {
+                    retry_after: remaining.as_seconds_f64().ceil() as u64,
+                })
+            }
+        };
+        let verification = match &result {
+            Some(result) => {
+                super::password::verify(&ctx.config, &result.pass_hash, &request.password)
+            }
+            // as slow as a wrong password so as not to disclose which identifiers exist
+            None => super::password::verify_dummy(&ctx.config, &request.password),
+        };
+        let result = match (result, verification) {
+            (Some(result), super::password::Verification::Accepted { .. }) => result,
+            // unknown identifiers count too, guessing them is just as bad
+            _ => return Err(Error::CredentialsRejected),
+        };
+        if let super::password::Verification::Accepted { needs_rehash: true } = verification {
+            // unless the password was changed in the meantime
+            sqlx::query!(
+                r#"
+UPDATE credentials
+SET pass_hash = $3
+WHERE user_id = $1 AND pass_hash = $2
+                "#,
+                &result.user_id,
+                &result.pass_hash,
+                super::password::hash(&ctx.config, &request.password),
+            )
+            .execute(&ctx.db_pool)
+            .await
+            .map_err(db_err)?;
         }
-
-        let user_id = result.user_id;
-        let expires_at =
-            time::OffsetDateTime::now_utc().saturating_add(ctx.config.auth_token_lifespan);
-        let token = uuid::Uuid::new_v4().to_string();
-        sqlx::query!(
-            r#"
-INSERT INTO sessions (token, user_id, expires_at)
-VALUES (
-    $1,
-    $2,
-    $3
-)
-        "#,
-            &token,
-            &user_id,
-            &expires_at
+        super::lockout::record_success(ctx, &lockout_keys, attempted_at)
+            .await
+            .map_err(db_err)?;
+        // only checked after the password so as not to disclose the account state
+        sign_in(
+            ctx,
+            Account {
+                user_id: result.user_id,
+                created_at: result.created_at,
+                email_verified_at: result.email_verified_at,
+                totp_enabled: result.totp_enabled,
+            },
+            client,
+            request.device_label.as_deref(),
         )
-        .execute(&ctx.db_pool)
         .await
-        .map_err(|err| Error::Internal {
-            message: format!("db error: {err}"),
-        })?;
-
-        Ok(Response {
-            user_id,
-            expires_at,
-            token,
-        })
     }
 }
 
@@ -107,26 +335,51 @@ impl HttpEndpoint for Authenticate {
     const METHOD: Method = Method::Post;
     const PATH: &'static str = "/authenticate";
 
-    type HttpRequest = (Json<Request>,);
+    type HttpRequest = (ClientInfo, Json<Request>);
 
-    fn request((Json(req),): Self::HttpRequest) -> Result<Self::Request, Self::Error> {
-        Ok(req)
+    fn request((client, Json(req)): Self::HttpRequest) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            client: Some(client),
+            ..req
+        })
     }
 
     fn response(resp: Self::Response) -> axum::response::Response {
         Json(resp).into_response()
     }
+
+    fn error_response(err: Self::Error) -> axum::response::Response {
+        let retry_after = match &err {
+            Error::TooManyAttempts { retry_after } => Some(*retry_after),
+            _ => None,
+        };
+        let mut resp = (StatusCode::from(&err), Json(err)).into_response();
+        if let Some(retry_after) = retry_after {
+            super::lockout::set_retry_after(&mut resp, retry_after);
+        }
+        resp
+    }
 }
 
 impl DocumentedEndpoint for Authenticate {
     const TAG: &'static Tag = &super::TAG;
+    const SUCCESS_DESCRIPTION: &'static str =
+        "A session or, if the user has two-factor authentication enabled, a challenge to be completed at `/authenticate/totp`.";
 
     fn success_examples() -> Vec<serde_json::Value> {
-        [Self::Response {
-            user_id: Default::default(),
-            token: "mcpqwen8y3489nc8y2pf".into(),
-            expires_at: time::OffsetDateTime::now_utc(),
-        }]
+        [
+            Response::Session(Tokens {
+                user_id: Default::default(),
+                token: "mcpqwen8y3489nc8y2pf".into(),
+                expires_at: time::OffsetDateTime::now_utc(),
+                refresh_token: "nbqiwe7b34l9xh2i3hdw".into(),
+                refresh_expires_at: time::OffsetDateTime::now_utc(),
+            }),
+            Response::TotpRequired(TotpChallenge {
+                challenge_token: "Jx1bVq0cR4m2Zp8sYk3LwT7eHn5GdA9uFo6iCj0vXyE".into(),
+                challenge_expires_at: time::OffsetDateTime::now_utc(),
+            }),
+        ]
         .into_iter()
         .map(serde_json::to_value)
         .collect::<Result<_, _>>()
@@ -136,6 +389,33 @@ impl DocumentedEndpoint for Authenticate {
     fn errors() -> Vec<ErrorResponse<Self::Error>> {
         vec![
             ("Credentials rejected", Error::CredentialsRejected),
+            (
+                "Email not verified within the grace period",
+                Error::EmailNotVerified,
+            ),
+            (
+                "Too many failed attempts, see the `Retry-After` header",
+                Error::TooManyAttempts { retry_after: 30 },
+            ),
+            (
+                "Invalid input",
+                Error::InvalidInput {
+                    issues: {
+                        let mut issues = validator::ValidationErrors::new();
+                        issues.add(
+                            "device_label",
+                            validator::ValidationError {
+                                code: std::borrow::Cow::from("length"),
+                                message: None,
+                                params: [(std::borrow::Cow::from("value"), serde_json::json!(""))]
+                                    .into_iter()
+                                    .collect(),
+                            },
+                        );
+                        issues.into()
+                    },
+                },
+            ),
             (
                 "Internal server error",
                 Error::Internal {
@@ -150,7 +430,9 @@ impl From<&Error> for axum::http::StatusCode {
     fn from(err: &Error) -> Self {
         use Error::*;
         match err {
-            CredentialsRejected { .. } => Self::BAD_REQUEST,
+            CredentialsRejected | InvalidInput { .. } => Self::BAD_REQUEST,
+            EmailNotVerified => Self::FORBIDDEN,
+            TooManyAttempts { .. } => Self::TOO_MANY_REQUESTS,
             Internal { .. } => Self::INTERNAL_SERVER_ERROR,
         }
     }
@@ -377,4 +659,201 @@ mod tests {
         }
         ctx.close().await;
     }
+
+    #[tokio::test]
+    async fn authenticate_fails_if_unverified_past_grace_period() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let authenticate = || async {
+                let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+                let body_json = serde_json::json!({
+                    "identifier": USER_03_USERNAME,
+                    "password": "password",
+                });
+                let resp = app
+                    .oneshot(
+                        http::Request::builder()
+                            .method("POST")
+                            .uri("/authenticate")
+                            .header("Content-Type", "application/json")
+                            .body(serde_json::to_vec(&body_json).unwrap().into())
+                            .unwrap_or_log(),
+                    )
+                    .await
+                    .unwrap_or_log();
+                let status = resp.status();
+                let body = hyper::body::to_bytes(resp.into_body())
+                    .await
+                    .unwrap_or_log();
+                (status, serde_json::from_slice(&body).unwrap_or_log())
+            };
+            // still within the grace period
+            let (status, _): (_, serde_json::Value) = authenticate().await;
+            assert_eq!(status, http::StatusCode::OK);
+
+            ctx.clock
+                .set(time::OffsetDateTime::now_utc() + time::Duration::days(30));
+            let (status, body) = authenticate().await;
+            assert_eq!(status, http::StatusCode::FORBIDDEN);
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({
+                        "error": "emailNotVerified"
+                    }),
+                ),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+
+    async fn attempt(
+        ctx: &TestContext,
+        identifier: &str,
+        password: &str,
+        ip_addr: [u8; 4],
+    ) -> axum::response::Response {
+        let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+        app.oneshot(
+            http::Request::builder()
+                .method("POST")
+                .uri("/authenticate")
+                .header(axum::http::header::CONTENT_TYPE, "application/json")
+                .extension(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+                    ip_addr, 4242,
+                ))))
+                .body(
+                    serde_json::to_vec(&serde_json::json!({
+                        "identifier": identifier,
+                        "password": password,
+                    }))
+                    .unwrap()
+                    .into(),
+                )
+                .unwrap_or_log(),
+        )
+        .await
+        .unwrap_or_log()
+    }
+
+    #[tokio::test]
+    async fn authenticate_locks_out_accounts() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let now = time::OffsetDateTime::now_utc();
+            ctx.clock.set(now);
+            let threshold = ctx.ctx().config.login_lockout.threshold;
+            for _ in 0..threshold {
+                let resp = attempt(&ctx, USER_01_USERNAME, "hunter2", [10, 0, 0, 1]).await;
+                assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+            }
+            // even the right password is turned away, from any address and
+            // under any of the user's identifiers
+            let resp = attempt(&ctx, USER_01_EMAIL, "password", [10, 0, 0, 2]).await;
+            assert_eq!(resp.status(), http::StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(resp.headers()[axum::http::header::RETRY_AFTER], "30");
+            let body = hyper::body::to_bytes(resp.into_body())
+                .await
+                .unwrap_or_log();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_log();
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({ "error": "tooManyAttempts", "retryAfter": 30 }),
+                ),
+                ("response", &body),
+            );
+            let resp = attempt(&ctx, USER_02_USERNAME, "password", [10, 0, 0, 1]).await;
+            assert_eq!(resp.status(), http::StatusCode::OK);
+
+            // the next failure doubles the lockout
+            ctx.clock.set(now + time::Duration::seconds(31));
+            let resp = attempt(&ctx, USER_01_USERNAME, "hunter2", [10, 0, 0, 1]).await;
+            assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+            let resp = attempt(&ctx, USER_01_USERNAME, "password", [10, 0, 0, 1]).await;
+            assert_eq!(resp.headers()[axum::http::header::RETRY_AFTER], "60");
+
+            ctx.clock.set(now + time::Duration::seconds(92));
+            let resp = attempt(&ctx, USER_01_USERNAME, "password", [10, 0, 0, 1]).await;
+            assert_eq!(resp.status(), http::StatusCode::OK);
+            // success starts over
+            let resp = attempt(&ctx, USER_01_USERNAME, "hunter2", [10, 0, 0, 1]).await;
+            assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+            let resp = attempt(&ctx, USER_01_USERNAME, "password", [10, 0, 0, 1]).await;
+            assert_eq!(resp.status(), http::StatusCode::OK);
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn authenticate_counts_racing_attempts() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let threshold = ctx.ctx().config.login_lockout.threshold;
+            let statuses = futures::future::join_all(
+                (0..threshold * 3)
+                    .map(|_| attempt(&ctx, USER_01_USERNAME, "hunter2", [10, 0, 0, 1])),
+            )
+            .await
+            .into_iter()
+            .map(|resp| resp.status())
+            .collect::<Vec<_>>();
+            let rejected = statuses
+                .iter()
+                .filter(|status| **status == http::StatusCode::BAD_REQUEST)
+                .count();
+            assert_eq!(rejected, threshold as usize, "{statuses:?}");
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn authenticate_locks_out_addresses() {
+        let ctx = TestContext::with_config(crate::function!(), |config| {
+            config.login_lockout.ip_threshold = 3;
+        })
+        .await;
+        {
+            for identifier in ["golden_eel", "silver_eel", USER_02_USERNAME] {
+                let resp = attempt(&ctx, identifier, "hunter2", [10, 0, 0, 1]).await;
+                assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+            }
+            let resp = attempt(&ctx, USER_01_USERNAME, "password", [10, 0, 0, 1]).await;
+            assert_eq!(resp.status(), http::StatusCode::TOO_MANY_REQUESTS);
+            let resp = attempt(&ctx, USER_01_USERNAME, "password", [10, 0, 0, 2]).await;
+            assert_eq!(resp.status(), http::StatusCode::OK);
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn authenticate_upgrades_password_hashes() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let pass_hash = || async {
+                sqlx::query_scalar::<_, String>(
+                    "SELECT pass_hash FROM credentials WHERE user_id = $1",
+                )
+                .bind(USER_01_ID)
+                .fetch_one(&ctx.ctx().db_pool)
+                .await
+                .unwrap_or_log()
+            };
+            let fixture_hash = pass_hash().await;
+            let resp = attempt(&ctx, USER_01_USERNAME, "password", [10, 0, 0, 1]).await;
+            assert_eq!(resp.status(), http::StatusCode::OK);
+            let upgraded = pass_hash().await;
+            assert_ne!(upgraded, fixture_hash);
+            assert!(!crate::auth::password::is_weaker(
+                &ctx.ctx().config.argon2_conf,
+                &upgraded
+            ));
+
+            let resp = attempt(&ctx, USER_01_USERNAME, "password", [10, 0, 0, 1]).await;
+            assert_eq!(resp.status(), http::StatusCode::OK);
+            assert_eq!(pass_hash().await, upgraded, "only weaker hashes are redone");
+        }
+        ctx.close().await;
+    }
 }
@@ -0,0 +1,416 @@
+use deps::*;
+
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+pub struct AuthenticateTotp;
+
+/// Wrong codes a challenge survives before it has to be started over.
+pub const MAX_ATTEMPTS: i32 = 5;
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Request {
+    /// As returned by `/authenticate`.
+    pub challenge_token: String,
+    /// Current code of the authenticator or one of the recovery codes.
+    #[schema(example = "287082")]
+    pub code: String,
+}
+
+pub type Response = super::authenticate::Tokens;
+
+#[derive(Debug, Serialize, thiserror::Error, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase", tag = "error")]
+pub enum Error {
+    #[error("challenge rejected")]
+    ChallengeRejected,
+    #[error("invalid code")]
+    InvalidCode,
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+#[async_trait::async_trait]
+impl Endpoint for AuthenticateTotp {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let db_err = |err| Error::Internal {
+            message: format!("db error: {err}"),
+        };
+        let mut tx = ctx.db_pool.begin().await.map_err(db_err)?;
+        let challenge = sqlx::query!(
+            r#"
+UPDATE totp_challenges
+SET attempts = attempts + 1
+WHERE token_hash = $1
+RETURNING user_id, expires_at, attempts, user_agent, ip_addr, device_label
+            "#,
+            super::tokens::hash_token(&request.challenge_token),
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(db_err)?;
+        let challenge = match challenge {
+            Some(challenge)
+                if challenge.expires_at > ctx.clock.now() && challenge.attempts <= MAX_ATTEMPTS =>
+            {
+                challenge
+            }
+            Some(_) => {
+                sqlx::query!(
+                    r#"
+DELETE FROM totp_challenges
+WHERE token_hash = $1
+                    "#,
+                    super::tokens::hash_token(&request.challenge_token),
+                )
+                .execute(&mut tx)
+                .await
+                .map_err(db_err)?;
+                tx.commit().await.map_err(db_err)?;
+                return Err(Error::ChallengeRejected);
+            }
+            None => return Err(Error::ChallengeRejected),
+        };
+
+        let totp = sqlx::query!(
+            r#"
+SELECT secret, last_used_step
+FROM totp_secrets
+WHERE user_id = $1 AND confirmed_at IS NOT NULL
+FOR UPDATE
+            "#,
+            &challenge.user_id,
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(db_err)?;
+        // two-factor authentication was turned off in the meantime
+        let totp = if let Some(totp) = totp {
+            totp
+        } else {
+            return Err(Error::ChallengeRejected);
+        };
+
+        if let Some(step) = super::totp::verify(
+            &totp.secret,
+            &request.code,
+            ctx.clock.now(),
+            totp.last_used_step,
+        ) {
+            sqlx::query!(
+                r#"
+UPDATE totp_secrets
+SET last_used_step = $2
+WHERE user_id = $1
+                "#,
+                &challenge.user_id,
+                &step,
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(db_err)?;
+        } else {
+            let recovered = sqlx::query!(
+                r#"
+DELETE FROM totp_recovery_codes
+WHERE code_hash = $1 AND user_id = $2
+                "#,
+                super::totp::hash_recovery_code(&request.code),
+                &challenge.user_id,
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(db_err)?;
+            if recovered.rows_affected() == 0 {
+                // keep the attempt on the books
+                tx.commit().await.map_err(db_err)?;
+                return Err(Error::InvalidCode);
+            }
+            tracing::info!(user_id = %challenge.user_id, "recovery code used");
+        }
+        sqlx::query!(
+            r#"
+DELETE FROM totp_challenges
+WHERE token_hash = $1
+            "#,
+            super::tokens::hash_token(&request.challenge_token),
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(db_err)?;
+        tx.commit().await.map_err(db_err)?;
+
+        super::authenticate::create_session(
+            ctx,
+            challenge.user_id,
+            ClientInfo {
+                user_agent: challenge.user_agent,
+                ip_addr: challenge.ip_addr.and_then(|addr| addr.parse().ok()),
+            },
+            challenge.device_label.as_deref(),
+        )
+        .await
+        .map_err(db_err)
+    }
+}
+
+impl HttpEndpoint for AuthenticateTotp {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/authenticate/totp";
+
+    type HttpRequest = (Json<Request>,);
+
+    fn request((Json(req),): Self::HttpRequest) -> Result<Self::Request, Self::Error> {
+        Ok(req)
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for AuthenticateTotp {
+    const TAG: &'static Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Complete the authentication of a user with two-factor authentication enabled.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        super::refresh::Refresh::success_examples()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Self::Error>> {
+        vec![
+            (
+                "Challenge expired, exhausted or unknown",
+                Error::ChallengeRejected,
+            ),
+            ("Invalid code", Error::InvalidCode),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            ChallengeRejected | InvalidCode => Self::BAD_REQUEST,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+    use crate::{AuthenticatedEndpoint, Endpoint};
+
+    use axum::http;
+    use tower::ServiceExt;
+
+    /// 2009-02-13T23:31:30Z, at the start of a TOTP step.
+    fn epoch() -> time::OffsetDateTime {
+        time::OffsetDateTime::from_unix_timestamp(1234567890).unwrap()
+    }
+
+    /// Returns the secret and the recovery codes.
+    async fn enable_totp(ctx: &TestContext) -> (String, Vec<String>) {
+        use crate::auth::{confirm_totp, enroll_totp};
+        ctx.clock.set(epoch());
+        let secret = AuthenticatedEndpoint::handle(
+            &enroll_totp::EnrollTotp,
+            &ctx.ctx(),
+            accessing(USER_01_ID),
+            enroll_totp::Request {
+                auth_token: crate::auth::testing::USER_01_SESSION.into(),
+                user_id: USER_01_ID,
+            },
+        )
+        .await
+        .unwrap_or_log()
+        .secret;
+        let code =
+            crate::auth::totp::code_at_step(&secret, crate::auth::totp::step_at(epoch())).unwrap();
+        let recovery_codes = AuthenticatedEndpoint::handle(
+            &confirm_totp::ConfirmTotp,
+            &ctx.ctx(),
+            accessing(USER_01_ID),
+            confirm_totp::Request {
+                auth_token: Some(crate::auth::testing::USER_01_SESSION.into()),
+                user_id: Some(USER_01_ID),
+                code,
+            },
+        )
+        .await
+        .unwrap_or_log()
+        .recovery_codes;
+        (secret, recovery_codes)
+    }
+
+    async fn challenge(ctx: &TestContext) -> String {
+        match crate::auth::authenticate::Authenticate
+            .handle(
+                &ctx.ctx(),
+                crate::auth::authenticate::Request {
+                    identifier: USER_01_USERNAME.into(),
+                    password: "password".into(),
+                    device_label: Some("Phone".into()),
+                    client: None,
+                },
+            )
+            .await
+            .unwrap_or_log()
+        {
+            crate::auth::authenticate::Response::TotpRequired(challenge) => {
+                challenge.challenge_token
+            }
+            crate::auth::authenticate::Response::Session(_) => panic!("expected a challenge"),
+        }
+    }
+
+    async fn exchange(
+        ctx: &TestContext,
+        challenge_token: &str,
+        code: &str,
+    ) -> (http::StatusCode, serde_json::Value) {
+        let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+        let resp = app
+            .oneshot(
+                http::Request::builder()
+                    .method("POST")
+                    .uri("/authenticate/totp")
+                    .header(axum::http::header::CONTENT_TYPE, "application/json")
+                    .body(
+                        serde_json::to_vec(&serde_json::json!({
+                            "challengeToken": challenge_token,
+                            "code": code,
+                        }))
+                        .unwrap()
+                        .into(),
+                    )
+                    .unwrap_or_log(),
+            )
+            .await
+            .unwrap_or_log();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body())
+            .await
+            .unwrap_or_log();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn authenticate_totp_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (secret, _) = enable_totp(&ctx).await;
+            let now = epoch() + time::Duration::minutes(1);
+            ctx.clock.set(now);
+            let code =
+                crate::auth::totp::code_at_step(&secret, crate::auth::totp::step_at(now)).unwrap();
+
+            let (status, body) = exchange(&ctx, &challenge(&ctx).await, &code).await;
+            assert_eq!(status, http::StatusCode::OK, "{body:?}");
+            assert_eq!(body["userId"], USER_01_ID.to_string());
+            let device_label = sqlx::query_scalar::<_, Option<String>>(
+                "SELECT device_label FROM sessions WHERE token = $1",
+            )
+            .bind(body["token"].as_str().unwrap())
+            .fetch_one(&ctx.ctx().db_pool)
+            .await
+            .unwrap_or_log();
+            assert_eq!(device_label.as_deref(), Some("Phone"));
+
+            // codes can't be replayed
+            let (status, body) = exchange(&ctx, &challenge(&ctx).await, &code).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            check_json(
+                ("expected", &serde_json::json!({ "error": "invalidCode" })),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn authenticate_totp_takes_recovery_codes_once() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (_, recovery_codes) = enable_totp(&ctx).await;
+            assert_eq!(recovery_codes.len(), crate::auth::totp::RECOVERY_CODE_COUNT);
+
+            let (status, body) = exchange(&ctx, &challenge(&ctx).await, &recovery_codes[3]).await;
+            assert_eq!(status, http::StatusCode::OK, "{body:?}");
+            let (status, _) = exchange(&ctx, &challenge(&ctx).await, &recovery_codes[3]).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn authenticate_totp_expires_challenges() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (_, recovery_codes) = enable_totp(&ctx).await;
+            let challenge_token = challenge(&ctx).await;
+            ctx.clock.set(epoch() + time::Duration::minutes(6));
+            let (status, body) = exchange(&ctx, &challenge_token, &recovery_codes[0]).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({ "error": "challengeRejected" }),
+                ),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn authenticate_totp_limits_attempts() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (_, recovery_codes) = enable_totp(&ctx).await;
+            let challenge_token = challenge(&ctx).await;
+            for _ in 0..super::MAX_ATTEMPTS {
+                let (status, body) = exchange(&ctx, &challenge_token, "000000").await;
+                assert_eq!(status, http::StatusCode::BAD_REQUEST);
+                check_json(
+                    ("expected", &serde_json::json!({ "error": "invalidCode" })),
+                    ("response", &body),
+                );
+            }
+            let (status, body) = exchange(&ctx, &challenge_token, &recovery_codes[0]).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({ "error": "challengeRejected" }),
+                ),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+}
@@ -3,7 +3,7 @@
 
 use deps::*;
 
-use crate::auth::{Action, Resource};
+use crate::auth::{policy, Action, Resource, Role};
 
 #[derive(Clone, Copy, Debug)]
 pub struct Authorize;
@@ -22,14 +22,25 @@ pub enum Error {
     Unauthorized,
     #[error("invalid token")]
     InvalidToken,
+    #[error("not allowed while impersonating")]
+    ImpersonationForbidden,
     #[error("internal server error: {message:?}")]
     Internal { message: String },
 }
 
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub user_id: uuid::Uuid,
+    pub roles: Vec<Role>,
+    /// The admin acting as `user_id` if the token is from
+    /// [`Impersonate`](super::impersonate::Impersonate).
+    pub impersonator_id: Option<uuid::Uuid>,
+}
+
 #[async_trait::async_trait]
 impl crate::Endpoint for Authorize {
     type Request = Request;
-    type Response = uuid::Uuid;
+    type Response = Response;
     type Error = Error;
 
     #[tracing::instrument(skip(ctx))]
@@ -38,30 +49,180 @@ impl crate::Endpoint for Authorize {
         ctx: &crate::Context,
         request: Self::Request,
     ) -> Result<Self::Response, Self::Error> {
-        // TODO: roles support
-        // TODO: cache db access
-        let session = sqlx::query_as!(
-            super::Session,
-            r#"
-SELECT * 
-FROM sessions
-WHERE token = $1
-            "#,
-            &request.auth_token[..]
-        )
-        .fetch_one(&ctx.db_pool)
-        .await
-        .map_err(|err| match err {
-            sqlx::Error::RowNotFound => Error::InvalidToken,
-            _ => Error::Internal {
-                message: format!("{err}"),
+        let (user_id, roles, impersonator_id) = match &ctx.config.jwt {
+            Some(jwt) if super::jwt::looks_like_jwt(&request.auth_token) => {
+                // the claims carry everything, no db involved
+                let claims = jwt.verify(&request.auth_token).map_err(|err| {
+                    tracing::debug!(%err, "rejected jwt");
+                    Error::InvalidToken
+                })?;
+                if ctx.session_cache.is_revoked(&request.auth_token) {
+                    return Err(Error::InvalidToken);
+                }
+                (claims.sub, claims.roles, claims.act.map(|act| act.sub))
+            }
+            _ => {
+                let (user_id, impersonator_id) = if request
+                    .auth_token
+                    .starts_with(super::tokens::API_KEY_PREFIX)
+                {
+                    (authenticate_api_key(ctx, &request).await?, None)
+                } else {
+                    let session = authenticate_session(ctx, &request.auth_token).await?;
+                    (session.user_id, session.impersonator_id)
+                };
+                let roles = user_roles(ctx, user_id)
+                    .await
+                    .map_err(|err| Error::Internal {
+                        message: format!("{err}"),
+                    })?;
+                (user_id, roles, impersonator_id)
+            }
+        };
+
+        let subject = policy::Subject { user_id, roles };
+        let attributes = policy::Attributes {
+            owner_id: match &request.resource {
+                Resource::User { id } => Some(*id),
+                // session endpoints only ever touch the accessing user's sessions
+                Resource::Session { .. } => Some(user_id),
+                Resource::Sessions { user_id: owner_id }
+                | Resource::ApiKeys { user_id: owner_id } => Some(*owner_id),
+                // nobody owns the right to become someone else
+                Resource::Impersonation { .. } | Resource::Users | Resource::DeletedUsers => None,
             },
-        })?;
-        if session.expires_at < time::OffsetDateTime::now_utc() {
-            return Err(Error::InvalidToken);
+        };
+        let decision = ctx
+            .policy
+            .decide(&subject, &request.resource, request.action, &attributes)
+            .await
+            .map_err(|err| match err {
+                policy::Error::Internal { message } => Error::Internal { message },
+            })?;
+        if !decision.is_allowed() {
+            tracing::info!(
+                reason = decision.reason(),
+                user_id = %subject.user_id,
+                resource = ?request.resource,
+                action = ?request.action,
+                "access denied"
+            );
+            return Err(Error::Unauthorized);
+        }
+        let policy::Subject { user_id, roles } = subject;
+        Ok(Response {
+            user_id,
+            roles,
+            impersonator_id,
+        })
+    }
+}
+
+pub(crate) async fn user_roles(
+    ctx: &crate::Context,
+    user_id: uuid::Uuid,
+) -> Result<Vec<Role>, sqlx::Error> {
+    Ok(sqlx::query_scalar!(
+        r#"
+SELECT role
+FROM user_roles
+WHERE user_id = $1
+        "#,
+        &user_id
+    )
+    .fetch_all(&ctx.db_pool)
+    .await?
+    .into_iter()
+    .filter_map(|role| {
+        role.parse::<Role>()
+            .map_err(|err| tracing::warn!(%err, "ignoring role"))
+            .ok()
+    })
+    .collect())
+}
+
+#[tracing::instrument(skip_all)]
+async fn authenticate_session(
+    ctx: &crate::Context,
+    auth_token: &str,
+) -> Result<super::session_cache::CachedSession, Error> {
+    let session = match ctx.session_cache.get(auth_token).await {
+        Some(session) => session,
+        None => {
+            // `last_seen_at` is only as fresh as the cache's ttl
+            let session = sqlx::query_as!(
+                super::session_cache::CachedSession,
+                r#"
+UPDATE sessions
+SET last_seen_at = CURRENT_TIMESTAMP
+WHERE token = $1
+RETURNING user_id, expires_at, impersonator_id
+                "#,
+                auth_token
+            )
+            .fetch_one(&ctx.db_pool)
+            .await
+            .map_err(|err| match err {
+                sqlx::Error::RowNotFound => Error::InvalidToken,
+                _ => Error::Internal {
+                    message: format!("{err}"),
+                },
+            })?;
+            if session.expires_at < ctx.clock.now() {
+                return Err(Error::InvalidToken);
+            }
+            ctx.session_cache.insert(auth_token, session.clone()).await;
+            session
         }
-        Ok(session.user_id)
+    };
+    Ok(session)
+}
+
+/// Keys are checked against the database on every use, there's no caching
+/// like for sessions.
+#[tracing::instrument(skip_all)]
+async fn authenticate_api_key(
+    ctx: &crate::Context,
+    request: &Request,
+) -> Result<uuid::Uuid, Error> {
+    let key = sqlx::query!(
+        r#"
+UPDATE api_keys
+SET last_used_at = CURRENT_TIMESTAMP
+WHERE key_hash = $1 AND (expires_at IS NULL OR expires_at > $2)
+RETURNING user_id, scopes
+        "#,
+        super::tokens::hash_token(&request.auth_token),
+        ctx.clock.now(),
+    )
+    .fetch_optional(&ctx.db_pool)
+    .await
+    .map_err(|err| Error::Internal {
+        message: format!("{err}"),
+    })?;
+    let key = if let Some(key) = key {
+        key
+    } else {
+        return Err(Error::InvalidToken);
+    };
+    // keys can't be used to mint more keys or sessions
+    if matches!(
+        request.resource,
+        Resource::ApiKeys { .. } | Resource::Impersonation { .. }
+    ) || !key
+        .scopes
+        .iter()
+        .any(|scope| scope == request.action.as_str())
+    {
+        tracing::info!(
+            user_id = %key.user_id,
+            resource = ?request.resource,
+            action = ?request.action,
+            "api key out of scope"
+        );
+        return Err(Error::Unauthorized);
     }
+    Ok(key.user_id)
 }
 
 #[cfg(test)]
@@ -80,15 +241,24 @@ mod tests {
             {
                 let res = authenticate::Authenticate.handle(&ctx.ctx(), authenticate::Request{
                     identifier: username.to_string(),
-                    password: "password".into()
-                }).await.unwrap_or_log();
-                for (resource, action) in resource_actions {
-                    let user_id = authorize::Authorize.handle(&ctx.ctx(), authorize::Request {
+                    password: "password".into(),
+                    device_label: None,
+                    client: None,
+                }).await.unwrap_or_log().into_tokens().unwrap();
+                for (resource, action, allowed) in resource_actions {
+                    let result = authorize::Authorize.handle(&ctx.ctx(), authorize::Request {
                         auth_token: res.token.clone().into(),
-                        resource,
+                        resource: resource.clone(),
                         action
-                    }).await.unwrap_or_log();
-                    assert_eq!(id, user_id);
+                    }).await;
+                    if allowed {
+                        assert_eq!(id, result.unwrap_or_log().user_id);
+                    } else {
+                        assert!(
+                            matches!(result, Err(authorize::Error::Unauthorized)),
+                            "{resource:?} {action:?} {result:?}"
+                        );
+                    }
                 }
             }
             ctx.close().await;
@@ -111,9 +281,70 @@ mod tests {
                         Action::Delete
                     ]
                     .into_iter()
-                    .map(move |act| (res.clone(), act))
+                    .map(move |act| (res.clone(), act, true))
                 }).collect::<Vec<_>>()
             }
         ),
+        denies_access_to_others_without_role: (
+            USER_01_USERNAME,
+            USER_01_ID,
+            {
+                [
+                    Resource::User { id: USER_02_ID },
+                    Resource::Users,
+                ]
+                .into_iter()
+                .flat_map(|res| {
+                    [
+                        Action::Read,
+                        Action::Write,
+                        Action::Delete
+                    ]
+                    .into_iter()
+                    .map(move |act| (res.clone(), act, false))
+                }).collect::<Vec<_>>()
+            }
+        ),
+        allows_super_admin_any_action: (
+            USER_04_USERNAME,
+            USER_04_ID,
+            {
+                [
+                    Resource::User { id: USER_02_ID },
+                    Resource::Users,
+                ]
+                .into_iter()
+                .flat_map(|res| {
+                    [
+                        Action::Read,
+                        Action::Write,
+                        Action::Delete
+                    ]
+                    .into_iter()
+                    .map(move |act| (res.clone(), act, true))
+                }).collect::<Vec<_>>()
+            }
+        ),
+    }
+
+    #[tokio::test]
+    async fn authorize_returns_roles() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let res = authorize::Authorize
+                .handle(
+                    &ctx.ctx(),
+                    authorize::Request {
+                        auth_token: USER_04_SESSION.into(),
+                        resource: Resource::Users,
+                        action: Action::Read,
+                    },
+                )
+                .await
+                .unwrap_or_log();
+            assert_eq!(res.user_id, USER_04_ID);
+            assert_eq!(res.roles, vec![Role::SuperAdmin]);
+        }
+        ctx.close().await;
     }
 }
@@ -0,0 +1,175 @@
+use deps::*;
+
+use crate::*;
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug)]
+pub struct BeginIdentityLink;
+
+#[derive(Debug)]
+pub struct Request {
+    pub auth_token: std::sync::Arc<str>,
+    pub user_id: uuid::Uuid,
+    pub provider: String,
+}
+
+pub type Response = super::oidc::AuthorizationRedirect;
+
+#[derive(Debug, thiserror::Error, Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("unknown provider: {name:?}")]
+    UnknownProvider { name: String },
+    #[error("provider error: {message:?}")]
+    Provider { message: String },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+impl From<super::oidc::Error> for Error {
+    fn from(err: super::oidc::Error) -> Self {
+        use super::oidc::Error::*;
+        match err {
+            UnknownProvider { name } => Self::UnknownProvider { name },
+            Provider { message } => Self::Provider { message },
+            StateRejected => Self::Internal {
+                message: "state rejected when beginning".into(),
+            },
+            Internal { message } => Self::Internal { message },
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for BeginIdentityLink {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone(),
+            resource: crate::auth::Resource::User {
+                id: request.user_id,
+            },
+            action: crate::auth::Action::Write,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        // a linked identity can authenticate as the user, only they may add one
+        if accessing_user.user_id != request.user_id {
+            return Err(Error::AccessDenied);
+        }
+        Ok(super::oidc::begin(ctx, &request.provider, Some(request.user_id)).await?)
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            UnknownProvider { .. } => Self::NOT_FOUND,
+            Provider { .. } => Self::BAD_GATEWAY,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for BeginIdentityLink {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/users/:id/identities/:provider";
+
+    type HttpRequest = (BearerToken, Path<(uuid::Uuid, String)>);
+
+    fn request(
+        (BearerToken(token), Path((user_id, provider))): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            auth_token: token,
+            user_id,
+            provider,
+        })
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for BeginIdentityLink {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Start linking an identity at an OpenID Connect provider to the user. Once the user signs in there, forward the `code` and `state` the provider redirects back with to `/users/{id}/identities/{provider}/callback`.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        super::begin_oidc_login::BeginOidcLogin::success_examples()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Unknown provider",
+                Error::UnknownProvider {
+                    name: "myspace".into(),
+                },
+            ),
+            (
+                "Provider unreachable or misbehaving",
+                Error::Provider {
+                    message: "error sending request".into(),
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    crate::integration_table_tests! {
+        begin_identity_link_works: {
+            uri: format!("/users/{USER_01_ID}/identities/mock"),
+            method: "POST",
+            status: StatusCode::OK,
+            router: crate::auth::router(),
+            auth_token: USER_01_SESSION.into(),
+        },
+        begin_identity_link_rejects_other_users: {
+            uri: format!("/users/{USER_01_ID}/identities/mock"),
+            method: "POST",
+            status: StatusCode::UNAUTHORIZED,
+            router: crate::auth::router(),
+            check_json: serde_json::json!({
+                "error": "accessDenied",
+            }),
+            auth_token: USER_04_SESSION.into(),
+        },
+    }
+}
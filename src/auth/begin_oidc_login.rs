@@ -0,0 +1,156 @@
+use deps::*;
+
+use crate::*;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone)]
+pub struct BeginOidcLogin;
+
+#[derive(Debug)]
+pub struct Request {
+    pub provider: String,
+}
+
+pub type Response = super::oidc::AuthorizationRedirect;
+
+#[derive(Debug, Serialize, thiserror::Error, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase", tag = "error")]
+pub enum Error {
+    #[error("unknown provider: {name:?}")]
+    UnknownProvider { name: String },
+    #[error("provider error: {message:?}")]
+    Provider { message: String },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+impl From<super::oidc::Error> for Error {
+    fn from(err: super::oidc::Error) -> Self {
+        use super::oidc::Error::*;
+        match err {
+            UnknownProvider { name } => Self::UnknownProvider { name },
+            Provider { message } => Self::Provider { message },
+            StateRejected => Self::Internal {
+                message: "state rejected when beginning".into(),
+            },
+            Internal { message } => Self::Internal { message },
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Endpoint for BeginOidcLogin {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        Ok(super::oidc::begin(ctx, &request.provider, None).await?)
+    }
+}
+
+impl HttpEndpoint for BeginOidcLogin {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/authenticate/oidc/:provider";
+
+    type HttpRequest = (Path<String>,);
+
+    fn request((Path(provider),): Self::HttpRequest) -> Result<Self::Request, Self::Error> {
+        Ok(Request { provider })
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for BeginOidcLogin {
+    const TAG: &'static Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Start authenticating through an OpenID Connect provider. Once the user signs in there, forward the `code` and `state` the provider redirects back with to `/authenticate/oidc/{provider}/callback`.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        [Response {
+            authorization_url: "https://accounts.google.com/o/oauth2/v2/auth?response_type=code&client_id=1234.apps.googleusercontent.com&state=Jx1bVq0cR4m2Zp8sYk3LwT7eHn5GdA9uFo6iCj0vXyE".into(),
+        }]
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Self::Error>> {
+        vec![
+            (
+                "Unknown provider",
+                Error::UnknownProvider {
+                    name: "myspace".into(),
+                },
+            ),
+            (
+                "Provider unreachable or misbehaving",
+                Error::Provider {
+                    message: "error sending request".into(),
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            UnknownProvider { .. } => Self::NOT_FOUND,
+            Provider { .. } => Self::BAD_GATEWAY,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::utils::testing::*;
+
+    crate::integration_table_tests! {
+        begin_oidc_login_works: {
+            uri: "/authenticate/oidc/mock",
+            method: "POST",
+            status: StatusCode::OK,
+            router: crate::auth::router(),
+            extra_assertions: &|EAArgs { response_json, ctx, .. }| {
+                Box::pin(async move {
+                    let body = response_json.unwrap();
+                    let url = body["authorizationUrl"].as_str().unwrap();
+                    assert!(url.contains("code_challenge_method=S256"), "{url}");
+                    // the issuer accepts it
+                    ctx.oidc_issuer.authorize(url, "sub-01", "hex.queen@teen.dj");
+                })
+            },
+        },
+        begin_oidc_login_rejects_unknown_providers: {
+            uri: "/authenticate/oidc/myspace",
+            method: "POST",
+            status: StatusCode::NOT_FOUND,
+            router: crate::auth::router(),
+            check_json: serde_json::json!({
+                "error": "unknownProvider",
+                "name": "myspace",
+            }),
+        },
+    }
+}
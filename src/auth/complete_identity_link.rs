@@ -0,0 +1,272 @@
+use deps::*;
+
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug)]
+pub struct CompleteIdentityLink;
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Request {
+    #[serde(skip)]
+    pub auth_token: Option<std::sync::Arc<str>>,
+    #[serde(skip)]
+    pub user_id: Option<uuid::Uuid>,
+    #[serde(skip)]
+    pub provider: String,
+    /// As received from the provider at the redirect URI.
+    pub code: String,
+    /// As received from the provider at the redirect URI.
+    pub state: String,
+}
+
+pub type Response = super::oidc::Identity;
+
+#[derive(Debug, thiserror::Error, Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("unknown provider: {name:?}")]
+    UnknownProvider { name: String },
+    #[error("invalid or expired state")]
+    StateRejected,
+    #[error("identity already linked to another user")]
+    IdentityTaken,
+    #[error("provider error: {message:?}")]
+    Provider { message: String },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+impl From<super::oidc::Error> for Error {
+    fn from(err: super::oidc::Error) -> Self {
+        use super::oidc::Error::*;
+        match err {
+            UnknownProvider { name } => Self::UnknownProvider { name },
+            StateRejected => Self::StateRejected,
+            Provider { message } => Self::Provider { message },
+            Internal { message } => Self::Internal { message },
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for CompleteIdentityLink {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone().unwrap(),
+            resource: crate::auth::Resource::User {
+                id: request.user_id.unwrap(),
+            },
+            action: crate::auth::Action::Write,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let user_id = request.user_id.unwrap();
+        if accessing_user.user_id != user_id {
+            return Err(Error::AccessDenied);
+        }
+        let claims = super::oidc::complete(
+            ctx,
+            &request.provider,
+            &request.code,
+            &request.state,
+            Some(user_id),
+        )
+        .await?;
+        // relinking to the same user only refreshes the email
+        let identity = sqlx::query_as!(
+            Response,
+            r#"
+INSERT INTO user_identities (provider, subject, user_id, email)
+VALUES ($1, $2, $3, $4::TEXT::CITEXT)
+ON CONFLICT (provider, subject) DO UPDATE
+SET email = EXCLUDED.email
+WHERE user_identities.user_id = EXCLUDED.user_id
+RETURNING provider, subject, email::TEXT, created_at
+            "#,
+            &request.provider,
+            &claims.sub,
+            &user_id,
+            claims.email.as_deref(),
+        )
+        .fetch_optional(&ctx.db_pool)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
+        identity.ok_or(Error::IdentityTaken)
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            UnknownProvider { .. } => Self::NOT_FOUND,
+            StateRejected => Self::BAD_REQUEST,
+            IdentityTaken => Self::CONFLICT,
+            Provider { .. } => Self::BAD_GATEWAY,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for CompleteIdentityLink {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/users/:id/identities/:provider/callback";
+    const SUCCESS_CODE: StatusCode = StatusCode::CREATED;
+
+    type HttpRequest = (BearerToken, Path<(uuid::Uuid, String)>, Json<Request>);
+
+    fn request(
+        (BearerToken(token), Path((user_id, provider)), Json(req)): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            auth_token: Some(token),
+            user_id: Some(user_id),
+            provider,
+            ..req
+        })
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for CompleteIdentityLink {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Complete linking an identity at an OpenID Connect provider to the user, after which the user can authenticate through the provider.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        [Response {
+            provider: "google".into(),
+            subject: "110169484474386276334".into(),
+            email: Some("hex.queen@teen.dj".into()),
+            created_at: time::OffsetDateTime::now_utc(),
+        }]
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Unknown provider",
+                Error::UnknownProvider {
+                    name: "myspace".into(),
+                },
+            ),
+            ("State expired, spent or unknown", Error::StateRejected),
+            (
+                "Identity already linked to another user",
+                Error::IdentityTaken,
+            ),
+            (
+                "Provider unreachable or rejected the code",
+                Error::Provider {
+                    message: "HTTP status client error (400 Bad Request)".into(),
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+    use crate::AuthenticatedEndpoint;
+
+    async fn link(
+        ctx: &TestContext,
+        user_id: uuid::Uuid,
+        auth_token: &str,
+        subject: &str,
+    ) -> Result<super::Response, super::Error> {
+        let redirect = crate::auth::oidc::begin(&ctx.ctx(), "mock", Some(user_id))
+            .await
+            .unwrap_or_log();
+        let (code, state) =
+            ctx.oidc_issuer
+                .authorize(&redirect.authorization_url, subject, "hex.queen@teen.dj");
+        super::CompleteIdentityLink
+            .handle(
+                &ctx.ctx(),
+                accessing(user_id),
+                super::Request {
+                    auth_token: Some(auth_token.into()),
+                    user_id: Some(user_id),
+                    provider: "mock".into(),
+                    code,
+                    state,
+                },
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn complete_identity_link_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let identity = link(&ctx, USER_01_ID, USER_01_SESSION, "sub-01")
+                .await
+                .unwrap_or_log();
+            assert_eq!(identity.provider, "mock");
+            assert_eq!(identity.subject, "sub-01");
+            assert_eq!(identity.email.as_deref(), Some("hex.queen@teen.dj"));
+            // relinking is fine
+            link(&ctx, USER_01_ID, USER_01_SESSION, "sub-01")
+                .await
+                .unwrap_or_log();
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn complete_identity_link_rejects_taken_identities() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            link(&ctx, USER_01_ID, USER_01_SESSION, "sub-01")
+                .await
+                .unwrap_or_log();
+            let err = link(&ctx, USER_04_ID, USER_04_SESSION, "sub-01")
+                .await
+                .unwrap_err();
+            assert!(matches!(err, super::Error::IdentityTaken), "{err:?}");
+        }
+        ctx.close().await;
+    }
+}
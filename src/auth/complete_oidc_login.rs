@@ -0,0 +1,433 @@
+use deps::*;
+
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+pub struct CompleteOidcLogin;
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Request {
+    #[serde(skip)]
+    pub provider: String,
+    /// As received from the provider at the redirect URI.
+    pub code: String,
+    /// As received from the provider at the redirect URI.
+    pub state: String,
+    #[serde(skip)]
+    pub client: Option<ClientInfo>,
+}
+
+pub type Response = super::authenticate::Response;
+
+#[derive(Debug, Serialize, thiserror::Error, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase", tag = "error")]
+pub enum Error {
+    #[error("unknown provider: {name:?}")]
+    UnknownProvider { name: String },
+    #[error("invalid or expired state")]
+    StateRejected,
+    #[error("identity not linked to any user")]
+    IdentityNotLinked,
+    #[error("email not verified")]
+    EmailNotVerified,
+    #[error("too many failed attempts, retry after {retry_after}s")]
+    #[serde(rename_all = "camelCase")]
+    TooManyAttempts {
+        /// In seconds.
+        retry_after: u64,
+    },
+    #[error("provider error: {message:?}")]
+    Provider { message: String },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+impl From<super::oidc::Error> for Error {
+    fn from(err: super::oidc::Error) -> Self {
+        use super::oidc::Error::*;
+        match err {
+            UnknownProvider { name } => Self::UnknownProvider { name },
+            StateRejected => Self::StateRejected,
+            Provider { message } => Self::Provider { message },
+            Internal { message } => Self::Internal { message },
+        }
+    }
+}
+
+impl From<super::authenticate::Error> for Error {
+    fn from(err: super::authenticate::Error) -> Self {
+        use super::authenticate::Error::*;
+        match err {
+            EmailNotVerified => Self::EmailNotVerified,
+            TooManyAttempts { retry_after } => Self::TooManyAttempts { retry_after },
+            Internal { message } => Self::Internal { message },
+            CredentialsRejected | InvalidInput { .. } => Self::Internal {
+                message: format!("unexpected error: {err}"),
+            },
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Endpoint for CompleteOidcLogin {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let claims =
+            super::oidc::complete(ctx, &request.provider, &request.code, &request.state, None)
+                .await?;
+        let db_err = |err| Error::Internal {
+            message: format!("db error: {err}"),
+        };
+        // identities are only ever linked explicitly, never matched by email
+        let account = sqlx::query_as!(
+            super::authenticate::Account,
+            r#"
+SELECT
+    users.id as user_id,
+    users.created_at,
+    users.email_verified_at,
+    totp_secrets.confirmed_at IS NOT NULL as "totp_enabled!"
+FROM user_identities
+JOIN users ON users.id = user_identities.user_id
+LEFT JOIN totp_secrets ON totp_secrets.user_id = user_identities.user_id
+WHERE provider = $1 AND subject = $2
+            "#,
+            &request.provider,
+            &claims.sub,
+        )
+        .fetch_optional(&ctx.db_pool)
+        .await
+        .map_err(db_err)?;
+        let account = if let Some(account) = account {
+            account
+        } else {
+            return Err(Error::IdentityNotLinked);
+        };
+        let client = request.client.unwrap_or_default();
+        // the provider vouches for the user but a locked account stays locked
+        let lockout_keys = super::lockout::Keys::for_user(account.user_id, client.ip_addr);
+        if let Some(locked_for) = super::lockout::locked_for(ctx, &lockout_keys)
+            .await
+            .map_err(db_err)?
+        {
+            return Err(Error::TooManyAttempts {
+                retry_after: locked_for.as_seconds_f64().ceil() as u64,
+            });
+        }
+        Ok(super::authenticate::sign_in(ctx, account, client, None).await?)
+    }
+}
+
+impl HttpEndpoint for CompleteOidcLogin {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/authenticate/oidc/:provider/callback";
+
+    type HttpRequest = (Path<String>, ClientInfo, Json<Request>);
+
+    fn request(
+        (Path(provider), client, Json(req)): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            provider,
+            client: Some(client),
+            ..req
+        })
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+
+    fn error_response(err: Self::Error) -> axum::response::Response {
+        let retry_after = match &err {
+            Error::TooManyAttempts { retry_after } => Some(*retry_after),
+            _ => None,
+        };
+        let mut resp = (StatusCode::from(&err), Json(err)).into_response();
+        if let Some(retry_after) = retry_after {
+            super::lockout::set_retry_after(&mut resp, retry_after);
+        }
+        resp
+    }
+}
+
+impl DocumentedEndpoint for CompleteOidcLogin {
+    const TAG: &'static Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Complete authenticating through an OpenID Connect provider. The identity must have been linked to a user through `/users/{id}/identities/{provider}` beforehand.";
+    const SUCCESS_DESCRIPTION: &'static str =
+        super::authenticate::Authenticate::SUCCESS_DESCRIPTION;
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        super::authenticate::Authenticate::success_examples()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Self::Error>> {
+        vec![
+            (
+                "Unknown provider",
+                Error::UnknownProvider {
+                    name: "myspace".into(),
+                },
+            ),
+            ("State expired, spent or unknown", Error::StateRejected),
+            ("Identity not linked to any user", Error::IdentityNotLinked),
+            (
+                "Email not verified within the grace period",
+                Error::EmailNotVerified,
+            ),
+            (
+                "Too many failed attempts, see the `Retry-After` header",
+                Error::TooManyAttempts { retry_after: 30 },
+            ),
+            (
+                "Provider unreachable or rejected the code",
+                Error::Provider {
+                    message: "HTTP status client error (400 Bad Request)".into(),
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            UnknownProvider { .. } => Self::NOT_FOUND,
+            StateRejected | IdentityNotLinked => Self::BAD_REQUEST,
+            EmailNotVerified => Self::FORBIDDEN,
+            TooManyAttempts { .. } => Self::TOO_MANY_REQUESTS,
+            Provider { .. } => Self::BAD_GATEWAY,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::{begin_oidc_login, complete_identity_link};
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+    use crate::{AuthenticatedEndpoint, Endpoint};
+
+    /// Returns the `code` and `state` for signing in as `subject`.
+    async fn sign_in(ctx: &TestContext, subject: &str) -> (String, String) {
+        let redirect = begin_oidc_login::BeginOidcLogin
+            .handle(
+                &ctx.ctx(),
+                begin_oidc_login::Request {
+                    provider: "mock".into(),
+                },
+            )
+            .await
+            .unwrap_or_log();
+        ctx.oidc_issuer
+            .authorize(&redirect.authorization_url, subject, "hex.queen@teen.dj")
+    }
+
+    async fn callback(
+        ctx: &TestContext,
+        code: &str,
+        state: &str,
+    ) -> (http::StatusCode, serde_json::Value) {
+        let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+        let resp = app
+            .oneshot(
+                http::Request::builder()
+                    .method("POST")
+                    .uri("/authenticate/oidc/mock/callback")
+                    .header(axum::http::header::CONTENT_TYPE, "application/json")
+                    .body(
+                        serde_json::to_vec(&serde_json::json!({
+                            "code": code,
+                            "state": state,
+                        }))
+                        .unwrap()
+                        .into(),
+                    )
+                    .unwrap_or_log(),
+            )
+            .await
+            .unwrap_or_log();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body())
+            .await
+            .unwrap_or_log();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    /// Links `sub-01` to `USER_01`.
+    async fn link_user_01(ctx: &TestContext) {
+        let redirect = crate::auth::oidc::begin(&ctx.ctx(), "mock", Some(USER_01_ID))
+            .await
+            .unwrap_or_log();
+        let (code, state) =
+            ctx.oidc_issuer
+                .authorize(&redirect.authorization_url, "sub-01", "hex.queen@teen.dj");
+        AuthenticatedEndpoint::handle(
+            &complete_identity_link::CompleteIdentityLink,
+            &ctx.ctx(),
+            accessing(USER_01_ID),
+            complete_identity_link::Request {
+                auth_token: Some(USER_01_SESSION.into()),
+                user_id: Some(USER_01_ID),
+                provider: "mock".into(),
+                code,
+                state,
+            },
+        )
+        .await
+        .unwrap_or_log();
+    }
+
+    #[tokio::test]
+    async fn complete_oidc_login_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            link_user_01(&ctx).await;
+            let (code, state) = sign_in(&ctx, "sub-01").await;
+            let (status, body) = callback(&ctx, &code, &state).await;
+            assert_eq!(status, http::StatusCode::OK, "{body:?}");
+            assert_eq!(body["userId"], USER_01_ID.to_string());
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn complete_oidc_login_challenges_totp_users() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            link_user_01(&ctx).await;
+            sqlx::query(
+                "INSERT INTO totp_secrets (user_id, secret, confirmed_at) VALUES ($1, 'JBSWY3DPEHPK3PXP', CURRENT_TIMESTAMP)",
+            )
+            .bind(USER_01_ID)
+            .execute(&ctx.ctx().db_pool)
+            .await
+            .unwrap_or_log();
+            let (code, state) = sign_in(&ctx, "sub-01").await;
+            let (status, body) = callback(&ctx, &code, &state).await;
+            assert_eq!(status, http::StatusCode::OK, "{body:?}");
+            assert!(body["challengeToken"].is_string(), "{body:?}");
+            assert!(body.get("token").is_none(), "{body:?}");
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn complete_oidc_login_respects_lockouts() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            link_user_01(&ctx).await;
+            let keys = crate::auth::lockout::Keys::for_user(USER_01_ID, None);
+            for _ in 0..ctx.ctx().config.login_lockout.threshold {
+                crate::auth::lockout::record_attempt(&ctx.ctx(), &keys)
+                    .await
+                    .unwrap_or_log();
+            }
+            let (code, state) = sign_in(&ctx, "sub-01").await;
+            let (status, body) = callback(&ctx, &code, &state).await;
+            assert_eq!(status, http::StatusCode::TOO_MANY_REQUESTS, "{body:?}");
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({ "error": "tooManyAttempts" }),
+                ),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn complete_oidc_login_fails_if_unverified_past_grace_period() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            link_user_01(&ctx).await;
+            sqlx::query("UPDATE users SET email_verified_at = NULL WHERE id = $1")
+                .bind(USER_01_ID)
+                .execute(&ctx.ctx().db_pool)
+                .await
+                .unwrap_or_log();
+            ctx.clock
+                .set(time::OffsetDateTime::now_utc() + time::Duration::days(30));
+            let (code, state) = sign_in(&ctx, "sub-01").await;
+            let (status, body) = callback(&ctx, &code, &state).await;
+            assert_eq!(status, http::StatusCode::FORBIDDEN, "{body:?}");
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({ "error": "emailNotVerified" }),
+                ),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn complete_oidc_login_rejects_unlinked_identities() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (code, state) = sign_in(&ctx, "sub-unknown").await;
+            let (status, body) = callback(&ctx, &code, &state).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({ "error": "identityNotLinked" }),
+                ),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn complete_oidc_login_spends_state() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (code, state) = sign_in(&ctx, "sub-unknown").await;
+            callback(&ctx, &code, &state).await;
+            let (status, body) = callback(&ctx, &code, &state).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            check_json(
+                ("expected", &serde_json::json!({ "error": "stateRejected" })),
+                ("response", &body),
+            );
+
+            // a state for linking can't be used to authenticate
+            let redirect = crate::auth::oidc::begin(&ctx.ctx(), "mock", Some(USER_01_ID))
+                .await
+                .unwrap_or_log();
+            let (code, state) = ctx.oidc_issuer.authorize(
+                &redirect.authorization_url,
+                "sub-01",
+                "hex.queen@teen.dj",
+            );
+            let (status, _) = callback(&ctx, &code, &state).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+        }
+        ctx.close().await;
+    }
+}
@@ -0,0 +1,314 @@
+use deps::*;
+
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+pub struct ConfirmPasswordReset;
+
+#[derive(Debug, Deserialize, validator::Validate, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Request {
+    /// As mailed by `/password-reset/request`.
+    pub token: String,
+    /// Has to satisfy the password policy.
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, thiserror::Error, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase", tag = "error")]
+pub enum Error {
+    #[error("invalid or expired token")]
+    InvalidToken,
+    #[error("invalid input: {issues:?}")]
+    InvalidInput {
+        #[from]
+        issues: ValidationErrors,
+    },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+pub type Response = NoContent;
+
+#[async_trait::async_trait]
+impl Endpoint for ConfirmPasswordReset {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        validator::Validate::validate(&request).map_err(utils::ValidationErrors::from)?;
+        let db_err = |err| Error::Internal {
+            message: format!("db error: {err}"),
+        };
+        let mut tx = ctx.db_pool.begin().await.map_err(db_err)?;
+        // deleting spends the token
+        let spent = sqlx::query!(
+            r#"
+DELETE FROM password_reset_tokens
+WHERE token_hash = $1
+RETURNING user_id, expires_at
+            "#,
+            super::tokens::hash_token(&request.token),
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(db_err)?;
+        let user_id = match spent {
+            Some(spent) if spent.expires_at > ctx.clock.now() => spent.user_id,
+            Some(_) => {
+                tx.commit().await.map_err(db_err)?;
+                return Err(Error::InvalidToken);
+            }
+            None => return Err(Error::InvalidToken),
+        };
+
+        let user = sqlx::query!(
+            r#"
+SELECT username::TEXT as "username!", email::TEXT as "email!"
+FROM users
+WHERE id = $1
+            "#,
+            &user_id,
+        )
+        .fetch_one(&mut tx)
+        .await
+        .map_err(db_err)?;
+        let mut issues = validator::ValidationErrors::new();
+        ctx.config.password_policy.check(
+            &mut issues,
+            "new_password",
+            &request.new_password,
+            &[&user.username, &user.email],
+        );
+        // dropping the transaction leaves the token unspent
+        if !issues.is_empty() {
+            return Err(utils::ValidationErrors::from(issues).into());
+        }
+
+        let pass_hash = crate::auth::password::hash(&ctx.config, &request.new_password);
+        sqlx::query!(
+            r#"
+UPDATE credentials
+SET pass_hash = $2
+WHERE user_id = $1
+            "#,
+            &user_id,
+            &pass_hash,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(db_err)?;
+        // other outstanding reset tokens are no good either
+        sqlx::query!(
+            r#"
+DELETE FROM password_reset_tokens
+WHERE user_id = $1
+            "#,
+            &user_id,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(db_err)?;
+        let revoked = sqlx::query_scalar!(
+            r#"
+DELETE FROM sessions
+WHERE user_id = $1
+RETURNING token
+            "#,
+            &user_id,
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(db_err)?;
+        tx.commit().await.map_err(db_err)?;
+        ctx.session_cache.invalidate(revoked).await;
+        Ok(NoContent)
+    }
+}
+
+impl HttpEndpoint for ConfirmPasswordReset {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/password-reset/confirm";
+    const SUCCESS_CODE: StatusCode = StatusCode::NO_CONTENT;
+
+    type HttpRequest = (Json<Request>,);
+
+    fn request((Json(req),): Self::HttpRequest) -> Result<Self::Request, Self::Error> {
+        Ok(req)
+    }
+
+    fn response(_: Self::Response) -> axum::response::Response {
+        Default::default()
+    }
+}
+
+impl DocumentedEndpoint for ConfirmPasswordReset {
+    const TAG: &'static Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Set a new password using a password reset token. Revokes all sessions of the user.";
+
+    fn errors() -> Vec<ErrorResponse<Self::Error>> {
+        vec![
+            ("Invalid or expired token", Error::InvalidToken),
+            (
+                "Invalid input",
+                Error::InvalidInput {
+                    issues: {
+                        let mut issues = validator::ValidationErrors::new();
+                        issues.add(
+                            "new_password",
+                            validator::ValidationError {
+                                code: std::borrow::Cow::from("length"),
+                                message: None,
+                                params: [(std::borrow::Cow::from("value"), serde_json::json!(""))]
+                                    .into_iter()
+                                    .collect(),
+                            },
+                        );
+                        issues.into()
+                    },
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            InvalidToken | InvalidInput { .. } => Self::BAD_REQUEST,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+    use crate::Endpoint;
+
+    use axum::http;
+    use tower::ServiceExt;
+
+    async fn confirm(ctx: &TestContext, token: &str) -> (http::StatusCode, serde_json::Value) {
+        let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+        let resp = app
+            .oneshot(
+                http::Request::builder()
+                    .method("POST")
+                    .uri("/password-reset/confirm")
+                    .header(axum::http::header::CONTENT_TYPE, "application/json")
+                    .body(
+                        serde_json::to_vec(&serde_json::json!({
+                            "token": token,
+                            "newPassword": "hunter2Hunter!",
+                        }))
+                        .unwrap()
+                        .into(),
+                    )
+                    .unwrap_or_log(),
+            )
+            .await
+            .unwrap_or_log();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body())
+            .await
+            .unwrap_or_log();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    async fn request_token(ctx: &TestContext) -> String {
+        crate::auth::request_password_reset::RequestPasswordReset
+            .handle(
+                &ctx.ctx(),
+                crate::auth::request_password_reset::Request {
+                    identifier: USER_01_EMAIL.into(),
+                },
+            )
+            .await
+            .unwrap_or_log();
+        // let the mail go out
+        tokio::task::yield_now().await;
+        let body = ctx.mailer.outbox.lock().last().unwrap().body.clone();
+        body.lines().last().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn confirm_password_reset_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let token = request_token(&ctx).await;
+            let (status, body) = confirm(&ctx, &token).await;
+            assert_eq!(status, http::StatusCode::NO_CONTENT, "{body:?}");
+
+            let shared_ctx = ctx.ctx();
+            let authenticate = |password: &'static str| {
+                crate::auth::authenticate::Authenticate.handle(
+                    &shared_ctx,
+                    crate::auth::authenticate::Request {
+                        identifier: USER_01_USERNAME.into(),
+                        password: password.into(),
+                        device_label: None,
+                        client: None,
+                    },
+                )
+            };
+            assert!(authenticate("password").await.is_err());
+            assert!(authenticate("hunter2Hunter!").await.is_ok());
+
+            let count =
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM sessions WHERE token = ANY($1)")
+                    .bind(&[USER_01_SESSION, USER_01_SESSION_2][..])
+                    .fetch_one(&ctx.ctx().db_pool)
+                    .await
+                    .unwrap_or_log();
+            assert_eq!(count, 0);
+
+            // single use
+            let (status, body) = confirm(&ctx, &token).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            check_json(
+                ("expected", &serde_json::json!({ "error": "invalidToken" })),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn confirm_password_reset_rejects_expired() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let now = time::OffsetDateTime::now_utc();
+            ctx.clock.set(now);
+            let token = request_token(&ctx).await;
+            ctx.clock.set(now + time::Duration::minutes(61));
+            let (status, body) = confirm(&ctx, &token).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            check_json(
+                ("expected", &serde_json::json!({ "error": "invalidToken" })),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+}
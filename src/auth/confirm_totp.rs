@@ -0,0 +1,208 @@
+use deps::*;
+
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ConfirmTotp;
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Request {
+    #[serde(skip)]
+    pub auth_token: Option<std::sync::Arc<str>>,
+    #[serde(skip)]
+    pub user_id: Option<uuid::Uuid>,
+    /// Current code of the authenticator.
+    #[schema(example = "287082")]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Response {
+    /// Single use codes that can stand in for a TOTP code at
+    /// `/authenticate/totp`. They're not retrievable later.
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("no pending enrollment")]
+    NotEnrolled,
+    #[error("invalid code")]
+    InvalidCode,
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for ConfirmTotp {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone().unwrap(),
+            resource: crate::auth::Resource::User {
+                id: request.user_id.unwrap(),
+            },
+            action: crate::auth::Action::Write,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let user_id = request.user_id.unwrap();
+        if accessing_user.user_id != user_id {
+            return Err(Error::AccessDenied);
+        }
+        let db_err = |err| Error::Internal {
+            message: format!("db error: {err}"),
+        };
+        let mut tx = ctx.db_pool.begin().await.map_err(db_err)?;
+        let pending = sqlx::query!(
+            r#"
+SELECT secret
+FROM totp_secrets
+WHERE user_id = $1 AND confirmed_at IS NULL
+FOR UPDATE
+            "#,
+            &user_id,
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(db_err)?;
+        let secret = if let Some(pending) = pending {
+            pending.secret
+        } else {
+            return Err(Error::NotEnrolled);
+        };
+        let step = if let Some(step) =
+            super::totp::verify(&secret, &request.code, ctx.clock.now(), None)
+        {
+            step
+        } else {
+            return Err(Error::InvalidCode);
+        };
+        sqlx::query!(
+            r#"
+UPDATE totp_secrets
+SET confirmed_at = CURRENT_TIMESTAMP, last_used_step = $2
+WHERE user_id = $1
+            "#,
+            &user_id,
+            &step,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(db_err)?;
+
+        let recovery_codes = (0..super::totp::RECOVERY_CODE_COUNT)
+            .map(|_| super::totp::generate_recovery_code())
+            .collect::<Vec<_>>();
+        sqlx::query!(
+            r#"
+DELETE FROM totp_recovery_codes
+WHERE user_id = $1
+            "#,
+            &user_id,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(db_err)?;
+        sqlx::query!(
+            r#"
+INSERT INTO totp_recovery_codes (code_hash, user_id)
+SELECT code_hash, $2
+FROM UNNEST($1::TEXT[]) as code_hash
+            "#,
+            &recovery_codes
+                .iter()
+                .map(|code| super::totp::hash_recovery_code(code))
+                .collect::<Vec<_>>()[..],
+            &user_id,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(db_err)?;
+        tx.commit().await.map_err(db_err)?;
+        Ok(Response { recovery_codes })
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            NotEnrolled | InvalidCode => Self::BAD_REQUEST,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for ConfirmTotp {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/users/:id/totp/confirm";
+
+    type HttpRequest = (BearerToken, Path<uuid::Uuid>, Json<Request>);
+
+    fn request(
+        (BearerToken(token), Path(user_id), Json(req)): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            auth_token: Some(token),
+            user_id: Some(user_id),
+            ..req
+        })
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for ConfirmTotp {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Enable two-factor authentication by confirming a code for the secret from `/users/{id}/totp`.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        [Response {
+            recovery_codes: vec!["mfrg-gzdf-mztw-q2lk".into(), "nbsw-y3dp-ebxw-k4tm".into()],
+        }]
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            ("No pending enrollment", Error::NotEnrolled),
+            ("Invalid code", Error::InvalidCode),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
@@ -0,0 +1,420 @@
+use deps::*;
+
+use crate::utils::*;
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use super::{Action, ApiKey};
+
+#[derive(Clone, Copy, Debug)]
+pub struct CreateApiKey;
+
+#[derive(Debug, Deserialize, Validate, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Request {
+    #[serde(skip)]
+    pub auth_token: Option<std::sync::Arc<str>>,
+    #[serde(skip)]
+    pub user_id: Option<uuid::Uuid>,
+    #[validate(length(min = 1, max = 64))]
+    #[schema(example = "CI deploy bot")]
+    pub name: String,
+    /// The actions the key may be used for.
+    #[validate(length(min = 1))]
+    pub scopes: Vec<Action>,
+    /// In seconds since unix epoch in UTC. Never expires if not set.
+    #[schema(example = 1234567)]
+    #[serde(default, with = "time::serde::timestamp::option")]
+    pub expires_at: Option<time::OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Response {
+    /// To be used as a bearer token. It's not retrievable later.
+    #[schema(example = "wak_3f9c21ab_Jx1bVq0cR4m2Zp8sYk3LwT7eHn5GdA9uFo6iCj0vXyE")]
+    pub key: String,
+    pub api_key: ApiKey,
+}
+
+#[derive(Debug, thiserror::Error, Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("invalid input: {issues:?}")]
+    InvalidInput {
+        #[from]
+        issues: ValidationErrors,
+    },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for CreateApiKey {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone().unwrap(),
+            resource: crate::auth::Resource::ApiKeys {
+                user_id: request.user_id.unwrap(),
+            },
+            action: crate::auth::Action::Write,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let user_id = request.user_id.unwrap();
+        // keys act as the user, only they get to create them
+        if accessing_user.user_id != user_id {
+            return Err(Error::AccessDenied);
+        }
+        validator::Validate::validate(&request).map_err(utils::ValidationErrors::from)?;
+        if let Some(expires_at) = request.expires_at {
+            if expires_at <= time::OffsetDateTime::now_utc() {
+                let mut issues = validator::ValidationErrors::new();
+                issues.add(
+                    "expiresAt",
+                    validator::ValidationError {
+                        code: "past".into(),
+                        message: Some("expiry must lie in the future".into()),
+                        params: [(
+                            std::borrow::Cow::from("value"),
+                            serde_json::json!(expires_at.unix_timestamp()),
+                        )]
+                        .into_iter()
+                        .collect(),
+                    },
+                );
+                return Err(utils::ValidationErrors::from(issues).into());
+            }
+        }
+        let mut scopes = request
+            .scopes
+            .iter()
+            .map(|scope| scope.as_str().to_string())
+            .collect::<Vec<_>>();
+        scopes.sort();
+        scopes.dedup();
+        let (key, prefix) = super::tokens::generate_api_key();
+        let api_key = sqlx::query_as!(
+            super::ApiKeyRow,
+            r#"
+INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, expires_at)
+VALUES ($1, $2, $3, $4, $5, $6)
+RETURNING id, user_id, name, prefix, scopes, expires_at, last_used_at, created_at, updated_at
+            "#,
+            &user_id,
+            &request.name,
+            &prefix,
+            super::tokens::hash_token(&key),
+            &scopes[..],
+            request.expires_at,
+        )
+        .fetch_one(&ctx.db_pool)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
+        Ok(Response {
+            key,
+            api_key: api_key.into(),
+        })
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            InvalidInput { .. } => Self::BAD_REQUEST,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for CreateApiKey {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/users/:id/api-keys";
+    const SUCCESS_CODE: StatusCode = StatusCode::CREATED;
+
+    type HttpRequest = (BearerToken, Path<uuid::Uuid>, Json<Request>);
+
+    fn request(
+        (BearerToken(token), Path(user_id), Json(req)): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            auth_token: Some(token),
+            user_id: Some(user_id),
+            ..req
+        })
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for CreateApiKey {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Create an API key for the user. API keys can't be used to manage API keys.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        [Response {
+            key: "wak_3f9c21ab_Jx1bVq0cR4m2Zp8sYk3LwT7eHn5GdA9uFo6iCj0vXyE".into(),
+            api_key: super::get_api_key::example(),
+        }]
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Invalid input",
+                Error::InvalidInput {
+                    issues: {
+                        let mut issues = validator::ValidationErrors::new();
+                        issues.add(
+                            "scopes",
+                            validator::ValidationError {
+                                code: std::borrow::Cow::from("length"),
+                                message: None,
+                                params: [(std::borrow::Cow::from("value"), serde_json::json!([]))]
+                                    .into_iter()
+                                    .collect(),
+                            },
+                        );
+                        issues.into()
+                    },
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    async fn request(
+        ctx: &TestContext,
+        router: axum::Router,
+        method: &str,
+        uri: String,
+        auth_token: &str,
+        body: serde_json::Value,
+    ) -> (http::StatusCode, serde_json::Value) {
+        let app = router.layer(axum::Extension(ctx.ctx()));
+        let resp = app
+            .oneshot(
+                http::Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(http::header::AUTHORIZATION, format!("Bearer {auth_token}"))
+                    .header(axum::http::header::CONTENT_TYPE, "application/json")
+                    .body(serde_json::to_vec(&body).unwrap().into())
+                    .unwrap_or_log(),
+            )
+            .await
+            .unwrap_or_log();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body())
+            .await
+            .unwrap_or_log();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    crate::integration_table_tests! {
+        create_api_key_works: {
+            uri: format!("/users/{USER_01_ID}/api-keys"),
+            method: "POST",
+            status: StatusCode::CREATED,
+            router: crate::auth::router(),
+            body: serde_json::json!({
+                "name": "CI deploy bot",
+                "scopes": ["read", "read"],
+            }),
+            check_json: serde_json::json!({
+                "apiKey": {
+                    "userId": USER_01_ID,
+                    "name": "CI deploy bot",
+                    "scopes": ["read"],
+                    "expiresAt": null,
+                    "lastUsedAt": null,
+                },
+            }),
+            auth_token: USER_01_SESSION.into(),
+            extra_assertions: &|EAArgs { response_json, .. }| {
+                Box::pin(async move {
+                    let body = response_json.unwrap();
+                    let key = body["key"].as_str().unwrap();
+                    assert!(key.starts_with(body["apiKey"]["prefix"].as_str().unwrap()));
+                })
+            },
+        },
+        create_api_key_rejects_other_users: {
+            uri: format!("/users/{USER_01_ID}/api-keys"),
+            method: "POST",
+            status: StatusCode::UNAUTHORIZED,
+            router: crate::auth::router(),
+            body: serde_json::json!({
+                "name": "CI deploy bot",
+                "scopes": ["read"],
+            }),
+            check_json: serde_json::json!({
+                "error": "accessDenied",
+            }),
+            auth_token: USER_04_SESSION.into(),
+        },
+        create_api_key_rejects_past_expiry: {
+            uri: format!("/users/{USER_01_ID}/api-keys"),
+            method: "POST",
+            status: StatusCode::BAD_REQUEST,
+            router: crate::auth::router(),
+            body: serde_json::json!({
+                "name": "CI deploy bot",
+                "scopes": ["read"],
+                "expiresAt": 1234567,
+            }),
+            check_json: serde_json::json!({
+                "error": "invalidInput",
+            }),
+            auth_token: USER_01_SESSION.into(),
+        },
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_limited_to_their_scopes() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (status, body) = request(
+                &ctx,
+                crate::auth::router(),
+                "POST",
+                format!("/users/{USER_01_ID}/api-keys"),
+                USER_01_SESSION,
+                serde_json::json!({ "name": "CI", "scopes": ["read", "write"] }),
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED, "{body:?}");
+            let key = body["key"].as_str().unwrap().to_string();
+
+            let (status, body) = request(
+                &ctx,
+                crate::user::router(),
+                "GET",
+                format!("/users/{USER_01_ID}"),
+                &key,
+                serde_json::json!({}),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            assert_eq!(body["id"], USER_01_ID.to_string());
+
+            let (status, _) = request(
+                &ctx,
+                crate::user::router(),
+                "DELETE",
+                format!("/users/{USER_01_ID}"),
+                &key,
+                serde_json::json!({}),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+            // keys can't mint keys, not even with the write scope
+            let (status, _) = request(
+                &ctx,
+                crate::auth::router(),
+                "POST",
+                format!("/users/{USER_01_ID}/api-keys"),
+                &key,
+                serde_json::json!({ "name": "CI 2", "scopes": ["delete"] }),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+            let (status, body) = request(
+                &ctx,
+                crate::auth::router(),
+                "GET",
+                format!("/users/{USER_01_ID}/api-keys"),
+                USER_01_SESSION,
+                serde_json::json!({}),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            assert!(body["items"][0]["lastUsedAt"].is_number(), "{body:?}");
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn api_keys_expire() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (status, body) = request(
+                &ctx,
+                crate::auth::router(),
+                "POST",
+                format!("/users/{USER_01_ID}/api-keys"),
+                USER_01_SESSION,
+                serde_json::json!({
+                    "name": "CI",
+                    "scopes": ["read"],
+                    "expiresAt": time::OffsetDateTime::now_utc().unix_timestamp() + 60,
+                }),
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED, "{body:?}");
+            let key = body["key"].as_str().unwrap().to_string();
+            sqlx::query("UPDATE api_keys SET expires_at = CURRENT_TIMESTAMP - INTERVAL '1 minute'")
+                .execute(&ctx.ctx().db_pool)
+                .await
+                .unwrap_or_log();
+            let (status, _) = request(
+                &ctx,
+                crate::user::router(),
+                "GET",
+                format!("/users/{USER_01_ID}"),
+                &key,
+                serde_json::json!({}),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        ctx.close().await;
+    }
+}
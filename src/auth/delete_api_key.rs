@@ -0,0 +1,187 @@
+use deps::*;
+
+use axum::extract::*;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DeleteApiKey;
+
+#[derive(Debug)]
+pub struct Request {
+    pub auth_token: std::sync::Arc<str>,
+    pub user_id: uuid::Uuid,
+    pub id: uuid::Uuid,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+pub type Response = NoContent;
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for DeleteApiKey {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone(),
+            resource: crate::auth::Resource::ApiKeys {
+                user_id: request.user_id,
+            },
+            action: crate::auth::Action::Delete,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        if accessing_user.user_id != request.user_id {
+            return Err(Error::AccessDenied);
+        }
+        let result = sqlx::query!(
+            r#"
+DELETE FROM api_keys
+WHERE id = $1 AND user_id = $2
+            "#,
+            &request.id,
+            &request.user_id,
+        )
+        .execute(&ctx.db_pool)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
+        tracing::trace!(rows_affected = result.rows_affected());
+        Ok(NoContent)
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for DeleteApiKey {
+    const METHOD: Method = Method::Delete;
+    const PATH: &'static str = "/users/:id/api-keys/:keyId";
+    const SUCCESS_CODE: StatusCode = StatusCode::NO_CONTENT;
+
+    type HttpRequest = (BearerToken, Path<(uuid::Uuid, uuid::Uuid)>);
+
+    fn request(
+        (BearerToken(auth_token), Path((user_id, id))): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            auth_token,
+            user_id,
+            id,
+        })
+    }
+
+    fn response(_: Self::Response) -> axum::response::Response {
+        Default::default()
+    }
+}
+
+impl DocumentedEndpoint for DeleteApiKey {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str = "Revoke one of the user's API keys.";
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+    use crate::Endpoint;
+
+    #[tokio::test]
+    async fn delete_api_key_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let created = crate::AuthenticatedEndpoint::handle(
+                &crate::auth::create_api_key::CreateApiKey,
+                &ctx.ctx(),
+                accessing(USER_01_ID),
+                crate::auth::create_api_key::Request {
+                    auth_token: Some(USER_01_SESSION.into()),
+                    user_id: Some(USER_01_ID),
+                    name: "CI".into(),
+                    scopes: vec![crate::auth::Action::Read],
+                    expires_at: None,
+                },
+            )
+            .await
+            .unwrap_or_log();
+            let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+            let resp = app
+                .oneshot(
+                    http::Request::builder()
+                        .method("DELETE")
+                        .uri(format!(
+                            "/users/{USER_01_ID}/api-keys/{}",
+                            created.api_key.id
+                        ))
+                        .header(
+                            http::header::AUTHORIZATION,
+                            format!("Bearer {USER_01_SESSION}"),
+                        )
+                        .body(Default::default())
+                        .unwrap_or_log(),
+                )
+                .await
+                .unwrap_or_log();
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            let err = crate::auth::authorize::Authorize
+                .handle(
+                    &ctx.ctx(),
+                    crate::auth::authorize::Request {
+                        auth_token: created.key.into(),
+                        resource: crate::auth::Resource::User { id: USER_01_ID },
+                        action: crate::auth::Action::Read,
+                    },
+                )
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(err, crate::auth::authorize::Error::InvalidToken),
+                "{err:?}"
+            );
+        }
+        ctx.close().await;
+    }
+}
@@ -0,0 +1,213 @@
+use deps::*;
+
+use crate::*;
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug)]
+pub struct EnrollTotp;
+
+#[derive(Debug)]
+pub struct Request {
+    pub auth_token: std::sync::Arc<str>,
+    pub user_id: uuid::Uuid,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Response {
+    /// Base32 encoded, for manual entry into authenticator apps.
+    pub secret: String,
+    /// `otpauth://` URI to be presented as a QR code.
+    pub provisioning_uri: String,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("two-factor authentication already enabled")]
+    AlreadyEnabled,
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for EnrollTotp {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone(),
+            resource: crate::auth::Resource::User {
+                id: request.user_id,
+            },
+            action: crate::auth::Action::Write,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        // the secret is as good as a password, only the user gets to see it
+        if accessing_user.user_id != request.user_id {
+            return Err(Error::AccessDenied);
+        }
+        let secret = super::totp::generate_secret();
+        // re-enrolling replaces the secret until it's confirmed
+        let email = sqlx::query_scalar!(
+            r#"
+WITH enrolled AS (
+    INSERT INTO totp_secrets (user_id, secret)
+    VALUES ($1, $2)
+    ON CONFLICT (user_id) DO UPDATE
+    SET secret = EXCLUDED.secret
+    WHERE totp_secrets.confirmed_at IS NULL
+    RETURNING user_id
+)
+SELECT users.email::TEXT as "email!"
+FROM enrolled
+JOIN users ON users.id = enrolled.user_id
+            "#,
+            &request.user_id,
+            &secret,
+        )
+        .fetch_optional(&ctx.db_pool)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
+        let email = if let Some(email) = email {
+            email
+        } else {
+            return Err(Error::AlreadyEnabled);
+        };
+        Ok(Response {
+            provisioning_uri: super::totp::provisioning_uri(
+                &ctx.config.totp_issuer,
+                &email,
+                &secret,
+            ),
+            secret,
+        })
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            AlreadyEnabled => Self::BAD_REQUEST,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for EnrollTotp {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/users/:id/totp";
+
+    type HttpRequest = (BearerToken, Path<uuid::Uuid>);
+
+    fn request(
+        (BearerToken(token), Path(user_id)): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            auth_token: token,
+            user_id,
+        })
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for EnrollTotp {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Generate a TOTP secret for the user. Two-factor authentication is enabled once a code for it is confirmed at `/users/{id}/totp/confirm`.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        [Response {
+            secret: "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP".into(),
+            provisioning_uri: super::totp::provisioning_uri(
+                "web_api",
+                "hex.queen@teen.dj",
+                "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP",
+            ),
+        }]
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Two-factor authentication already enabled",
+                Error::AlreadyEnabled,
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    crate::integration_table_tests! {
+        enroll_totp_works: {
+            uri: format!("/users/{USER_01_ID}/totp"),
+            method: "POST",
+            status: StatusCode::OK,
+            router: crate::auth::router(),
+            auth_token: USER_01_SESSION.into(),
+            extra_assertions: &|EAArgs { response_json, .. }| {
+                Box::pin(async move {
+                    let body = response_json.unwrap();
+                    let secret = body["secret"].as_str().unwrap();
+                    assert!(crate::auth::totp::code_at_step(secret, 0).is_some());
+                    assert!(body["provisioningUri"]
+                        .as_str()
+                        .unwrap()
+                        .starts_with("otpauth://totp/"));
+                })
+            },
+        },
+        enroll_totp_rejects_other_users: {
+            uri: format!("/users/{USER_01_ID}/totp"),
+            method: "POST",
+            status: StatusCode::UNAUTHORIZED,
+            router: crate::auth::router(),
+            check_json: serde_json::json!({
+                "error": "accessDenied",
+            }),
+            auth_token: USER_04_SESSION.into(),
+        },
+    }
+}
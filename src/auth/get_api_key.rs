@@ -0,0 +1,190 @@
+use deps::*;
+
+use axum::extract::*;
+
+use crate::*;
+
+use super::ApiKey;
+
+#[derive(Clone, Copy, Debug)]
+pub struct GetApiKey;
+
+#[derive(Debug)]
+pub struct Request {
+    pub auth_token: std::sync::Arc<str>,
+    pub user_id: uuid::Uuid,
+    pub id: uuid::Uuid,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("not found at id: {id:?}")]
+    NotFound { id: uuid::Uuid },
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+pub type Response = ApiKey;
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for GetApiKey {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone(),
+            resource: crate::auth::Resource::ApiKeys {
+                user_id: request.user_id,
+            },
+            action: crate::auth::Action::Read,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        if accessing_user.user_id != request.user_id {
+            return Err(Error::AccessDenied);
+        }
+        sqlx::query_as!(
+            super::ApiKeyRow,
+            r#"
+SELECT id, user_id, name, prefix, scopes, expires_at, last_used_at, created_at, updated_at
+FROM api_keys
+WHERE id = $1 AND user_id = $2
+            "#,
+            &request.id,
+            &request.user_id,
+        )
+        .fetch_one(&ctx.db_pool)
+        .await
+        .map(ApiKey::from)
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => Error::NotFound { id: request.id },
+            _ => Error::Internal {
+                message: format!("db error: {err}"),
+            },
+        })
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            NotFound { .. } => Self::NOT_FOUND,
+            AccessDenied => Self::UNAUTHORIZED,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for GetApiKey {
+    const METHOD: Method = Method::Get;
+    const PATH: &'static str = "/users/:id/api-keys/:keyId";
+
+    type HttpRequest = (BearerToken, Path<(uuid::Uuid, uuid::Uuid)>);
+
+    fn request(
+        (BearerToken(auth_token), Path((user_id, id))): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            auth_token,
+            user_id,
+            id,
+        })
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+pub(crate) fn example() -> ApiKey {
+    ApiKey {
+        id: Default::default(),
+        user_id: crate::user::testing::USER_01_ID,
+        name: "CI deploy bot".into(),
+        prefix: "wak_3f9c21ab".into(),
+        scopes: vec![super::Action::Read, super::Action::Write],
+        expires_at: None,
+        last_used_at: Some(time::OffsetDateTime::now_utc()),
+        created_at: time::OffsetDateTime::now_utc(),
+        updated_at: time::OffsetDateTime::now_utc(),
+    }
+}
+
+impl DocumentedEndpoint for GetApiKey {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str = "Get one of the user's API keys.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        [example()]
+            .into_iter()
+            .map(serde_json::to_value)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Not found",
+                Error::NotFound {
+                    id: Default::default(),
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    crate::integration_table_tests! {
+        get_api_key_not_found: {
+            uri: format!("/users/{USER_01_ID}/api-keys/{USER_01_ID}"),
+            method: "GET",
+            status: StatusCode::NOT_FOUND,
+            router: crate::auth::router(),
+            check_json: serde_json::json!({
+                "error": "notFound",
+                "id": USER_01_ID,
+            }),
+            auth_token: USER_01_SESSION.into(),
+        },
+        get_api_key_rejects_other_users: {
+            uri: format!("/users/{USER_01_ID}/api-keys/{USER_01_ID}"),
+            method: "GET",
+            status: StatusCode::UNAUTHORIZED,
+            router: crate::auth::router(),
+            check_json: serde_json::json!({
+                "error": "accessDenied",
+            }),
+            auth_token: USER_04_SESSION.into(),
+        },
+    }
+}
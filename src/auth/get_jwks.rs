@@ -0,0 +1,102 @@
+use deps::*;
+
+use crate::*;
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug)]
+pub struct GetJwks;
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Response {
+    /// Keys JWT access tokens may be signed with. Empty unless JWT access
+    /// tokens are enabled with an asymmetric key.
+    pub keys: Vec<super::jwt::Jwk>,
+}
+
+#[derive(Debug, Serialize, thiserror::Error, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase", tag = "error")]
+pub enum Error {
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+#[async_trait::async_trait]
+impl Endpoint for GetJwks {
+    type Request = ();
+    type Response = Response;
+    type Error = Error;
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        _request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        Ok(Response {
+            keys: ctx
+                .config
+                .jwt
+                .as_ref()
+                .map(|jwt| jwt.jwks())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl HttpEndpoint for GetJwks {
+    const METHOD: Method = Method::Get;
+    const PATH: &'static str = "/.well-known/jwks.json";
+
+    type HttpRequest = ();
+
+    fn request(_: Self::HttpRequest) -> Result<Self::Request, Self::Error> {
+        Ok(())
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for GetJwks {
+    const TAG: &'static Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Get the public keys for verifying JWT access tokens, e.g. in downstream services.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        [Response {
+            keys: vec![super::jwt::Jwk {
+                kty: "OKP".into(),
+                crv: "Ed25519".into(),
+                alg: "EdDSA".into(),
+                r#use: "sig".into(),
+                kid: "2022-10".into(),
+                x: "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo".into(),
+            }],
+        }]
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Self::Error>> {
+        vec![(
+            "Internal server error",
+            Error::Internal {
+                message: "internal server error".to_string(),
+            },
+        )]
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
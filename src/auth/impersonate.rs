@@ -0,0 +1,492 @@
+use deps::*;
+
+use crate::*;
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Impersonate;
+
+#[derive(Debug)]
+pub struct Request {
+    pub auth_token: std::sync::Arc<str>,
+    pub user_id: uuid::Uuid,
+    pub client: Option<ClientInfo>,
+}
+
+/// A short-lived session that can't be refreshed.
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Response {
+    /// The impersonated user.
+    pub user_id: uuid::Uuid,
+    pub impersonator_id: uuid::Uuid,
+    pub token: String,
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
+    pub expires_at: time::OffsetDateTime,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("not found at id: {id:?}")]
+    NotFound { id: uuid::Uuid },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for Impersonate {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    // no impersonating from behind someone else's identity
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone(),
+            resource: crate::auth::Resource::Impersonation {
+                user_id: request.user_id,
+            },
+            action: crate::auth::Action::Write,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let user_id = request.user_id;
+        if accessing_user.user_id == user_id {
+            return Err(Error::AccessDenied);
+        }
+        let db_err = |err| Error::Internal {
+            message: format!("db error: {err}"),
+        };
+        let exists = sqlx::query_scalar!(
+            r#"
+SELECT EXISTS (SELECT 1 FROM users WHERE id = $1) as "exists!"
+            "#,
+            &user_id,
+        )
+        .fetch_one(&ctx.db_pool)
+        .await
+        .map_err(db_err)?;
+        if !exists {
+            return Err(Error::NotFound { id: user_id });
+        }
+        // impersonating shouldn't grant more than the admin already has
+        let impersonator_roles = super::authorize::user_roles(ctx, accessing_user.user_id)
+            .await
+            .map_err(db_err)?;
+        let roles = super::authorize::user_roles(ctx, user_id)
+            .await
+            .map_err(db_err)?;
+        if roles.iter().any(|role| !impersonator_roles.contains(role)) {
+            tracing::info!(
+                target: "audit",
+                impersonator_id = %accessing_user.user_id,
+                user_id = %user_id,
+                ?roles,
+                "impersonation denied"
+            );
+            return Err(Error::AccessDenied);
+        }
+
+        let now = ctx.clock.now();
+        let expires_at = now.saturating_add(ctx.config.impersonation_lifespan);
+        let token = super::authenticate::access_token(
+            ctx,
+            user_id,
+            Some(accessing_user.user_id),
+            now,
+            expires_at,
+        )
+        .await
+        .map_err(db_err)?;
+        let client = request.client.unwrap_or_default();
+        sqlx::query!(
+            r#"
+INSERT INTO sessions (token, user_id, expires_at, impersonator_id, user_agent, ip_addr)
+VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            &token,
+            &user_id,
+            &expires_at,
+            &accessing_user.user_id,
+            client.user_agent.as_ref(),
+            client.ip_addr.map(|addr| addr.to_string()),
+        )
+        .execute(&ctx.db_pool)
+        .await
+        .map_err(db_err)?;
+        tracing::info!(
+            target: "audit",
+            impersonator_id = %accessing_user.user_id,
+            user_id = %user_id,
+            %expires_at,
+            "impersonation started"
+        );
+        Ok(Response {
+            user_id,
+            impersonator_id: accessing_user.user_id,
+            token,
+            expires_at,
+        })
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            NotFound { .. } => Self::NOT_FOUND,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for Impersonate {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/users/:id/impersonate";
+
+    type HttpRequest = (BearerToken, ClientInfo, Path<uuid::Uuid>);
+
+    fn request(
+        (BearerToken(token), client, Path(user_id)): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            auth_token: token,
+            user_id,
+            client: Some(client),
+        })
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for Impersonate {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Open a session as the user. Changing their credentials or deleting the account isn't allowed with it.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        use crate::user::testing::*;
+        [Response {
+            user_id: USER_01_ID,
+            impersonator_id: USER_04_ID,
+            token: "8f3c5e2a-6b1d-4f7e-9a0c-3d2b1e4f5a6c".into(),
+            expires_at: time::OffsetDateTime::now_utc(),
+        }]
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Not found",
+                Error::NotFound {
+                    id: Default::default(),
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+    use crate::Endpoint;
+
+    use axum::http;
+    use tower::ServiceExt;
+
+    async fn request(
+        ctx: &TestContext,
+        router: axum::Router,
+        method: &str,
+        uri: String,
+        auth_token: &str,
+        body: Option<serde_json::Value>,
+    ) -> (http::StatusCode, serde_json::Value) {
+        let resp = router
+            .layer(axum::Extension(ctx.ctx()))
+            .oneshot(
+                http::Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(http::header::AUTHORIZATION, format!("Bearer {auth_token}"))
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(
+                        body.map(|body| serde_json::to_vec(&body).unwrap().into())
+                            .unwrap_or_default(),
+                    )
+                    .unwrap_or_log(),
+            )
+            .await
+            .unwrap_or_log();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body())
+            .await
+            .unwrap_or_log();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    async fn impersonate(
+        ctx: &TestContext,
+        auth_token: &str,
+        user_id: uuid::Uuid,
+    ) -> (http::StatusCode, serde_json::Value) {
+        request(
+            ctx,
+            crate::auth::router(),
+            "POST",
+            format!("/users/{user_id}/impersonate"),
+            auth_token,
+            None,
+        )
+        .await
+    }
+
+    async fn impersonation_works(ctx: TestContext) {
+        {
+            let (status, body) = impersonate(&ctx, USER_04_SESSION, USER_01_ID).await;
+            assert_eq!(status, http::StatusCode::OK, "{body:?}");
+            assert_eq!(body["userId"], USER_01_ID.to_string());
+            assert_eq!(body["impersonatorId"], USER_04_ID.to_string());
+            assert!(body.get("refreshToken").is_none());
+            let token = body["token"].as_str().unwrap();
+
+            let authorized = crate::auth::authorize::Authorize
+                .handle(
+                    &ctx.ctx(),
+                    crate::auth::authorize::Request {
+                        auth_token: token.into(),
+                        resource: crate::auth::Resource::User { id: USER_01_ID },
+                        action: crate::auth::Action::Read,
+                    },
+                )
+                .await
+                .unwrap_or_log();
+            assert_eq!(authorized.user_id, USER_01_ID);
+            assert_eq!(authorized.impersonator_id, Some(USER_04_ID));
+            // the target's roles apply, not the admin's
+            assert!(authorized.roles.is_empty());
+
+            let (status, _) = request(
+                &ctx,
+                crate::user::router(),
+                "GET",
+                format!("/users/{USER_02_ID}"),
+                token,
+                None,
+            )
+            .await;
+            assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+            let (status, body) = request(
+                &ctx,
+                crate::user::router(),
+                "GET",
+                format!("/users/{USER_01_ID}"),
+                token,
+                None,
+            )
+            .await;
+            assert_eq!(status, http::StatusCode::OK, "{body:?}");
+
+            let impersonator_id = sqlx::query_scalar::<_, Option<uuid::Uuid>>(
+                "SELECT impersonator_id FROM sessions WHERE token = $1",
+            )
+            .bind(token)
+            .fetch_one(&ctx.ctx().db_pool)
+            .await
+            .unwrap_or_log();
+            assert_eq!(impersonator_id, Some(USER_04_ID));
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn impersonate_works() {
+        impersonation_works(TestContext::new(crate::function!()).await).await;
+    }
+
+    #[tokio::test]
+    async fn impersonate_works_with_jwts() {
+        impersonation_works(
+            TestContext::with_config(crate::function!(), |config| {
+                config.jwt = Some(crate::auth::jwt::JwtConfig {
+                    issuer: "web_api".into(),
+                    keys: vec![crate::auth::jwt::Key::generate_ed25519("ed")],
+                    revocation_sync_interval: time::Duration::seconds(5),
+                })
+            })
+            .await,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn impersonate_sessions_expire() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let now = time::OffsetDateTime::now_utc();
+            ctx.clock.set(now);
+            let (status, body) = impersonate(&ctx, USER_04_SESSION, USER_01_ID).await;
+            assert_eq!(status, http::StatusCode::OK, "{body:?}");
+            let token = body["token"].as_str().unwrap().to_string();
+
+            ctx.clock.set(now + time::Duration::minutes(16));
+            let (status, _) = request(
+                &ctx,
+                crate::user::router(),
+                "GET",
+                format!("/users/{USER_01_ID}"),
+                &token,
+                None,
+            )
+            .await;
+            assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn impersonate_forbids_sensitive_actions() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (_, body) = impersonate(&ctx, USER_04_SESSION, USER_01_ID).await;
+            let token = body["token"].as_str().unwrap();
+            for (router, method, uri, body) in [
+                (
+                    crate::user::router(),
+                    "POST",
+                    format!("/users/{USER_01_ID}/password"),
+                    Some(serde_json::json!({
+                        "currentPassword": "password",
+                        "newPassword": "Tr0ub4dor&3",
+                    })),
+                ),
+                (
+                    crate::user::router(),
+                    "PATCH",
+                    format!("/users/{USER_01_ID}"),
+                    Some(serde_json::json!({ "email": "takeover@example.com" })),
+                ),
+                (
+                    crate::user::router(),
+                    "DELETE",
+                    format!("/users/{USER_01_ID}"),
+                    None,
+                ),
+                (
+                    crate::auth::router(),
+                    "POST",
+                    format!("/users/{USER_02_ID}/impersonate"),
+                    None,
+                ),
+                (
+                    crate::auth::router(),
+                    "GET",
+                    format!("/users/{USER_01_ID}/sessions"),
+                    None,
+                ),
+            ] {
+                let (status, body) = request(&ctx, router, method, uri.clone(), token, body).await;
+                assert_eq!(status, http::StatusCode::UNAUTHORIZED, "{method} {uri}");
+                check_json(
+                    ("expected", &serde_json::json!({ "error": "accessDenied" })),
+                    ("response", &body),
+                );
+            }
+            let (status, _) = request(
+                &ctx,
+                crate::user::router(),
+                "GET",
+                format!("/users/{USER_01_ID}"),
+                USER_01_SESSION,
+                None,
+            )
+            .await;
+            assert_eq!(status, http::StatusCode::OK);
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn impersonate_rejects_escalation() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            // not an admin
+            let (status, _) = impersonate(&ctx, USER_01_SESSION, USER_02_ID).await;
+            assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+
+            // an admin, but less of one than the target
+            sqlx::query("INSERT INTO user_roles (user_id, role) VALUES ($1, 'admin')")
+                .bind(USER_02_ID)
+                .execute(&ctx.ctx().db_pool)
+                .await
+                .unwrap_or_log();
+            let tokens = crate::auth::authenticate::Authenticate
+                .handle(
+                    &ctx.ctx(),
+                    crate::auth::authenticate::Request {
+                        identifier: USER_02_USERNAME.into(),
+                        password: "password".into(),
+                        device_label: None,
+                        client: None,
+                    },
+                )
+                .await
+                .unwrap_or_log()
+                .into_tokens()
+                .unwrap();
+            let (status, _) = impersonate(&ctx, &tokens.token, USER_04_ID).await;
+            assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+            let (status, body) = impersonate(&ctx, &tokens.token, USER_01_ID).await;
+            assert_eq!(status, http::StatusCode::OK, "{body:?}");
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn impersonate_fails_on_unknown_users() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (status, body) = impersonate(&ctx, USER_04_SESSION, uuid::Uuid::new_v4()).await;
+            assert_eq!(status, http::StatusCode::NOT_FOUND);
+            check_json(
+                ("expected", &serde_json::json!({ "error": "notFound" })),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+}
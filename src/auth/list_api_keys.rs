@@ -0,0 +1,140 @@
+use deps::*;
+
+use axum::extract::*;
+
+use crate::*;
+
+use super::ApiKey;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ListApiKeys;
+
+#[derive(Debug)]
+pub struct Request {
+    pub auth_token: std::sync::Arc<str>,
+    pub user_id: uuid::Uuid,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Response {
+    /// Newest first.
+    pub items: Vec<ApiKey>,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for ListApiKeys {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone(),
+            resource: crate::auth::Resource::ApiKeys {
+                user_id: request.user_id,
+            },
+            action: crate::auth::Action::Read,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        if accessing_user.user_id != request.user_id {
+            return Err(Error::AccessDenied);
+        }
+        // users only hold a handful of keys, no need for paging
+        let items = sqlx::query_as!(
+            super::ApiKeyRow,
+            r#"
+SELECT id, user_id, name, prefix, scopes, expires_at, last_used_at, created_at, updated_at
+FROM api_keys
+WHERE user_id = $1
+ORDER BY created_at DESC, id
+            "#,
+            &request.user_id,
+        )
+        .fetch_all(&ctx.db_pool)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?
+        .into_iter()
+        .map(ApiKey::from)
+        .collect();
+        Ok(Response { items })
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for ListApiKeys {
+    const METHOD: Method = Method::Get;
+    const PATH: &'static str = "/users/:id/api-keys";
+
+    type HttpRequest = (BearerToken, Path<uuid::Uuid>);
+
+    fn request(
+        (BearerToken(auth_token), Path(user_id)): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            auth_token,
+            user_id,
+        })
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for ListApiKeys {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str = "List the API keys of the user.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        [Response {
+            items: vec![super::get_api_key::example()],
+        }]
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
@@ -0,0 +1,544 @@
+use deps::*;
+
+use axum::extract::*;
+
+use crate::utils::*;
+use crate::*;
+
+use super::Session;
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub enum SessionSortingField {
+    CreatedAt,
+    LastSeenAt,
+    ExpiresAt,
+}
+
+impl SortingField for SessionSortingField {
+    const TIE_BREAKER: (&'static str, &'static str) = ("id", "UUID");
+
+    #[inline]
+    fn sql_field_name(&self) -> String {
+        match self {
+            Self::CreatedAt => "created_at",
+            Self::LastSeenAt => "last_seen_at",
+            Self::ExpiresAt => "expires_at",
+        }
+        .into()
+    }
+
+    #[inline]
+    fn sql_field_type(&self) -> &'static str {
+        match self {
+            Self::CreatedAt | Self::LastSeenAt | Self::ExpiresAt => "TIMESTAMPTZ",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ListSessions;
+
+crate::alias_and_ref!(
+    ListRequest<SessionSortingField>,
+    ListSessionsRequest,
+    RequestBody,
+    de
+);
+
+#[derive(Debug)]
+pub struct Request {
+    pub user_id: uuid::Uuid,
+    pub list: ListSessionsRequest,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("invalid input: {issues:?}")]
+    InvalidInput {
+        #[from]
+        issues: ValidationErrors,
+    },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+crate::alias_and_ref!(ListResponse<Session>, ListSessionsResponse, Response, ser);
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for ListSessions {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.list.auth_token.clone().unwrap(),
+            resource: crate::auth::Resource::Sessions {
+                user_id: request.user_id,
+            },
+            action: crate::auth::Action::Read,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        _accessing_user: crate::auth::authorize::Response,
+        Request {
+            user_id,
+            list: request,
+        }: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        validator::Validate::validate(&request).map_err(utils::ValidationErrors::from)?;
+        let keyset = Keyset::from_request(
+            request,
+            SessionSortingField::LastSeenAt,
+            SortingOrder::Descending,
+            &ctx.config.cursors,
+            ctx.clock.now(),
+        )?;
+        let now = ctx.clock.now();
+        // expired sessions linger until the sweeper gets to them
+        let push_from = |query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>| {
+            query
+                .push(" FROM sessions WHERE user_id = ")
+                .push_bind(user_id)
+                .push(" AND expires_at > ")
+                .push_bind(now);
+            if let Some(filter) = &keyset.filter {
+                // the filter matches literally, wildcards and all
+                let filter = filter
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                query
+                    .push(" AND (user_agent ILIKE '%' || ")
+                    .push_bind(filter.clone())
+                    .push(" || '%' ESCAPE '\\' OR device_label ILIKE '%' || ")
+                    .push_bind(filter)
+                    .push(" || '%' ESCAPE '\\')");
+            }
+        };
+        let mut query = sqlx::QueryBuilder::new("SELECT *");
+        keyset.push_key_columns(&mut query);
+        push_from(&mut query);
+        query.push(" AND ");
+        keyset.push_condition(&mut query);
+        keyset.push_order_and_limit(&mut query);
+        let mut page = query
+            .build()
+            .fetch_all(&ctx.db_pool)
+            .await
+            .and_then(|rows| {
+                keyset.paginate(rows, &ctx.config.cursors, ctx.clock.now(), |row| {
+                    use sqlx::Row;
+                    Ok(Session {
+                        id: row.try_get("id")?,
+                        user_id: row.try_get("user_id")?,
+                        expires_at: row.try_get("expires_at")?,
+                        created_at: row.try_get("created_at")?,
+                        updated_at: row.try_get("updated_at")?,
+                        user_agent: row.try_get("user_agent")?,
+                        ip_addr: row.try_get("ip_addr")?,
+                        device_label: row.try_get("device_label")?,
+                        last_seen_at: row.try_get("last_seen_at")?,
+                        impersonator_id: row.try_get("impersonator_id")?,
+                    })
+                })
+            })
+            .map_err(|err| Error::Internal {
+                message: format!("db err: {err}"),
+            })?;
+        page.total =
+            keyset
+                .total(&ctx.db_pool, push_from)
+                .await
+                .map_err(|err| Error::Internal {
+                    message: format!("db err: {err}"),
+                })?;
+        Ok(page.into())
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            InvalidInput { .. } => Self::BAD_REQUEST,
+            AccessDenied => Self::UNAUTHORIZED,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for ListSessions {
+    const METHOD: Method = Method::Get;
+    const PATH: &'static str = "/users/:id/sessions";
+
+    /// The JSON body, when there's one, takes precedence over the query string.
+    type HttpRequest = (
+        BearerToken,
+        Path<uuid::Uuid>,
+        Query<RequestBody>,
+        Option<Json<RequestBody>>,
+    );
+
+    fn request(
+        (BearerToken(token), Path(user_id), Query(RequestBody(query)), body): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        let request = match body {
+            Some(Json(RequestBody(body))) => body,
+            None => query,
+        };
+        Ok(Request {
+            user_id,
+            list: ListSessionsRequest {
+                auth_token: Some(token),
+                ..request
+            },
+        })
+    }
+
+    fn response(Response(resp): Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for ListSessions {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str = "List the sessions of the user.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        use crate::user::testing::*;
+        [ListSessionsResponse {
+            next_cursor: None,
+            prev_cursor: None,
+            has_more: false,
+            limit: DEFAULT_LIST_LIMIT,
+            total: None,
+            items: vec![Session {
+                id: uuid::Uuid::new_v4(),
+                user_id: USER_01_ID,
+                expires_at: time::OffsetDateTime::now_utc(),
+                created_at: time::OffsetDateTime::now_utc(),
+                updated_at: time::OffsetDateTime::now_utc(),
+                user_agent: Some("curl/7.85.0".into()),
+                ip_addr: Some("127.0.0.1".into()),
+                device_label: Some("Work laptop".into()),
+                last_seen_at: time::OffsetDateTime::now_utc(),
+                impersonator_id: None,
+            }],
+        }]
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Invalid input",
+                Error::InvalidInput {
+                    issues: {
+                        let mut issues = validator::ValidationErrors::new();
+                        issues.add(
+                            "limit",
+                            validator::ValidationError {
+                                code: std::borrow::Cow::from("range"),
+                                message: None,
+                                params: [(std::borrow::Cow::from("value"), serde_json::json!(0))]
+                                    .into_iter()
+                                    .collect(),
+                            },
+                        );
+                        issues.into()
+                    },
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    macro_rules! list_sessions_integ {
+        ($(
+            $name:ident: {
+                uri: $uri:expr,
+                auth_token: $auth_token:expr,
+                status: $status:expr,
+                body: $json_body:expr,
+                $(check_json: $check_json:expr,)?
+                $(extra_assertions: $extra_fn:expr,)?
+            },
+        )*) => {
+            mod integ {
+                use super::*;
+                crate::integration_table_tests! {
+                    $(
+                        $name: {
+                            uri: $uri,
+                            method: "GET",
+                            status: $status,
+                            router: crate::auth::router(),
+                            body: $json_body,
+                            $(check_json: $check_json,)?
+                            auth_token: $auth_token,
+                            $(extra_assertions: $extra_fn,)?
+                        },
+                    )*
+                }
+            }
+        };
+    }
+
+    list_sessions_integ! {
+        works: {
+            uri: format!("/users/{USER_01_ID}/sessions"),
+            auth_token: USER_01_SESSION.into(),
+            status: StatusCode::OK,
+            body: serde_json::json!({
+                "limit": 1,
+                "sortingField": "createdAt",
+                "sortingOrder": "ascending",
+            }),
+            extra_assertions: &|EAArgs { ctx, response_json, .. }| {
+                Box::pin(async move {
+                    let resp_body_json = response_json.unwrap();
+                    assert_eq!(resp_body_json["items"].as_array().unwrap().len(), 1);
+                    assert_eq!(resp_body_json["items"][0]["userId"], USER_01_ID.to_string());
+                    assert!(resp_body_json["items"][0]["lastSeenAt"].is_number());
+                    assert!(resp_body_json["nextCursor"].as_str().is_some());
+                    assert!(resp_body_json["prevCursor"].is_null());
+                    let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+                    let resp = app
+                        .oneshot(
+                            http::Request::builder()
+                                .method("GET")
+                                .uri(format!("/users/{USER_01_ID}/sessions"))
+                                .header(
+                                    http::header::AUTHORIZATION,
+                                    format!("Bearer {USER_01_SESSION}"),
+                                )
+                                .header(axum::http::header::CONTENT_TYPE, "application/json")
+                                .body(
+                                    serde_json::to_vec(
+                                        &serde_json::json!({
+                                            "afterCursor": resp_body_json["nextCursor"]
+                                                                .as_str()
+                                                                .unwrap()
+                                        })
+                                    ).unwrap().into()
+                                )
+                                .unwrap_or_log(),
+                        )
+                        .await
+                        .unwrap_or_log();
+                    let (head, body) = resp.into_parts();
+                    let body = hyper::body::to_bytes(body).await.unwrap_or_log();
+                    let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_log();
+                    assert_eq!(head.status, StatusCode::OK, "{head:?} {body:?}");
+                    assert_eq!(
+                        body["items"].as_array().unwrap().len(),
+                        1,
+                        "{resp_body_json:?}\n{body:?}"
+                    );
+                    assert!(body["nextCursor"].is_null(), "{resp_body_json:?}\n{body:?}");
+                    assert!(body["prevCursor"].is_string(), "{resp_body_json:?}\n{body:?}");
+                })
+            },
+        },
+        works_for_super_admins: {
+            uri: format!("/users/{USER_01_ID}/sessions"),
+            auth_token: USER_04_SESSION.into(),
+            status: StatusCode::OK,
+            body: serde_json::json!({}),
+            extra_assertions: &|EAArgs { response_json, .. }| {
+                Box::pin(async move {
+                    let resp_body_json = response_json.unwrap();
+                    assert_eq!(resp_body_json["items"][0]["userId"], USER_01_ID.to_string());
+                })
+            },
+        },
+        fails_for_other_users: {
+            uri: format!("/users/{USER_04_ID}/sessions"),
+            auth_token: USER_01_SESSION.into(),
+            status: StatusCode::UNAUTHORIZED,
+            body: serde_json::json!({}),
+            check_json: serde_json::json!({
+                "error": "accessDenied",
+            }),
+        },
+    }
+
+    #[tokio::test]
+    async fn authenticate_records_client_metadata() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+            let resp = app
+                .oneshot(
+                    http::Request::builder()
+                        .method("POST")
+                        .uri("/authenticate")
+                        .header(axum::http::header::CONTENT_TYPE, "application/json")
+                        .header(axum::http::header::USER_AGENT, "curl/7.85.0")
+                        .body(
+                            serde_json::to_vec(&serde_json::json!({
+                                "identifier": USER_02_USERNAME,
+                                "password": "password",
+                                "deviceLabel": "Work laptop",
+                            }))
+                            .unwrap()
+                            .into(),
+                        )
+                        .unwrap_or_log(),
+                )
+                .await
+                .unwrap_or_log();
+            assert_eq!(resp.status(), http::StatusCode::OK);
+            let body = hyper::body::to_bytes(resp.into_body())
+                .await
+                .unwrap_or_log();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_log();
+            let token = body["token"].as_str().unwrap();
+
+            let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+            let resp = app
+                .oneshot(
+                    http::Request::builder()
+                        .method("GET")
+                        .uri(format!("/users/{USER_02_ID}/sessions"))
+                        .header(axum::http::header::AUTHORIZATION, format!("Bearer {token}"))
+                        .header(axum::http::header::CONTENT_TYPE, "application/json")
+                        .body(serde_json::to_vec(&serde_json::json!({})).unwrap().into())
+                        .unwrap_or_log(),
+                )
+                .await
+                .unwrap_or_log();
+            assert_eq!(resp.status(), http::StatusCode::OK);
+            let body = hyper::body::to_bytes(resp.into_body())
+                .await
+                .unwrap_or_log();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_log();
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({
+                        "items": [{
+                            "userAgent": "curl/7.85.0",
+                            "deviceLabel": "Work laptop",
+                        }]
+                    }),
+                ),
+                ("response", &body),
+            );
+            assert!(body["items"][0]["id"].is_string());
+            assert!(body["items"][0].get("token").is_none());
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn omits_expired_sessions() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let in_8_days = time::OffsetDateTime::now_utc() + time::Duration::days(8);
+            ctx.clock.set(in_8_days);
+            // a fresh session as the fixture ones have now expired
+            sqlx::query("INSERT INTO sessions (token, user_id, expires_at) VALUES ($1, $2, $3)")
+                .bind("fresh_session")
+                .bind(USER_01_ID)
+                .bind(in_8_days + time::Duration::days(1))
+                .execute(&ctx.ctx().db_pool)
+                .await
+                .unwrap_or_log();
+
+            let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+            let resp = app
+                .oneshot(
+                    http::Request::builder()
+                        .method("GET")
+                        .uri(format!("/users/{USER_01_ID}/sessions"))
+                        .header(axum::http::header::AUTHORIZATION, "Bearer fresh_session")
+                        .body(Default::default())
+                        .unwrap_or_log(),
+                )
+                .await
+                .unwrap_or_log();
+            assert_eq!(resp.status(), http::StatusCode::OK);
+            let body = hyper::body::to_bytes(resp.into_body())
+                .await
+                .unwrap_or_log();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_log();
+            assert_eq!(body["items"].as_array().unwrap().len(), 1, "{body:?}");
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn filter_matches_wildcards_literally() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            for (token, user_agent) in [("percent_session", "100% curl"), ("plain_session", "curl")]
+            {
+                sqlx::query(
+                    "INSERT INTO sessions (token, user_id, expires_at, user_agent) VALUES ($1, $2, $3, $4)",
+                )
+                .bind(token)
+                .bind(USER_01_ID)
+                .bind(time::OffsetDateTime::now_utc() + time::Duration::days(1))
+                .bind(user_agent)
+                .execute(&ctx.ctx().db_pool)
+                .await
+                .unwrap_or_log();
+            }
+
+            let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+            let resp = app
+                .oneshot(
+                    http::Request::builder()
+                        .method("GET")
+                        .uri(format!("/users/{USER_01_ID}/sessions?filter=%25"))
+                        .header(
+                            axum::http::header::AUTHORIZATION,
+                            format!("Bearer {USER_01_SESSION}"),
+                        )
+                        .body(Default::default())
+                        .unwrap_or_log(),
+                )
+                .await
+                .unwrap_or_log();
+            assert_eq!(resp.status(), http::StatusCode::OK);
+            let body = hyper::body::to_bytes(resp.into_body())
+                .await
+                .unwrap_or_log();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_log();
+            assert_eq!(body["items"].as_array().unwrap().len(), 1, "{body:?}");
+            assert_eq!(body["items"][0]["userAgent"], "100% curl");
+        }
+        ctx.close().await;
+    }
+}
@@ -0,0 +1,286 @@
+//! Throttling of password guesses at [`Authenticate`](super::authenticate::Authenticate).
+//!
+//! Failures are counted per account and per client address. Once either
+//! count reaches its threshold, every further failure locks the key for twice
+//! as long as the last one, up to [`LockoutPolicy::max_lockout`].
+
+use deps::*;
+
+#[derive(Debug, Clone)]
+pub struct LockoutPolicy {
+    /// Failures per account before lockouts kick in.
+    pub threshold: u32,
+    /// Failures per client address before lockouts kick in. Should allow for
+    /// many users sharing an address.
+    pub ip_threshold: u32,
+    /// Length of the first lockout.
+    pub base_lockout: time::Duration,
+    pub max_lockout: time::Duration,
+    /// Failures older than this are forgotten.
+    pub reset_after: time::Duration,
+    /// How often forgotten failures are dropped.
+    pub prune_interval: time::Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            threshold: 5,
+            ip_threshold: 50,
+            base_lockout: time::Duration::seconds(30),
+            max_lockout: time::Duration::hours(1),
+            reset_after: time::Duration::days(1),
+            prune_interval: time::Duration::minutes(10),
+        }
+    }
+}
+
+impl LockoutPolicy {
+    /// How long a key with `failures` failures gets locked for.
+    pub fn lockout(&self, failures: u32, threshold: u32) -> Option<time::Duration> {
+        let doublings = failures.checked_sub(threshold)?;
+        // anything past 2^20 is well beyond any sane `max_lockout`
+        let lockout = self.base_lockout * 2_i32.pow(doublings.min(20));
+        Some(std::cmp::min(lockout, self.max_lockout))
+    }
+}
+
+/// The keys failures are tracked under.
+#[derive(Debug, Clone)]
+pub struct Keys {
+    pub account: String,
+    pub ip_addr: Option<String>,
+}
+
+impl Keys {
+    /// Shared by all the identifiers of the user, be it the username or email.
+    pub fn for_user(user_id: uuid::Uuid, ip_addr: Option<std::net::IpAddr>) -> Self {
+        Self {
+            account: format!("user:{user_id}"),
+            ip_addr: ip_addr.map(|addr| format!("ip:{addr}")),
+        }
+    }
+
+    /// For identifiers that don't belong to any user.
+    pub fn for_identifier(identifier: &str, ip_addr: Option<std::net::IpAddr>) -> Self {
+        Self {
+            // identifiers are matched case insensitively
+            account: format!("identifier:{}", identifier.to_lowercase()),
+            ip_addr: ip_addr.map(|addr| format!("ip:{addr}")),
+        }
+    }
+
+    fn all(&self) -> Vec<&str> {
+        std::iter::once(&self.account[..])
+            .chain(self.ip_addr.as_deref())
+            .collect()
+    }
+}
+
+/// Returns how long until the keys are unlocked, if any is locked.
+pub async fn locked_for(
+    ctx: &crate::Context,
+    keys: &Keys,
+) -> Result<Option<time::Duration>, sqlx::Error> {
+    let now = ctx.clock.now();
+    let locked_until = sqlx::query_scalar!(
+        r#"
+SELECT MAX(locked_until)
+FROM login_failures
+WHERE key = ANY($1) AND locked_until > $2
+        "#,
+        &keys.all()[..] as &[&str],
+        &now,
+    )
+    .fetch_one(&ctx.db_pool)
+    .await?;
+    Ok(locked_until.map(|locked_until| locked_until - now))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attempt {
+    /// Counted as a failure until [`record_success`] takes it back.
+    Counted { at: time::OffsetDateTime },
+    /// Not counted, the keys stay locked for this long.
+    Locked { remaining: time::Duration },
+}
+
+/// Counts an attempt as a failure against all the keys before the password
+/// is checked, locking those that reach their threshold right away. Checking
+/// first and counting after would let racing guesses all get through before
+/// any of them is counted.
+pub async fn record_attempt(ctx: &crate::Context, keys: &Keys) -> Result<Attempt, sqlx::Error> {
+    let policy = &ctx.config.login_lockout;
+    let now = ctx.clock.now();
+    let mut tx = ctx.db_pool.begin().await?;
+    let mut remaining = None;
+    for (key, threshold) in std::iter::once((&keys.account, policy.threshold))
+        .chain(keys.ip_addr.iter().map(|key| (key, policy.ip_threshold)))
+    {
+        // the upsert keeps the row locked until the transaction ends so
+        // racing attempts queue up behind it and see its outcome
+        let failures = sqlx::query_scalar!(
+            r#"
+INSERT INTO login_failures (key, failures, last_failed_at)
+VALUES ($1, 1, $2)
+ON CONFLICT (key) DO UPDATE
+SET failures = CASE
+        WHEN login_failures.last_failed_at < $3 THEN 1
+        ELSE login_failures.failures + 1
+    END,
+    last_failed_at = EXCLUDED.last_failed_at
+WHERE login_failures.locked_until IS NULL OR login_failures.locked_until <= $2
+RETURNING failures
+            "#,
+            key,
+            &now,
+            &(now - policy.reset_after),
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        let failures = if let Some(failures) = failures {
+            failures
+        } else {
+            let locked_until = sqlx::query_scalar!(
+                r#"
+SELECT locked_until as "locked_until!"
+FROM login_failures
+WHERE key = $1
+                "#,
+                key,
+            )
+            .fetch_one(&mut tx)
+            .await?;
+            remaining = std::cmp::max(remaining, Some(locked_until - now));
+            continue;
+        };
+        let lockout = if let Some(lockout) = policy.lockout(failures as u32, threshold) {
+            lockout
+        } else {
+            continue;
+        };
+        let locked_until = now + lockout;
+        sqlx::query!(
+            r#"
+UPDATE login_failures
+SET locked_until = $2
+WHERE key = $1
+            "#,
+            key,
+            &locked_until,
+        )
+        .execute(&mut tx)
+        .await?;
+        tracing::warn!(
+            target: "audit",
+            key,
+            failures,
+            %locked_until,
+            "login locked"
+        );
+    }
+    if let Some(remaining) = remaining {
+        // locked attempts don't count against the other keys either
+        tx.rollback().await?;
+        return Ok(Attempt::Locked { remaining });
+    }
+    tx.commit().await?;
+    Ok(Attempt::Counted { at: now })
+}
+
+/// Takes back the attempt counted `at` and forgets the failures of the
+/// account. The address keeps the rest of its count so that signing into
+/// one account doesn't reset guessing at others.
+pub async fn record_success(
+    ctx: &crate::Context,
+    keys: &Keys,
+    at: time::OffsetDateTime,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+DELETE FROM login_failures
+WHERE key = $1
+        "#,
+        &keys.account,
+    )
+    .execute(&ctx.db_pool)
+    .await?;
+    if let Some(key) = &keys.ip_addr {
+        // any lock is the attempt's own doing if nothing was counted since
+        sqlx::query!(
+            r#"
+UPDATE login_failures
+SET failures = failures - 1,
+    locked_until = CASE WHEN last_failed_at = $2 THEN NULL ELSE locked_until END
+WHERE key = $1 AND failures > 0
+            "#,
+            key,
+            &at,
+        )
+        .execute(&ctx.db_pool)
+        .await?;
+    }
+    Ok(())
+}
+
+/// For responses to locked out requests, `retry_after` is in seconds.
+pub fn set_retry_after(resp: &mut axum::response::Response, retry_after: u64) {
+    resp.headers_mut().insert(
+        axum::http::header::RETRY_AFTER,
+        axum::http::HeaderValue::from(retry_after),
+    );
+}
+
+/// Drops failures that no longer count, returns the number of rows removed.
+pub async fn prune(ctx: &crate::Context) -> Result<u64, sqlx::Error> {
+    let now = ctx.clock.now();
+    Ok(sqlx::query!(
+        r#"
+DELETE FROM login_failures
+WHERE last_failed_at < $1 AND (locked_until IS NULL OR locked_until < $2)
+        "#,
+        &(now - ctx.config.login_lockout.reset_after),
+        &now,
+    )
+    .execute(&ctx.db_pool)
+    .await?
+    .rows_affected())
+}
+
+/// Prunes every `interval` until the returned handle is aborted.
+pub fn spawn_pruner(
+    ctx: crate::SharedContext,
+    interval: time::Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(
+            interval
+                .try_into()
+                .expect("prune interval must not be negative"),
+        );
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = prune(&ctx).await {
+                tracing::error!(%err, "error pruning login failures");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use super::*;
+
+    #[test]
+    fn lockout_backs_off_exponentially() {
+        let policy = LockoutPolicy::default();
+        assert_eq!(policy.lockout(4, 5), None);
+        assert_eq!(policy.lockout(5, 5), Some(time::Duration::seconds(30)));
+        assert_eq!(policy.lockout(6, 5), Some(time::Duration::seconds(60)));
+        assert_eq!(policy.lockout(7, 5), Some(time::Duration::seconds(120)));
+        assert_eq!(policy.lockout(500, 5), Some(time::Duration::hours(1)));
+    }
+}
@@ -0,0 +1,178 @@
+use deps::*;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Logout;
+
+#[derive(Debug)]
+pub struct Request {
+    pub auth_token: std::sync::Arc<str>,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+pub type Response = NoContent;
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for Logout {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone(),
+            resource: crate::auth::Resource::Session { id: None },
+            action: crate::auth::Action::Delete,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        _accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        sqlx::query!(
+            r#"
+DELETE FROM sessions
+WHERE token = $1
+            "#,
+            &request.auth_token[..]
+        )
+        .execute(&ctx.db_pool)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
+        ctx.session_cache.invalidate([&request.auth_token]).await;
+        Ok(NoContent)
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for Logout {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/logout";
+    const SUCCESS_CODE: StatusCode = StatusCode::NO_CONTENT;
+
+    type HttpRequest = (BearerToken,);
+
+    fn request((BearerToken(token),): Self::HttpRequest) -> Result<Self::Request, Self::Error> {
+        Ok(self::Request { auth_token: token })
+    }
+
+    fn response(_: Self::Response) -> axum::response::Response {
+        Default::default()
+    }
+}
+
+impl DocumentedEndpoint for Logout {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str = "Revoke the session used to make the request.";
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    macro_rules! logout_integ {
+        ($(
+            $name:ident: {
+                auth_token: $auth_token:expr,
+                status: $status:expr,
+                $(extra_assertions: $extra_fn:expr,)?
+            },
+        )*) => {
+            mod integ {
+                use super::*;
+                crate::integration_table_tests! {
+                    $(
+                        $name: {
+                            uri: "/logout",
+                            method: "POST",
+                            status: $status,
+                            router: crate::auth::router(),
+                            auth_token: $auth_token,
+                            $(extra_assertions: $extra_fn,)?
+                        },
+                    )*
+                }
+            }
+        };
+    }
+
+    logout_integ! {
+        works: {
+            auth_token: USER_01_SESSION.into(),
+            status: StatusCode::NO_CONTENT,
+            extra_assertions: &|EAArgs { ctx, .. }| {
+                Box::pin(async move {
+                    let app = crate::user::router().layer(axum::Extension(ctx.ctx()));
+                    let resp = app
+                        .oneshot(
+                            http::Request::builder()
+                                .method("GET")
+                                .uri(format!("/users/{USER_01_ID}"))
+                                .header(
+                                    axum::http::header::AUTHORIZATION,
+                                    format!("Bearer {USER_01_SESSION}"),
+                                )
+                                .body(Default::default())
+                                .unwrap_or_log(),
+                        )
+                        .await
+                        .unwrap_or_log();
+                    assert_eq!(resp.status(), http::StatusCode::UNAUTHORIZED);
+                    let count = sqlx::query_scalar::<_, i64>(
+                        "SELECT COUNT(*) FROM sessions_deleted WHERE token = $1"
+                    )
+                        .bind(USER_01_SESSION)
+                        .fetch_one(&ctx.ctx().db_pool)
+                        .await
+                        .unwrap_or_log();
+                    assert_eq!(count, 1);
+                })
+            },
+        },
+        fails_if_token_invalid: {
+            auth_token: uuid::Uuid::new_v4().to_string(),
+            status: StatusCode::UNAUTHORIZED,
+        },
+    }
+}
@@ -0,0 +1,306 @@
+//! OpenID Connect single sign-on using the authorization code flow with PKCE.
+//!
+//! Users first link an identity at a [`Provider`] to their account after
+//! which they can authenticate through the provider. Both flows start by
+//! sending the user to the URL from [`begin`] and end by handing the `code`
+//! and `state` the provider redirects back with to [`complete`].
+
+use deps::*;
+
+fn default_scopes() -> Vec<String> {
+    vec!["openid".into(), "email".into()]
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(crate = "serde", rename_all = "camelCase", deny_unknown_fields)]
+pub struct Provider {
+    /// Identifies the provider in paths, e.g. `/authenticate/oidc/{name}`.
+    pub name: String,
+    /// The discovery document is expected at
+    /// `{issuer}/.well-known/openid-configuration`.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Where the provider sends the user back to. The client application is
+    /// expected to forward the `code` and `state` it receives there to the
+    /// matching callback endpoint.
+    pub redirect_uri: String,
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<String>,
+}
+
+/// ```toml
+/// [[providers]]
+/// name = "google"
+/// issuer = "https://accounts.google.com"
+/// clientId = "1234.apps.googleusercontent.com"
+/// clientSecret = "hunter2"
+/// redirectUri = "https://example.com/sso/callback"
+/// ```
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(crate = "serde", deny_unknown_fields)]
+pub struct ProviderList {
+    #[serde(default)]
+    pub providers: Vec<Provider>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProviderListLoadError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("toml error: {0}")]
+    Toml(#[from] toml::de::Error),
+}
+
+impl ProviderList {
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, ProviderListLoadError> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(crate = "serde")]
+pub struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// The subset of the ID token claims we care about.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(crate = "serde")]
+pub struct IdClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    pub nonce: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct AuthorizationRedirect {
+    /// Send the user here to sign in at the provider.
+    pub authorization_url: String,
+}
+
+/// An identity at a [`Provider`] linked to a user.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Identity {
+    #[schema(example = "google")]
+    pub provider: String,
+    /// The `sub` claim issued by the provider.
+    pub subject: String,
+    /// As reported by the provider at the time of linking.
+    pub email: Option<String>,
+    /// In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
+    pub created_at: time::OffsetDateTime,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unknown provider: {name:?}")]
+    UnknownProvider { name: String },
+    #[error("invalid or expired state")]
+    StateRejected,
+    #[error("provider error: {message:?}")]
+    Provider { message: String },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+fn provider_err(err: impl std::fmt::Display) -> Error {
+    Error::Provider {
+        message: format!("{err}"),
+    }
+}
+
+fn db_err(err: sqlx::Error) -> Error {
+    Error::Internal {
+        message: format!("db error: {err}"),
+    }
+}
+
+pub fn provider<'a>(ctx: &'a crate::Context, name: &str) -> Result<&'a Provider, Error> {
+    ctx.config
+        .oidc_providers
+        .iter()
+        .find(|provider| provider.name == name)
+        .ok_or_else(|| Error::UnknownProvider { name: name.into() })
+}
+
+pub async fn discover(
+    http_client: &reqwest::Client,
+    provider: &Provider,
+) -> Result<Discovery, Error> {
+    let issuer = provider.issuer.trim_end_matches('/');
+    let discovery: Discovery = http_client
+        .get(format!("{issuer}/.well-known/openid-configuration"))
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(provider_err)?
+        .json()
+        .await
+        .map_err(provider_err)?;
+    if discovery.issuer.trim_end_matches('/') != issuer {
+        return Err(provider_err(format!(
+            "discovered issuer {:?} doesn't match configured {issuer:?}",
+            discovery.issuer
+        )));
+    }
+    Ok(discovery)
+}
+
+/// The `S256` PKCE code challenge for `verifier`.
+pub fn pkce_challenge(verifier: &str) -> String {
+    use sha2::Digest;
+    base64::encode_config(
+        sha2::Sha256::digest(verifier.as_bytes()),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+/// Returns the URL to send the user to. `link_user_id` is set when linking an
+/// identity to an account and must match when completing.
+#[tracing::instrument(skip(ctx))]
+pub async fn begin(
+    ctx: &crate::Context,
+    provider_name: &str,
+    link_user_id: Option<uuid::Uuid>,
+) -> Result<AuthorizationRedirect, Error> {
+    let provider = provider(ctx, provider_name)?;
+    let discovery = discover(&ctx.http_client, provider).await?;
+    let state = super::tokens::generate_token();
+    let verifier = super::tokens::generate_token();
+    let nonce = super::tokens::generate_token();
+    let expires_at = time::OffsetDateTime::now_utc().saturating_add(ctx.config.oidc_state_lifespan);
+    sqlx::query!(
+        r#"
+INSERT INTO oidc_states (state_hash, provider, pkce_verifier, nonce, link_user_id, expires_at)
+VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        super::tokens::hash_token(&state),
+        &provider.name,
+        &verifier,
+        &nonce,
+        link_user_id,
+        &expires_at,
+    )
+    .execute(&ctx.db_pool)
+    .await
+    .map_err(db_err)?;
+
+    let mut url = reqwest::Url::parse(&discovery.authorization_endpoint).map_err(provider_err)?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", &provider.redirect_uri)
+        .append_pair("scope", &provider.scopes.join(" "))
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce)
+        .append_pair("code_challenge", &pkce_challenge(&verifier))
+        .append_pair("code_challenge_method", "S256");
+    Ok(AuthorizationRedirect {
+        authorization_url: url.into(),
+    })
+}
+
+/// Spends `state` and trades `code` for the verified claims of the ID token.
+#[tracing::instrument(skip(ctx, code, state))]
+pub async fn complete(
+    ctx: &crate::Context,
+    provider_name: &str,
+    code: &str,
+    state: &str,
+    link_user_id: Option<uuid::Uuid>,
+) -> Result<IdClaims, Error> {
+    let provider = provider(ctx, provider_name)?;
+    let spent = sqlx::query!(
+        r#"
+DELETE FROM oidc_states
+WHERE state_hash = $1
+RETURNING provider, pkce_verifier, nonce, link_user_id, expires_at
+        "#,
+        super::tokens::hash_token(state),
+    )
+    .fetch_optional(&ctx.db_pool)
+    .await
+    .map_err(db_err)?;
+    let spent = match spent {
+        Some(spent)
+            if spent.provider == provider.name
+                && spent.link_user_id == link_user_id
+                && spent.expires_at > time::OffsetDateTime::now_utc() =>
+        {
+            spent
+        }
+        _ => return Err(Error::StateRejected),
+    };
+
+    let discovery = discover(&ctx.http_client, provider).await?;
+    #[derive(serde::Deserialize)]
+    #[serde(crate = "serde")]
+    struct TokenResponse {
+        id_token: String,
+    }
+    let tokens: TokenResponse = ctx
+        .http_client
+        .post(&discovery.token_endpoint)
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &provider.redirect_uri),
+            ("client_id", &provider.client_id),
+            ("client_secret", &provider.client_secret),
+            ("code_verifier", &spent.pkce_verifier),
+        ])
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(provider_err)?
+        .json()
+        .await
+        .map_err(provider_err)?;
+
+    let header = jsonwebtoken::decode_header(&tokens.id_token).map_err(provider_err)?;
+    // only keys published by the provider are trusted
+    if matches!(
+        header.alg,
+        jsonwebtoken::Algorithm::HS256
+            | jsonwebtoken::Algorithm::HS384
+            | jsonwebtoken::Algorithm::HS512
+    ) {
+        return Err(provider_err(
+            "symmetrically signed ID tokens are not supported",
+        ));
+    }
+    let jwks: jsonwebtoken::jwk::JwkSet = ctx
+        .http_client
+        .get(&discovery.jwks_uri)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(provider_err)?
+        .json()
+        .await
+        .map_err(provider_err)?;
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None => jwks.keys.first(),
+    }
+    .ok_or_else(|| provider_err("no matching key in JWKS"))?;
+    let key = jsonwebtoken::DecodingKey::from_jwk(jwk).map_err(provider_err)?;
+    let mut validation = jsonwebtoken::Validation::new(header.alg);
+    validation.set_audience(&[&provider.client_id]);
+    validation.set_issuer(&[&discovery.issuer]);
+    let claims = jsonwebtoken::decode::<IdClaims>(&tokens.id_token, &key, &validation)
+        .map_err(provider_err)?
+        .claims;
+    if claims.nonce.as_deref() != Some(&spent.nonce[..]) {
+        return Err(provider_err("ID token nonce mismatch"));
+    }
+    Ok(claims)
+}
@@ -0,0 +1,207 @@
+//! Hashing of user passwords.
+//!
+//! Every hash gets its own random salt and, if configured, is keyed with the
+//! server-side [`pass_pepper`](crate::Config::pass_pepper). Hashes made with
+//! weaker parameters than the current [`argon2_conf`](crate::Config::argon2_conf)
+//! are flagged by [`verify`] so that they can be upgraded while the password
+//! is at hand.
+
+use deps::*;
+
+/// In bytes.
+pub const SALT_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Rejected,
+    Accepted {
+        /// The hash should be replaced with a fresh one from [`hash`].
+        needs_rehash: bool,
+    },
+}
+
+fn argon2_conf(config: &crate::Config) -> argon2::Config<'_> {
+    argon2::Config {
+        secret: config.pass_pepper.as_deref().unwrap_or_default(),
+        ..config.argon2_conf.clone()
+    }
+}
+
+pub fn hash(config: &crate::Config, password: &str) -> String {
+    let salt: [u8; SALT_LEN] = rand::random();
+    argon2::hash_encoded(password.as_bytes(), &salt, &argon2_conf(config)).unwrap_or_log()
+}
+
+pub fn verify(config: &crate::Config, encoded: &str, password: &str) -> Verification {
+    let pepper = config.pass_pepper.as_deref().unwrap_or_default();
+    let verify = |secret| {
+        argon2::verify_encoded_ext(encoded, password.as_bytes(), secret, &[]).unwrap_or_else(
+            |err| {
+                tracing::error!(%err, "malformed password hash");
+                false
+            },
+        )
+    };
+    if verify(pepper) {
+        Verification::Accepted {
+            needs_rehash: is_weaker(&config.argon2_conf, encoded),
+        }
+    // hashes from before a pepper was configured
+    } else if !pepper.is_empty() && verify(&[]) {
+        Verification::Accepted { needs_rehash: true }
+    } else {
+        Verification::Rejected
+    }
+}
+
+/// Made with the default parameters, matches no password anyone knows.
+const DUMMY_HASH: &str =
+    "$argon2i$v=19$m=4096,t=3,p=1$6IYAiCwRkv+uw0XrOWSG6g$j5lpPfL4tdcHENxV2TSQDSkEhXyxLFqYReSI5Ad7wPw";
+
+/// Spends about as long as [`verify`] to reject a password, for when there's
+/// no hash to check it against.
+pub fn verify_dummy(config: &crate::Config, password: &str) -> Verification {
+    verify(config, DUMMY_HASH, password);
+    Verification::Rejected
+}
+
+/// Whether `encoded`, a PHC string like
+/// `$argon2i$v=19$m=4096,t=3,p=1$<salt>$<hash>`, falls short of `conf`
+/// anywhere. Unparseable hashes are considered weaker.
+pub fn is_weaker(conf: &argon2::Config, encoded: &str) -> bool {
+    let parts = encoded.split('$').skip(1).collect::<Vec<_>>();
+    // the version was only added in 0x13
+    let (variant, version, params, salt, hash) = match &parts[..] {
+        [variant, version, params, salt, hash] => (
+            *variant,
+            version
+                .strip_prefix("v=")
+                .and_then(|version| version.parse::<u32>().ok()),
+            *params,
+            *salt,
+            *hash,
+        ),
+        [variant, params, salt, hash] => (*variant, Some(0x10), *params, *salt, *hash),
+        _ => return true,
+    };
+    let param = |name: &str| {
+        params
+            .split(',')
+            .find_map(|param| param.strip_prefix(name)?.strip_prefix('='))
+            .and_then(|val| val.parse::<u32>().ok())
+            .unwrap_or(0)
+    };
+    let decoded_len = |val: &str| {
+        base64::decode_config(val, base64::STANDARD_NO_PAD)
+            .map(|bytes| bytes.len())
+            .unwrap_or(0)
+    };
+    variant != conf.variant.as_lowercase_str()
+        || version.unwrap_or(0) < conf.version.as_u32()
+        || param("m") < conf.mem_cost
+        || param("t") < conf.time_cost
+        || param("p") < conf.lanes
+        || decoded_len(salt) < SALT_LEN
+        || decoded_len(hash) < conf.hash_length as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use super::*;
+    use crate::utils::testing::*;
+
+    /// As found in the fixtures.
+    const FIXTURE_HASH: &str =
+        "$argon2i$v=19$m=4096,t=3,p=1$c29tZXNhbHQ$iWh06vD8Fy27wf9npn6FXWiCX4K6pW6Ue1Bnzz07Z8A";
+
+    #[tokio::test]
+    async fn password_hash_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let config = &ctx.ctx().config;
+            let first = hash(config, "password");
+            assert_ne!(first, hash(config, "password"), "salts must differ");
+            assert_eq!(
+                verify(config, &first, "password"),
+                Verification::Accepted {
+                    needs_rehash: false
+                }
+            );
+            assert_eq!(verify(config, &first, "hunter2"), Verification::Rejected);
+            assert_eq!(
+                verify(config, FIXTURE_HASH, "password"),
+                Verification::Accepted { needs_rehash: true },
+                "the fixture salt is too short"
+            );
+            assert_eq!(
+                verify(config, "nonsense", "password"),
+                Verification::Rejected
+            );
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn password_pepper_works() {
+        let ctx = TestContext::with_config(crate::function!(), |config| {
+            config.pass_pepper = Some(b"cayenne".to_vec());
+        })
+        .await;
+        {
+            let config = &ctx.ctx().config;
+            let peppered = hash(config, "password");
+            assert!(!argon2::verify_encoded(&peppered, b"password").unwrap());
+            assert_eq!(
+                verify(config, &peppered, "password"),
+                Verification::Accepted {
+                    needs_rehash: false
+                }
+            );
+            let unpeppered =
+                argon2::hash_encoded(b"password", &[0; SALT_LEN], &ctx.ctx().config.argon2_conf)
+                    .unwrap();
+            assert_eq!(
+                verify(config, &unpeppered, "password"),
+                Verification::Accepted { needs_rehash: true }
+            );
+        }
+        ctx.close().await;
+    }
+
+    #[test]
+    fn password_is_weaker_works() {
+        let conf = argon2::Config::default();
+        let with = |conf: &argon2::Config| {
+            argon2::hash_encoded(b"password", &[0; SALT_LEN], conf).unwrap()
+        };
+        assert!(!is_weaker(&conf, &with(&conf)));
+        for weaker in [
+            argon2::Config {
+                mem_cost: conf.mem_cost / 2,
+                ..conf.clone()
+            },
+            argon2::Config {
+                time_cost: conf.time_cost - 1,
+                ..conf.clone()
+            },
+            argon2::Config {
+                variant: argon2::Variant::Argon2d,
+                ..conf.clone()
+            },
+            argon2::Config {
+                version: argon2::Version::Version10,
+                ..conf.clone()
+            },
+        ] {
+            assert!(is_weaker(&conf, &with(&weaker)), "{weaker:?}");
+        }
+        let stronger = argon2::Config {
+            time_cost: conf.time_cost + 1,
+            ..conf.clone()
+        };
+        assert!(!is_weaker(&conf, &with(&stronger)));
+        assert!(is_weaker(&conf, FIXTURE_HASH));
+    }
+}
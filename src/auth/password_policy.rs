@@ -0,0 +1,233 @@
+//! Rules new passwords have to satisfy.
+
+use deps::*;
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    /// In characters.
+    pub min_length: usize,
+    /// In characters. Bounds the cost of hashing.
+    pub max_length: usize,
+    /// Minimum [`entropy_bits`] estimate.
+    pub min_entropy_bits: f64,
+    /// Reject passwords containing the username or email.
+    pub forbid_personal_info: bool,
+    /// Known leaked passwords.
+    pub breached: Option<std::sync::Arc<BreachedPasswords>>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 128,
+            min_entropy_bits: 40.,
+            forbid_personal_info: true,
+            breached: None,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Adds an issue under `field` for every rule `password` breaks.
+    /// `personal_info` holds the username and email of the account.
+    pub fn check(
+        &self,
+        issues: &mut validator::ValidationErrors,
+        field: &'static str,
+        password: &str,
+        personal_info: &[&str],
+    ) {
+        let length = password.chars().count();
+        if length < self.min_length || length > self.max_length {
+            let mut issue = validator::ValidationError::new("length");
+            issue.add_param("min".into(), &self.min_length);
+            issue.add_param("max".into(), &self.max_length);
+            issues.add(field, issue);
+        }
+        let entropy = entropy_bits(password);
+        if entropy < self.min_entropy_bits {
+            let mut issue = validator::ValidationError::new("entropy");
+            issue.add_param("min".into(), &self.min_entropy_bits);
+            issue.add_param("score".into(), &entropy.floor());
+            issues.add(field, issue);
+        }
+        if self.forbid_personal_info {
+            let password = password.to_lowercase();
+            let contains_personal_info = personal_info
+                .iter()
+                .flat_map(|info| {
+                    // the local part of emails on its own too
+                    std::iter::once(*info).chain(info.split_once('@').map(|(local, _)| local))
+                })
+                // too short to be telling
+                .filter(|info| info.chars().count() >= 3)
+                .any(|info| password.contains(&info.to_lowercase()));
+            if contains_personal_info {
+                issues.add(field, validator::ValidationError::new("personal_info"));
+            }
+        }
+        if let Some(breached) = &self.breached {
+            if breached.contains(password) {
+                issues.add(field, validator::ValidationError::new("breached"));
+            }
+        }
+    }
+}
+
+/// A rough estimate: the log2 of the size of the character classes present
+/// for every distinct character. Repeats and patterns are what make weak
+/// passwords weak so they don't count. Single-case words of eight letters or
+/// so, the dictionary favourites, end up below the default minimum.
+pub fn entropy_bits(password: &str) -> f64 {
+    let (mut lower, mut upper, mut digit, mut symbol, mut other) =
+        (false, false, false, false, false);
+    let mut distinct = std::collections::HashSet::new();
+    for ch in password.chars() {
+        match ch {
+            'a'..='z' => lower = true,
+            'A'..='Z' => upper = true,
+            '0'..='9' => digit = true,
+            _ if ch.is_ascii() => symbol = true,
+            _ => other = true,
+        }
+        distinct.insert(ch);
+    }
+    let pool = [
+        (lower, 26),
+        (upper, 26),
+        (digit, 10),
+        (symbol, 33),
+        (other, 100),
+    ]
+    .into_iter()
+    .filter(|(present, _)| *present)
+    .map(|(_, size)| size)
+    .sum::<u32>();
+    if pool == 0 {
+        return 0.;
+    }
+    distinct.len() as f64 * f64::from(pool).log2()
+}
+
+/// A sorted list of SHA-1 hashes of leaked passwords, searched without
+/// leaving the process.
+#[derive(Debug, Default)]
+pub struct BreachedPasswords {
+    hashes: Vec<[u8; 20]>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BreachedPasswordsLoadError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid hash on line {line}")]
+    InvalidHash { line: usize },
+}
+
+impl BreachedPasswords {
+    /// Reads one hex encoded SHA-1 hash per line. Anything after a `:` is
+    /// ignored so that the `HASH:COUNT` dumps of Have I Been Pwned can be
+    /// used as is.
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, BreachedPasswordsLoadError> {
+        use std::io::BufRead;
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut hashes = Vec::new();
+        for (idx, line) in file.lines().enumerate() {
+            let line = line?;
+            let hex = line.split(':').next().unwrap_or_default().trim();
+            if hex.is_empty() {
+                continue;
+            }
+            let hash = data_encoding::HEXLOWER_PERMISSIVE
+                .decode(hex.as_bytes())
+                .ok()
+                .and_then(|bytes| <[u8; 20]>::try_from(bytes).ok())
+                .ok_or(BreachedPasswordsLoadError::InvalidHash { line: idx + 1 })?;
+            hashes.push(hash);
+        }
+        Ok(Self::from_hashes(hashes))
+    }
+
+    pub fn from_hashes(mut hashes: Vec<[u8; 20]>) -> Self {
+        hashes.sort_unstable();
+        hashes.dedup();
+        Self { hashes }
+    }
+
+    pub fn contains(&self, password: &str) -> bool {
+        use sha1::Digest;
+        let hash: [u8; 20] = sha1::Sha1::digest(password.as_bytes()).into();
+        self.hashes.binary_search(&hash).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use super::*;
+
+    fn codes(policy: &PasswordPolicy, password: &str) -> Vec<String> {
+        let mut issues = validator::ValidationErrors::new();
+        policy.check(
+            &mut issues,
+            "password",
+            password,
+            &["whish_box12", "multis@cream.mux"],
+        );
+        issues
+            .field_errors()
+            .get("password")
+            .map(|errs| errs.iter().map(|err| err.code.to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn password_policy_works() {
+        let path = std::env::temp_dir().join(format!("breached_{}.txt", uuid::Uuid::new_v4()));
+        // sha1("correct horse battery staple"), in the HIBP format
+        std::fs::write(
+            &path,
+            "ABF7AAD6438836DBE526AA231ABDE2D0EEF74D42:3\n\n0000000000000000000000000000000000000000:1\n",
+        )
+        .unwrap();
+        let breached = BreachedPasswords::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let policy = PasswordPolicy {
+            breached: Some(std::sync::Arc::new(breached)),
+            ..Default::default()
+        };
+
+        assert!(codes(&policy, "lovebite!Rx9").is_empty());
+        assert!(codes(&policy, "Tr0ub4dor&3").is_empty());
+        assert_eq!(codes(&policy, "Sh0r+!x"), vec!["length"]);
+        assert_eq!(
+            codes(&policy, &"aB3$".repeat(40)),
+            vec!["length", "entropy"]
+        );
+        assert_eq!(codes(&policy, "aaaaaaaaaaaa"), vec!["entropy"]);
+        assert_eq!(codes(&policy, "12345678"), vec!["entropy"]);
+        for common in ["password", "sunshine", "football", "iloveyou", "princess"] {
+            assert_eq!(codes(&policy, common), vec!["entropy"], "{common}");
+        }
+        assert_eq!(codes(&policy, "xx-Whish_Box12-xx"), vec!["personal_info"]);
+        assert_eq!(codes(&policy, "iammultis!"), vec!["personal_info"]);
+        assert_eq!(
+            codes(&policy, "correct horse battery staple"),
+            vec!["breached"]
+        );
+    }
+
+    #[test]
+    fn breached_passwords_rejects_malformed_lists() {
+        let path = std::env::temp_dir().join(format!("breached_{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "ABF7AAD6438836DBE526AA231ABDE2D0EEF74D42\nhunter2\n").unwrap();
+        let result = BreachedPasswords::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            result,
+            Err(BreachedPasswordsLoadError::InvalidHash { line: 2 })
+        ));
+    }
+}
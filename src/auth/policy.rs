@@ -0,0 +1,335 @@
+//! Decide whether a [`Subject`] may perform an [`Action`] on a [`Resource`].
+//!
+//! [`Authorize`](super::authorize::Authorize) defers to the [`Policy`] held in
+//! the [`Context`](crate::Context).
+
+use deps::*;
+
+use crate::auth::{Action, Resource, Role};
+
+/// Who's asking.
+#[derive(Debug, Clone)]
+pub struct Subject {
+    pub user_id: uuid::Uuid,
+    pub roles: Vec<Role>,
+}
+
+/// Extra facts about the request gathered by `Authorize`.
+#[derive(Debug, Clone, Default)]
+pub struct Attributes {
+    /// The user the resource belongs to if it belongs to any.
+    pub owner_id: Option<uuid::Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Allow { reason: String },
+    Deny { reason: String },
+}
+
+impl Decision {
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, Self::Allow { .. })
+    }
+
+    pub fn reason(&self) -> &str {
+        match self {
+            Self::Allow { reason } | Self::Deny { reason } => reason,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+#[async_trait::async_trait]
+pub trait Policy: Send + Sync + std::fmt::Debug {
+    async fn decide(
+        &self,
+        subject: &Subject,
+        resource: &Resource,
+        action: Action,
+        attributes: &Attributes,
+    ) -> Result<Decision, Error>;
+}
+
+/// Users may do anything to what they own, everything else is looked up in
+/// the `auth_policies` table.
+#[derive(Debug, Clone)]
+pub struct DefaultPolicy {
+    db_pool: sqlx::postgres::PgPool,
+}
+
+impl DefaultPolicy {
+    pub fn new(db_pool: sqlx::postgres::PgPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl Policy for DefaultPolicy {
+    async fn decide(
+        &self,
+        subject: &Subject,
+        resource: &Resource,
+        action: Action,
+        attributes: &Attributes,
+    ) -> Result<Decision, Error> {
+        if attributes.owner_id == Some(subject.user_id) {
+            return Ok(Decision::Allow {
+                reason: "subject owns resource".into(),
+            });
+        }
+        // deny if any of the roles denies and allow if any allows
+        let allowed = sqlx::query_scalar!(
+            r#"
+SELECT bool_and(allow)
+FROM auth_policies
+WHERE role = ANY($1) AND resource = $2 AND action = $3
+            "#,
+            &subject
+                .roles
+                .iter()
+                .map(|role| role.as_str().to_string())
+                .collect::<Vec<_>>()[..],
+            resource.kind(),
+            action.as_str(),
+        )
+        .fetch_one(&self.db_pool)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("{err}"),
+        })?;
+        Ok(match allowed {
+            Some(true) => Decision::Allow {
+                reason: "allowed by role policy".into(),
+            },
+            Some(false) => Decision::Deny {
+                reason: "denied by role policy".into(),
+            },
+            None => Decision::Deny {
+                reason: format!(
+                    "no policy for roles {:?} on {} {}",
+                    subject.roles,
+                    resource.kind(),
+                    action.as_str()
+                ),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+/// A rule matches if all of its set conditions hold.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(crate = "serde", rename_all = "camelCase", deny_unknown_fields)]
+pub struct Rule {
+    /// Subject has to have this role.
+    pub role: Option<Role>,
+    /// [`Resource::kind`]
+    pub resource: Option<String>,
+    pub action: Option<Action>,
+    /// Subject has to (not) own the resource.
+    pub owner: Option<bool>,
+    pub effect: Effect,
+}
+
+impl Rule {
+    fn matches(
+        &self,
+        subject: &Subject,
+        resource: &Resource,
+        action: Action,
+        attributes: &Attributes,
+    ) -> bool {
+        let is_owner = attributes.owner_id == Some(subject.user_id);
+        self.role.iter().all(|role| subject.roles.contains(role))
+            && self.resource.iter().all(|kind| kind == resource.kind())
+            && self.action.iter().all(|act| *act == action)
+            && self.owner.iter().all(|owner| *owner == is_owner)
+    }
+}
+
+/// Declarative policy. Any matching deny rule wins over matching allow rules
+/// and if no rule matches, access is denied.
+///
+/// ```toml
+/// [[rules]]
+/// owner = true
+/// effect = "allow"
+///
+/// [[rules]]
+/// role = "admin"
+/// resource = "users"
+/// action = "read"
+/// effect = "allow"
+/// ```
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(crate = "serde", deny_unknown_fields)]
+pub struct RuleSet {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RuleSetLoadError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("toml error: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl RuleSet {
+    pub fn from_toml_str(src: &str) -> Result<Self, RuleSetLoadError> {
+        Ok(toml::from_str(src)?)
+    }
+
+    pub fn from_json_str(src: &str) -> Result<Self, RuleSetLoadError> {
+        Ok(serde_json::from_str(src)?)
+    }
+
+    /// Files ending in `.json` are read as JSON, anything else as TOML.
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, RuleSetLoadError> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json_str(&src),
+            _ => Self::from_toml_str(&src),
+        }
+    }
+
+    pub fn evaluate(
+        &self,
+        subject: &Subject,
+        resource: &Resource,
+        action: Action,
+        attributes: &Attributes,
+    ) -> Decision {
+        let mut allowed_by = None;
+        for (ii, rule) in self.rules.iter().enumerate() {
+            if !rule.matches(subject, resource, action, attributes) {
+                continue;
+            }
+            match rule.effect {
+                Effect::Deny => {
+                    return Decision::Deny {
+                        reason: format!("denied by rule {ii}"),
+                    }
+                }
+                Effect::Allow => {
+                    allowed_by.get_or_insert(ii);
+                }
+            }
+        }
+        match allowed_by {
+            Some(ii) => Decision::Allow {
+                reason: format!("allowed by rule {ii}"),
+            },
+            None => Decision::Deny {
+                reason: format!(
+                    "no rule allows roles {:?} to {} {}",
+                    subject.roles,
+                    action.as_str(),
+                    resource.kind()
+                ),
+            },
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Policy for RuleSet {
+    async fn decide(
+        &self,
+        subject: &Subject,
+        resource: &Resource,
+        action: Action,
+        attributes: &Attributes,
+    ) -> Result<Decision, Error> {
+        Ok(self.evaluate(subject, resource, action, attributes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::testing::*;
+
+    const RULES: &str = r#"
+[[rules]]
+owner = true
+effect = "allow"
+
+[[rules]]
+role = "admin"
+resource = "users"
+action = "read"
+effect = "allow"
+
+[[rules]]
+role = "admin"
+resource = "user"
+effect = "allow"
+
+[[rules]]
+role = "admin"
+resource = "user"
+action = "delete"
+owner = false
+effect = "deny"
+"#;
+
+    crate::table_tests! {
+        rule_set,
+        (roles, resource, action, owner_id, expected_allowed),
+        {
+            let rules = RuleSet::from_toml_str(RULES).unwrap();
+            let subject = Subject { user_id: USER_01_ID, roles };
+            let decision = rules.evaluate(
+                &subject,
+                &resource,
+                action,
+                &Attributes { owner_id },
+            );
+            assert_eq!(decision.is_allowed(), expected_allowed, "{decision:?}");
+        }
+    }
+
+    rule_set! {
+        allows_owner: (vec![], Resource::User { id: USER_01_ID }, Action::Delete, Some(USER_01_ID), true),
+        denies_non_owner: (vec![], Resource::User { id: USER_02_ID }, Action::Read, Some(USER_02_ID), false),
+        denies_list_without_role: (vec![], Resource::Users, Action::Read, None, false),
+        allows_list_for_admin: (vec![Role::Admin], Resource::Users, Action::Read, None, true),
+        denies_unlisted_action: (vec![Role::Admin], Resource::Users, Action::Delete, None, false),
+        allows_admin_write: (vec![Role::Admin], Resource::User { id: USER_02_ID }, Action::Write, Some(USER_02_ID), true),
+        deny_wins: (vec![Role::Admin], Resource::User { id: USER_02_ID }, Action::Delete, Some(USER_02_ID), false),
+    }
+
+    #[test]
+    fn rule_set_parses_json() {
+        let rules =
+            RuleSet::from_json_str(r#"{ "rules": [{ "role": "superAdmin", "effect": "allow" }] }"#)
+                .unwrap();
+        let decision = rules.evaluate(
+            &Subject {
+                user_id: USER_01_ID,
+                roles: vec![Role::SuperAdmin],
+            },
+            &Resource::Users,
+            Action::Write,
+            &Default::default(),
+        );
+        assert!(decision.is_allowed(), "{decision:?}");
+    }
+}
@@ -0,0 +1,384 @@
+use deps::*;
+
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+pub struct Refresh;
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Request {
+    pub refresh_token: String,
+}
+
+pub type Response = super::authenticate::Tokens;
+
+#[derive(Debug, Serialize, thiserror::Error, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase", tag = "error")]
+pub enum Error {
+    #[error("refresh token rejected")]
+    TokenRejected,
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+#[async_trait::async_trait]
+impl Endpoint for Refresh {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let db_err = |err| Error::Internal {
+            message: format!("db error: {err}"),
+        };
+        let mut tx = ctx.db_pool.begin().await.map_err(db_err)?;
+        // the delete makes sure only one request gets to spend the token even
+        // if they race
+        let spent = sqlx::query!(
+            r#"
+DELETE FROM sessions
+WHERE refresh_token = $1
+RETURNING token, user_id, family_id, refresh_expires_at, user_agent, ip_addr, device_label
+            "#,
+            &request.refresh_token,
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(db_err)?;
+
+        let spent = if let Some(spent) = spent {
+            spent
+        } else {
+            // a token that was already rotated out means it leaked, revoke
+            // every session descended from the same authentication. Sessions
+            // that were revoked or swept are just gone.
+            let revoked = sqlx::query_scalar!(
+                r#"
+DELETE FROM sessions
+WHERE family_id = (
+    SELECT family_id
+    FROM sessions_deleted
+    WHERE refresh_token = $1 AND rotated_at IS NOT NULL
+    LIMIT 1
+)
+RETURNING token
+                "#,
+                &request.refresh_token,
+            )
+            .fetch_all(&mut tx)
+            .await
+            .map_err(db_err)?;
+            tx.commit().await.map_err(db_err)?;
+            if !revoked.is_empty() {
+                tracing::warn!(
+                    revoked = revoked.len(),
+                    "refresh token reuse detected, token family revoked"
+                );
+                ctx.session_cache.invalidate(revoked).await;
+            }
+            return Err(Error::TokenRejected);
+        };
+
+        let now = ctx.clock.now();
+        if !matches!(spent.refresh_expires_at, Some(at) if at >= now) {
+            tx.commit().await.map_err(db_err)?;
+            ctx.session_cache.invalidate([&spent.token]).await;
+            return Err(Error::TokenRejected);
+        }
+
+        let expires_at = now.saturating_add(ctx.config.access_token_lifespan);
+        let refresh_expires_at = now.saturating_add(ctx.config.refresh_token_lifespan);
+        let token = super::authenticate::access_token(ctx, spent.user_id, None, now, expires_at)
+            .await
+            .map_err(db_err)?;
+        let refresh_token = uuid::Uuid::new_v4().to_string();
+        sqlx::query!(
+            r#"
+UPDATE sessions_deleted
+SET rotated_at = $2
+WHERE token = $1
+        "#,
+            &spent.token,
+            &now,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(db_err)?;
+        sqlx::query!(
+            r#"
+INSERT INTO sessions (
+    token, user_id, expires_at, family_id, refresh_token, refresh_expires_at,
+    user_agent, ip_addr, device_label
+)
+VALUES (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5,
+    $6,
+    $7,
+    $8,
+    $9
+)
+        "#,
+            &token,
+            &spent.user_id,
+            &expires_at,
+            &spent.family_id,
+            &refresh_token,
+            &refresh_expires_at,
+            spent.user_agent.as_ref(),
+            spent.ip_addr.as_ref(),
+            spent.device_label.as_ref(),
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(db_err)?;
+        tx.commit().await.map_err(db_err)?;
+        ctx.session_cache.invalidate([&spent.token]).await;
+
+        Ok(Response {
+            user_id: spent.user_id,
+            token,
+            expires_at,
+            refresh_token,
+            refresh_expires_at,
+        })
+    }
+}
+
+impl HttpEndpoint for Refresh {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/authenticate/refresh";
+
+    type HttpRequest = (Json<Request>,);
+
+    fn request((Json(req),): Self::HttpRequest) -> Result<Self::Request, Self::Error> {
+        Ok(req)
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for Refresh {
+    const TAG: &'static Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Exchange a refresh token for a new session. Refresh tokens are single use.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        [Self::Response {
+            user_id: Default::default(),
+            token: "mcpqwen8y3489nc8y2pf".into(),
+            expires_at: time::OffsetDateTime::now_utc(),
+            refresh_token: "nbqiwe7b34l9xh2i3hdw".into(),
+            refresh_expires_at: time::OffsetDateTime::now_utc(),
+        }]
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Self::Error>> {
+        vec![
+            ("Refresh token rejected", Error::TokenRejected),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            TokenRejected => Self::BAD_REQUEST,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+    use crate::Endpoint;
+
+    use axum::http;
+    use tower::ServiceExt;
+
+    async fn refresh(
+        ctx: &TestContext,
+        refresh_token: &str,
+    ) -> (http::StatusCode, serde_json::Value) {
+        let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+        let resp = app
+            .oneshot(
+                http::Request::builder()
+                    .method("POST")
+                    .uri("/authenticate/refresh")
+                    .header(axum::http::header::CONTENT_TYPE, "application/json")
+                    .body(
+                        serde_json::to_vec(&serde_json::json!({
+                            "refreshToken": refresh_token,
+                        }))
+                        .unwrap()
+                        .into(),
+                    )
+                    .unwrap_or_log(),
+            )
+            .await
+            .unwrap_or_log();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body())
+            .await
+            .unwrap_or_log();
+        (status, serde_json::from_slice(&body).unwrap_or_log())
+    }
+
+    async fn session_valid(ctx: &TestContext, token: &str) -> bool {
+        let app = crate::user::router().layer(axum::Extension(ctx.ctx()));
+        let resp = app
+            .oneshot(
+                http::Request::builder()
+                    .method("GET")
+                    .uri(format!("/users/{USER_01_ID}"))
+                    .header(axum::http::header::AUTHORIZATION, format!("Bearer {token}"))
+                    .body(Default::default())
+                    .unwrap_or_log(),
+            )
+            .await
+            .unwrap_or_log();
+        resp.status() == http::StatusCode::OK
+    }
+
+    async fn authenticate(ctx: &TestContext) -> crate::auth::authenticate::Tokens {
+        crate::auth::authenticate::Authenticate
+            .handle(
+                &ctx.ctx(),
+                crate::auth::authenticate::Request {
+                    identifier: USER_01_USERNAME.into(),
+                    password: "password".into(),
+                    device_label: None,
+                    client: None,
+                },
+            )
+            .await
+            .unwrap_or_log()
+            .into_tokens()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn refresh_rotates_tokens() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let auth = authenticate(&ctx).await;
+            let (status, body) = refresh(&ctx, &auth.refresh_token).await;
+            assert_eq!(status, http::StatusCode::OK, "{body:?}");
+            assert_eq!(body["userId"], USER_01_ID.to_string());
+            let token = body["token"].as_str().unwrap();
+            let refresh_token = body["refreshToken"].as_str().unwrap();
+            assert_ne!(token, auth.token);
+            assert_ne!(refresh_token, auth.refresh_token);
+            assert!(session_valid(&ctx, token).await);
+            assert!(!session_valid(&ctx, &auth.token).await);
+
+            let (status, _) = refresh(&ctx, refresh_token).await;
+            assert_eq!(status, http::StatusCode::OK);
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn refresh_reuse_revokes_family() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let auth = authenticate(&ctx).await;
+            let (status, body) = refresh(&ctx, &auth.refresh_token).await;
+            assert_eq!(status, http::StatusCode::OK);
+            let token = body["token"].as_str().unwrap();
+
+            let (status, body) = refresh(&ctx, &auth.refresh_token).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            check_json(
+                ("expected", &serde_json::json!({ "error": "tokenRejected" })),
+                ("response", &body),
+            );
+            assert!(!session_valid(&ctx, token).await);
+            // sessions from other authentications are left alone
+            assert!(session_valid(&ctx, USER_01_SESSION).await);
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn refresh_leaves_family_of_ended_sessions_alone() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let auth = authenticate(&ctx).await;
+            let db_pool = &ctx.ctx().db_pool;
+            // a sibling that's still around
+            sqlx::query(
+                r#"
+INSERT INTO sessions (token, user_id, expires_at, family_id)
+SELECT 'sibling', user_id, expires_at, family_id
+FROM sessions
+WHERE token = $1
+                "#,
+            )
+            .bind(&auth.token)
+            .execute(db_pool)
+            .await
+            .unwrap_or_log();
+            // as revocation and the sweeper do
+            sqlx::query("DELETE FROM sessions WHERE token = $1")
+                .bind(&auth.token)
+                .execute(db_pool)
+                .await
+                .unwrap_or_log();
+
+            let (status, _) = refresh(&ctx, &auth.refresh_token).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            let count = sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM sessions WHERE token = 'sibling'",
+            )
+            .fetch_one(db_pool)
+            .await
+            .unwrap_or_log();
+            assert_eq!(count, 1);
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn refresh_fails_if_token_unknown() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (status, body) = refresh(&ctx, &uuid::Uuid::new_v4().to_string()).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            check_json(
+                ("expected", &serde_json::json!({ "error": "tokenRejected" })),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+}
@@ -0,0 +1,205 @@
+use deps::*;
+
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+pub struct RequestPasswordReset;
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Request {
+    /// Email or username.
+    pub identifier: String,
+}
+
+#[derive(Debug, Serialize, thiserror::Error, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase", tag = "error")]
+pub enum Error {
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+pub type Response = NoContent;
+
+#[async_trait::async_trait]
+impl Endpoint for RequestPasswordReset {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let token = super::tokens::generate_token();
+        let expires_at = ctx
+            .clock
+            .now()
+            .saturating_add(ctx.config.password_reset_token_lifespan);
+        // looked up and stored in one statement so that unknown identifiers
+        // cost the same round trip
+        let email = sqlx::query_scalar!(
+            r#"
+WITH target AS (
+    SELECT id, email
+    FROM users
+    WHERE email = $1::TEXT::CITEXT OR username = $1::TEXT::CITEXT
+    LIMIT 1
+), inserted AS (
+    INSERT INTO password_reset_tokens (token_hash, user_id, expires_at)
+    SELECT $2, id, $3
+    FROM target
+)
+SELECT email::TEXT as "email!"
+FROM target
+        "#,
+            &request.identifier,
+            super::tokens::hash_token(&token),
+            &expires_at,
+        )
+        .fetch_optional(&ctx.db_pool)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
+        // don't disclose whether or not the account exists
+        let email = if let Some(email) = email {
+            email
+        } else {
+            tracing::debug!("no user found for password reset");
+            return Ok(NoContent);
+        };
+
+        // sent in the background, how long delivery takes would give away
+        // the account otherwise
+        let mailer = ctx.mailer.clone();
+        tokio::spawn(async move {
+            if let Err(err) = mailer
+                .send(crate::mail::Mail {
+                    to: email,
+                    subject: "Password reset".into(),
+                    body: format!(
+                        "Use the following token to reset your password. It expires at {expires_at}.\n\n{token}"
+                    ),
+                })
+                .await
+            {
+                tracing::error!(%err, "unable to send password reset mail");
+            }
+        });
+        Ok(NoContent)
+    }
+}
+
+impl HttpEndpoint for RequestPasswordReset {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/password-reset/request";
+    const SUCCESS_CODE: StatusCode = StatusCode::ACCEPTED;
+
+    type HttpRequest = (Json<Request>,);
+
+    fn request((Json(req),): Self::HttpRequest) -> Result<Self::Request, Self::Error> {
+        Ok(req)
+    }
+
+    fn response(_: Self::Response) -> axum::response::Response {
+        Default::default()
+    }
+}
+
+impl DocumentedEndpoint for RequestPasswordReset {
+    const TAG: &'static Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Mail a password reset token to the user. Succeeds even if no such user exists.";
+
+    fn errors() -> Vec<ErrorResponse<Self::Error>> {
+        vec![(
+            "Internal server error",
+            Error::Internal {
+                message: "internal server error".to_string(),
+            },
+        )]
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    macro_rules! request_password_reset_integ {
+        ($(
+            $name:ident: {
+                body: $json_body:expr,
+                $(extra_assertions: $extra_fn:expr,)?
+            },
+        )*) => {
+            mod integ {
+                use super::*;
+                crate::integration_table_tests! {
+                    $(
+                        $name: {
+                            uri: "/password-reset/request",
+                            method: "POST",
+                            status: StatusCode::ACCEPTED,
+                            router: crate::auth::router(),
+                            body: $json_body,
+                            $(extra_assertions: $extra_fn,)?
+                        },
+                    )*
+                }
+            }
+        };
+    }
+
+    request_password_reset_integ! {
+        works: {
+            body: serde_json::json!({
+                "identifier": USER_01_USERNAME,
+            }),
+            extra_assertions: &|EAArgs { ctx, .. }| {
+                Box::pin(async move {
+                    // let the mail go out
+                    tokio::task::yield_now().await;
+                    let outbox = ctx.mailer.outbox.lock().clone();
+                    assert_eq!(outbox.len(), 1);
+                    assert_eq!(outbox[0].to, USER_01_EMAIL);
+                    let count = sqlx::query_scalar::<_, i64>(
+                        "SELECT COUNT(*) FROM password_reset_tokens WHERE user_id = $1"
+                    )
+                        .bind(USER_01_ID)
+                        .fetch_one(&ctx.ctx().db_pool)
+                        .await
+                        .unwrap_or_log();
+                    assert_eq!(count, 1);
+                })
+            },
+        },
+        succeeds_if_user_not_found: {
+            body: serde_json::json!({
+                "identifier": "golden_eel",
+            }),
+            extra_assertions: &|EAArgs { ctx, .. }| {
+                Box::pin(async move {
+                    tokio::task::yield_now().await;
+                    assert!(ctx.mailer.outbox.lock().is_empty());
+                })
+            },
+        },
+    }
+}
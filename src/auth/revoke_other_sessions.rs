@@ -0,0 +1,155 @@
+use deps::*;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RevokeOtherSessions;
+
+#[derive(Debug)]
+pub struct Request {
+    pub auth_token: std::sync::Arc<str>,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+pub type Response = NoContent;
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for RevokeOtherSessions {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone(),
+            resource: crate::auth::Resource::Session { id: None },
+            action: crate::auth::Action::Delete,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let revoked = sqlx::query_scalar!(
+            r#"
+DELETE FROM sessions
+WHERE user_id = $1 AND token != $2
+RETURNING token
+            "#,
+            &accessing_user.user_id,
+            &request.auth_token[..],
+        )
+        .fetch_all(&ctx.db_pool)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
+        tracing::trace!(rows_affected = revoked.len());
+        ctx.session_cache.invalidate(revoked).await;
+        Ok(NoContent)
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for RevokeOtherSessions {
+    const METHOD: Method = Method::Delete;
+    const PATH: &'static str = "/sessions";
+    const SUCCESS_CODE: StatusCode = StatusCode::NO_CONTENT;
+
+    type HttpRequest = (BearerToken,);
+
+    fn request((BearerToken(token),): Self::HttpRequest) -> Result<Self::Request, Self::Error> {
+        Ok(self::Request { auth_token: token })
+    }
+
+    fn response(_: Self::Response) -> axum::response::Response {
+        Default::default()
+    }
+}
+
+impl DocumentedEndpoint for RevokeOtherSessions {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Revoke all of the accessing user's sessions except the one used to make the request.";
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    crate::integration_table_tests! {
+        revoke_other_sessions_works: {
+            uri: "/sessions",
+            method: "DELETE",
+            status: StatusCode::NO_CONTENT,
+            router: crate::auth::router(),
+            auth_token: USER_01_SESSION.into(),
+            extra_assertions: &|EAArgs { ctx, .. }| {
+                Box::pin(async move {
+                    for (token, id, expected) in [
+                        (USER_01_SESSION, USER_01_ID, http::StatusCode::OK),
+                        (USER_01_SESSION_2, USER_01_ID, http::StatusCode::UNAUTHORIZED),
+                        (USER_04_SESSION, USER_04_ID, http::StatusCode::OK),
+                    ] {
+                        let app = crate::user::router().layer(axum::Extension(ctx.ctx()));
+                        let resp = app
+                            .oneshot(
+                                http::Request::builder()
+                                    .method("GET")
+                                    .uri(format!("/users/{id}"))
+                                    .header(
+                                        axum::http::header::AUTHORIZATION,
+                                        format!("Bearer {token}"),
+                                    )
+                                    .body(Default::default())
+                                    .unwrap_or_log(),
+                            )
+                            .await
+                            .unwrap_or_log();
+                        assert_eq!(resp.status(), expected, "{token}");
+                    }
+                })
+            },
+        },
+    }
+}
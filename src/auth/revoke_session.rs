@@ -0,0 +1,214 @@
+use deps::*;
+
+use axum::extract::*;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RevokeSession;
+
+#[derive(Debug)]
+pub struct Request {
+    pub auth_token: std::sync::Arc<str>,
+    pub id: uuid::Uuid,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+pub type Response = NoContent;
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for RevokeSession {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone(),
+            resource: crate::auth::Resource::Session {
+                id: Some(request.id),
+            },
+            action: crate::auth::Action::Delete,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        // only sessions belonging to the accessing user are touched, making
+        // this idempotent and not leaking the existence of other's sessions
+        let tokens = sqlx::query_scalar!(
+            r#"
+DELETE FROM sessions
+WHERE id = $1 AND user_id = $2
+RETURNING token
+            "#,
+            &request.id,
+            &accessing_user.user_id
+        )
+        .fetch_all(&ctx.db_pool)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
+        tracing::trace!(rows_affected = tokens.len());
+        if !tokens.is_empty() {
+            ctx.session_cache.invalidate(&tokens).await;
+        }
+        Ok(NoContent)
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for RevokeSession {
+    const METHOD: Method = Method::Delete;
+    const PATH: &'static str = "/sessions/:id";
+    const SUCCESS_CODE: StatusCode = StatusCode::NO_CONTENT;
+
+    type HttpRequest = (BearerToken, Path<uuid::Uuid>);
+
+    fn request(
+        (BearerToken(auth_token), Path(id)): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(self::Request { auth_token, id })
+    }
+
+    fn response(_: Self::Response) -> axum::response::Response {
+        Default::default()
+    }
+}
+
+impl DocumentedEndpoint for RevokeSession {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str = "Revoke one of the accessing user's sessions by its id.";
+    const DESCRIPTION: &'static str = "Sessions are addressed by the `id` listed with them, \
+        not by their token, so that revoking one doesn't put the token in a URL.";
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    macro_rules! revoke_session_integ {
+        ($(
+            $name:ident: {
+                uri: $uri:expr,
+                auth_token: $auth_token:expr,
+                status: $status:expr,
+                $(extra_assertions: $extra_fn:expr,)?
+            },
+        )*) => {
+            mod integ {
+                use super::*;
+                crate::integration_table_tests! {
+                    $(
+                        $name: {
+                            uri: $uri,
+                            method: "DELETE",
+                            status: $status,
+                            router: crate::auth::router(),
+                            auth_token: $auth_token,
+                            $(extra_assertions: $extra_fn,)?
+                        },
+                    )*
+                }
+            }
+        };
+    }
+
+    revoke_session_integ! {
+        works: {
+            uri: format!("/sessions/{USER_01_SESSION_2_ID}"),
+            auth_token: USER_01_SESSION.into(),
+            status: StatusCode::NO_CONTENT,
+            extra_assertions: &|EAArgs { ctx, .. }| {
+                Box::pin(async move {
+                    for (token, expected) in [
+                        (USER_01_SESSION, http::StatusCode::OK),
+                        (USER_01_SESSION_2, http::StatusCode::UNAUTHORIZED),
+                    ] {
+                        let app = crate::user::router().layer(axum::Extension(ctx.ctx()));
+                        let resp = app
+                            .oneshot(
+                                http::Request::builder()
+                                    .method("GET")
+                                    .uri(format!("/users/{USER_01_ID}"))
+                                    .header(
+                                        axum::http::header::AUTHORIZATION,
+                                        format!("Bearer {token}"),
+                                    )
+                                    .body(Default::default())
+                                    .unwrap_or_log(),
+                            )
+                            .await
+                            .unwrap_or_log();
+                        assert_eq!(resp.status(), expected, "{token}");
+                    }
+                })
+            },
+        },
+        ignores_sessions_of_others: {
+            uri: format!("/sessions/{USER_04_SESSION_ID}"),
+            auth_token: USER_01_SESSION.into(),
+            status: StatusCode::NO_CONTENT,
+            extra_assertions: &|EAArgs { ctx, .. }| {
+                Box::pin(async move {
+                    let app = crate::user::router().layer(axum::Extension(ctx.ctx()));
+                    let resp = app
+                        .oneshot(
+                            http::Request::builder()
+                                .method("GET")
+                                .uri(format!("/users/{USER_04_ID}"))
+                                .header(
+                                    axum::http::header::AUTHORIZATION,
+                                    format!("Bearer {USER_04_SESSION}"),
+                                )
+                                .body(Default::default())
+                                .unwrap_or_log(),
+                        )
+                        .await
+                        .unwrap_or_log();
+                    assert_eq!(resp.status(), http::StatusCode::OK);
+                })
+            },
+        },
+    }
+}
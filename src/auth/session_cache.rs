@@ -0,0 +1,259 @@
+//! Read-through cache of session lookups for [`Authorize`](super::authorize::Authorize).
+//!
+//! Backed by Redis when a pool is provided, otherwise by an in-process LRU
+//! which is only suitable when running a single instance.
+//!
+//! Also holds the list of revoked JWT access tokens, see
+//! [`is_revoked`](SessionCache::is_revoked).
+
+use deps::*;
+
+use bb8_redis::{bb8, redis, RedisConnectionManager};
+
+pub type RedisPool = bb8::Pool<RedisConnectionManager>;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct CachedSession {
+    pub user_id: uuid::Uuid,
+    #[serde(with = "time::serde::timestamp")]
+    pub expires_at: time::OffsetDateTime,
+    #[serde(default)]
+    pub impersonator_id: Option<uuid::Uuid>,
+}
+
+#[derive(Debug)]
+pub struct SessionCache {
+    redis: Option<RedisPool>,
+    local: parking_lot::Mutex<lru::LruCache<String, (CachedSession, time::OffsetDateTime)>>,
+    /// How long an entry may be served before going back to the db. Bounds
+    /// the staleness of `last_seen_at`.
+    ttl: time::Duration,
+    /// Revoked JWTs mapped to their expiry, after which they're rejected
+    /// regardless.
+    revoked: parking_lot::Mutex<std::collections::HashMap<String, time::OffsetDateTime>>,
+    /// High water mark of `sessions_deleted.deleted_at` seen by
+    /// [`sync_revocations`](SessionCache::sync_revocations).
+    revocations_synced_at: parking_lot::Mutex<time::OffsetDateTime>,
+}
+
+impl SessionCache {
+    pub fn new(capacity: std::num::NonZeroUsize, ttl: time::Duration) -> Self {
+        Self {
+            redis: None,
+            local: parking_lot::Mutex::new(lru::LruCache::new(capacity)),
+            ttl,
+            revoked: Default::default(),
+            revocations_synced_at: parking_lot::Mutex::new(time::OffsetDateTime::now_utc()),
+        }
+    }
+
+    pub fn with_redis(self, pool: RedisPool) -> Self {
+        Self {
+            redis: Some(pool),
+            ..self
+        }
+    }
+
+    fn redis_key(token: &str) -> String {
+        format!("session:{token}")
+    }
+
+    /// Errors talking to Redis are logged and treated as misses.
+    pub async fn get(&self, token: &str) -> Option<CachedSession> {
+        let now = time::OffsetDateTime::now_utc();
+        let session = if let Some(pool) = &self.redis {
+            let result: Result<Option<String>, String> = async {
+                let mut conn = pool.get().await.map_err(|err| format!("{err}"))?;
+                redis::AsyncCommands::get(&mut *conn, Self::redis_key(token))
+                    .await
+                    .map_err(|err| format!("{err}"))
+            }
+            .await;
+            match result {
+                Ok(Some(json)) => serde_json::from_str(&json)
+                    .map_err(|err| tracing::warn!(%err, "corrupt session cache entry"))
+                    .ok(),
+                Ok(None) => None,
+                Err(err) => {
+                    tracing::warn!(%err, "session cache unavailable");
+                    None
+                }
+            }
+        } else {
+            let mut local = self.local.lock();
+            match local.get(token) {
+                Some((_, cached_until)) if *cached_until < now => {
+                    local.pop(token);
+                    None
+                }
+                Some((session, _)) => Some(session.clone()),
+                None => None,
+            }
+        };
+        match session {
+            Some(session) if session.expires_at < now => {
+                self.invalidate([token]).await;
+                None
+            }
+            session => session,
+        }
+    }
+
+    pub async fn insert(&self, token: &str, session: CachedSession) {
+        let now = time::OffsetDateTime::now_utc();
+        // never outlive the session itself
+        let ttl = std::cmp::min(self.ttl, session.expires_at - now);
+        if ttl <= time::Duration::ZERO {
+            return;
+        }
+        if let Some(pool) = &self.redis {
+            let result: Result<(), String> = async {
+                let mut conn = pool.get().await.map_err(|err| format!("{err}"))?;
+                let json = serde_json::to_string(&session).map_err(|err| format!("{err}"))?;
+                redis::AsyncCommands::set_ex(
+                    &mut *conn,
+                    Self::redis_key(token),
+                    json,
+                    ttl.whole_seconds().max(1) as usize,
+                )
+                .await
+                .map_err(|err| format!("{err}"))
+            }
+            .await;
+            if let Err(err) = result {
+                tracing::warn!(%err, "session cache unavailable");
+            }
+        } else {
+            self.local.lock().put(token.into(), (session, now + ttl));
+        }
+    }
+
+    /// Call this whenever sessions are removed from the db.
+    pub async fn invalidate<T: AsRef<str>>(&self, tokens: impl IntoIterator<Item = T>) {
+        let tokens = tokens.into_iter().collect::<Vec<_>>();
+        self.revoke(tokens.iter().map(|token| token.as_ref()));
+        if let Some(pool) = &self.redis {
+            let keys = tokens
+                .iter()
+                .map(|token| Self::redis_key(token.as_ref()))
+                .collect::<Vec<_>>();
+            if keys.is_empty() {
+                return;
+            }
+            let result: Result<(), String> = async {
+                let mut conn = pool.get().await.map_err(|err| format!("{err}"))?;
+                redis::AsyncCommands::del(&mut *conn, keys)
+                    .await
+                    .map_err(|err| format!("{err}"))
+            }
+            .await;
+            if let Err(err) = result {
+                // entries will still expire on their own after `ttl`
+                tracing::error!(%err, "unable to invalidate session cache");
+            }
+        } else {
+            let mut local = self.local.lock();
+            for token in tokens {
+                local.pop(token.as_ref());
+            }
+        }
+    }
+
+    /// Opaque tokens are ignored, they're checked against the db anyway.
+    fn revoke<'a>(&self, tokens: impl Iterator<Item = &'a str>) {
+        let mut revoked = self.revoked.lock();
+        for token in tokens {
+            if !super::jwt::looks_like_jwt(token) {
+                continue;
+            }
+            if let Some(expires_at) = super::jwt::unverified_expiry(token) {
+                revoked.insert(token.into(), expires_at);
+            }
+        }
+    }
+
+    /// Only covers revocations made by this instance and those picked up by
+    /// [`sync_revocations`](SessionCache::sync_revocations).
+    pub fn is_revoked(&self, token: &str) -> bool {
+        let now = time::OffsetDateTime::now_utc();
+        let mut revoked = self.revoked.lock();
+        // expired tokens fail verification, no need to remember them
+        revoked.retain(|_, expires_at| *expires_at > now);
+        revoked.contains_key(token)
+    }
+
+    /// Picks up sessions deleted by other instances since the last sync.
+    pub async fn sync_revocations(
+        &self,
+        db_pool: &sqlx::postgres::PgPool,
+    ) -> Result<(), sqlx::Error> {
+        // `deleted_at` is the start of the deleting transaction which may
+        // commit after a later sync, the overlap catches those
+        let since = *self.revocations_synced_at.lock() - time::Duration::minutes(1);
+        let deleted = sqlx::query!(
+            r#"
+SELECT token, deleted_at
+FROM sessions_deleted
+WHERE deleted_at >= $1 AND expires_at > CURRENT_TIMESTAMP
+            "#,
+            &since,
+        )
+        .fetch_all(db_pool)
+        .await?;
+        if let Some(latest) = deleted.iter().map(|row| row.deleted_at).max() {
+            *self.revocations_synced_at.lock() = latest;
+        }
+        self.revoke(deleted.iter().map(|row| &row.token[..]));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(expires_in: time::Duration) -> CachedSession {
+        CachedSession {
+            user_id: crate::user::testing::USER_01_ID,
+            expires_at: time::OffsetDateTime::now_utc() + expires_in,
+            impersonator_id: None,
+        }
+    }
+
+    fn cache(ttl: time::Duration) -> SessionCache {
+        SessionCache::new(std::num::NonZeroUsize::new(2).unwrap(), ttl)
+    }
+
+    #[tokio::test]
+    async fn local_cache_works() {
+        let cache = cache(time::Duration::minutes(1));
+        let entry = session(time::Duration::hours(1));
+        cache.insert("one", entry.clone()).await;
+        assert_eq!(cache.get("one").await, Some(entry));
+        cache.invalidate(["one"]).await;
+        assert_eq!(cache.get("one").await, None);
+    }
+
+    #[tokio::test]
+    async fn local_cache_evicts_least_recently_used() {
+        let cache = cache(time::Duration::minutes(1));
+        for token in ["one", "two", "three"] {
+            cache.insert(token, session(time::Duration::hours(1))).await;
+        }
+        assert_eq!(cache.get("one").await, None);
+        assert!(cache.get("three").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn local_cache_skips_expired() {
+        let cache = cache(time::Duration::minutes(1));
+        cache
+            .insert("one", session(-time::Duration::seconds(1)))
+            .await;
+        assert_eq!(cache.get("one").await, None);
+        let cache = self::cache(time::Duration::ZERO);
+        cache.insert("one", session(time::Duration::hours(1))).await;
+        assert_eq!(cache.get("one").await, None);
+    }
+}
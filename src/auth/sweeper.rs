@@ -0,0 +1,177 @@
+//! Moves expired sessions into `sessions_deleted`.
+//!
+//! Sessions are archived by the `archive_deleted_sessions` trigger. A session
+//! is only considered expired once both its token and refresh token are.
+
+use deps::*;
+
+#[derive(Debug, Default)]
+pub struct SweepMetrics {
+    pub runs: std::sync::atomic::AtomicU64,
+    pub failed_runs: std::sync::atomic::AtomicU64,
+    pub rows_swept: std::sync::atomic::AtomicU64,
+}
+
+impl SweepMetrics {
+    /// Reports the totals since startup.
+    pub fn log(&self) {
+        use std::sync::atomic::Ordering;
+        tracing::info!(
+            target: "metrics",
+            runs = self.runs.load(Ordering::Relaxed),
+            failed_runs = self.failed_runs.load(Ordering::Relaxed),
+            rows_swept = self.rows_swept.load(Ordering::Relaxed),
+            "session sweeper"
+        );
+    }
+}
+
+pub static METRICS: SweepMetrics = SweepMetrics {
+    runs: std::sync::atomic::AtomicU64::new(0),
+    failed_runs: std::sync::atomic::AtomicU64::new(0),
+    rows_swept: std::sync::atomic::AtomicU64::new(0),
+};
+
+/// Returns the number of rows swept.
+#[tracing::instrument(skip(db_pool))]
+pub async fn sweep_expired_sessions(
+    db_pool: &sqlx::postgres::PgPool,
+    batch_size: u32,
+) -> Result<u64, sqlx::Error> {
+    use std::sync::atomic::Ordering;
+    let mut swept = 0;
+    let result = loop {
+        // batching keeps each statement's locks and transaction short
+        let result = sqlx::query!(
+            r#"
+DELETE FROM sessions
+WHERE token IN (
+    SELECT token
+    FROM sessions
+    WHERE expires_at < CURRENT_TIMESTAMP
+        AND (refresh_expires_at IS NULL OR refresh_expires_at < CURRENT_TIMESTAMP)
+    LIMIT $1
+    FOR UPDATE SKIP LOCKED
+)
+            "#,
+            batch_size as i64
+        )
+        .execute(db_pool)
+        .await;
+        match result {
+            Ok(result) => {
+                swept += result.rows_affected();
+                METRICS
+                    .rows_swept
+                    .fetch_add(result.rows_affected(), Ordering::Relaxed);
+                if result.rows_affected() < batch_size as u64 {
+                    break Ok(swept);
+                }
+            }
+            Err(err) => break Err(err),
+        }
+    };
+    METRICS.runs.fetch_add(1, Ordering::Relaxed);
+    match &result {
+        Ok(swept) => tracing::info!(swept, "swept expired sessions"),
+        Err(err) => {
+            METRICS.failed_runs.fetch_add(1, Ordering::Relaxed);
+            tracing::error!(%err, swept, "error sweeping expired sessions");
+        }
+    }
+    result
+}
+
+/// Sweeps every `interval` until the returned handle is aborted.
+pub fn spawn_session_sweeper(
+    ctx: crate::SharedContext,
+    interval: time::Duration,
+    batch_size: u32,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(
+            interval
+                .try_into()
+                .expect("sweep interval must not be negative"),
+        );
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            // errors are logged and counted, just try again next time
+            let _ = sweep_expired_sessions(&ctx.db_pool, batch_size).await;
+            METRICS.log();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    #[tokio::test]
+    async fn sweep_expired_sessions_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let db_pool = &ctx.ctx().db_pool;
+            for (token, expires_at, refresh_expires_at) in [
+                ("expired_1", "-1 day", None),
+                ("expired_2", "-1 hour", Some("-1 hour")),
+                ("expired_3", "-1 minute", None),
+                ("refreshable", "-1 day", Some("1 day")),
+            ] {
+                sqlx::query(
+                    r#"
+INSERT INTO sessions (token, user_id, expires_at, refresh_expires_at)
+VALUES (
+    $1,
+    $2,
+    CURRENT_TIMESTAMP + $3::INTERVAL,
+    CURRENT_TIMESTAMP + $4::INTERVAL
+)
+                    "#,
+                )
+                .bind(token)
+                .bind(USER_01_ID)
+                .bind(expires_at)
+                .bind(refresh_expires_at)
+                .execute(db_pool)
+                .await
+                .unwrap_or_log();
+            }
+            let runs_before = super::METRICS
+                .runs
+                .load(std::sync::atomic::Ordering::Relaxed);
+
+            let swept = super::sweep_expired_sessions(db_pool, 2)
+                .await
+                .unwrap_or_log();
+            assert_eq!(swept, 3);
+            assert!(
+                super::METRICS
+                    .runs
+                    .load(std::sync::atomic::Ordering::Relaxed)
+                    > runs_before
+            );
+
+            let remaining = sqlx::query_scalar::<_, String>("SELECT token FROM sessions")
+                .fetch_all(db_pool)
+                .await
+                .unwrap_or_log();
+            assert!(remaining.contains(&"refreshable".to_string()));
+            assert!(remaining.contains(&USER_01_SESSION.to_string()));
+            assert!(!remaining.iter().any(|token| token.starts_with("expired")));
+            let archived = sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM sessions_deleted WHERE token LIKE 'expired_%'",
+            )
+            .fetch_one(db_pool)
+            .await
+            .unwrap_or_log();
+            assert_eq!(archived, 3);
+        }
+        ctx.close().await;
+    }
+}
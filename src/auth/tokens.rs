@@ -0,0 +1,36 @@
+//! Opaque secrets that are only stored hashed.
+
+use deps::*;
+
+/// 32 random bytes, URL safe base64 encoded.
+pub fn generate_token() -> String {
+    let bytes: [u8; 32] = rand::random();
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Tokens carry enough entropy not to need salting or a slow hash which
+/// keeps them lookup-able.
+pub fn hash_token(token: &str) -> String {
+    use sha2::Digest;
+    base64::encode_config(
+        sha2::Sha256::digest(token.as_bytes()),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+/// Marks bearer tokens that are API keys rather than session tokens.
+pub const API_KEY_PREFIX: &str = "wak_";
+
+/// Returns the key and its displayable prefix. Keys look like
+/// `wak_3f9c21ab_<token>`.
+pub fn generate_api_key() -> (String, String) {
+    let id: [u8; 4] = rand::random();
+    let prefix = format!(
+        "{API_KEY_PREFIX}{}",
+        id.iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>()
+    );
+    let key = format!("{prefix}_{}", generate_token());
+    (key, prefix)
+}
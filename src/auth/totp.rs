@@ -0,0 +1,172 @@
+//! Time-based one-time passwords as per RFC 6238 using the parameters
+//! authenticator apps assume by default: HMAC-SHA1, 6 digits and 30 second
+//! steps.
+
+use deps::*;
+
+pub const DIGITS: u32 = 6;
+pub const STEP_SECS: i64 = 30;
+/// How many steps a code may be off by to make up for clock drift.
+pub const ALLOWED_SKEW: i64 = 1;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// 20 random bytes, base32 encoded without padding.
+pub fn generate_secret() -> String {
+    let bytes: [u8; 20] = rand::random();
+    data_encoding::BASE32_NOPAD.encode(&bytes)
+}
+
+pub fn step_at(now: time::OffsetDateTime) -> i64 {
+    now.unix_timestamp().div_euclid(STEP_SECS)
+}
+
+/// `None` if `secret` isn't valid base32.
+pub fn code_at_step(secret: &str, step: i64) -> Option<String> {
+    use hmac::Mac;
+    let key = data_encoding::BASE32_NOPAD
+        .decode(secret.trim_end_matches('=').as_bytes())
+        .ok()?;
+    let mut mac = hmac::Hmac::<sha1::Sha1>::new_from_slice(&key).ok()?;
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    // dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    Some(format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// Returns the step `code` was generated for if it's valid around `now`.
+/// Steps at or before `last_used_step` are rejected so that codes can't be
+/// replayed.
+pub fn verify(
+    secret: &str,
+    code: &str,
+    now: time::OffsetDateTime,
+    last_used_step: Option<i64>,
+) -> Option<i64> {
+    let code = code.trim();
+    let current = step_at(now);
+    (current - ALLOWED_SKEW..=current + ALLOWED_SKEW)
+        .filter(|step| !matches!(last_used_step, Some(last) if *step <= last))
+        .find(|step| {
+            code_at_step(secret, *step)
+                .map(|expected| constant_time_eq(expected.as_bytes(), code.as_bytes()))
+                .unwrap_or(false)
+        })
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The `otpauth://` URI authenticator apps scan from QR codes.
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = percent_encode(issuer);
+    let account = percent_encode(account);
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECS}"
+    )
+}
+
+fn percent_encode(src: &str) -> String {
+    src.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// 80 random bits formatted as `xxxx-xxxx-xxxx-xxxx`.
+pub fn generate_recovery_code() -> String {
+    let bytes: [u8; 10] = rand::random();
+    let encoded = data_encoding::BASE32_NOPAD
+        .encode(&bytes)
+        .to_ascii_lowercase();
+    encoded
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| std::str::from_utf8(chunk).unwrap())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Recovery codes are hashed like [`tokens`](super::tokens) after dropping
+/// formatting so that users can type them in however.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized = code
+        .chars()
+        .filter(|ch| ch.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    super::tokens::hash_token(&normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The RFC 6238 SHA1 test key, "12345678901234567890".
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    crate::table_tests! {
+        totp_code_at,
+        (unix_time, expected),
+        {
+            let now = time::OffsetDateTime::from_unix_timestamp(unix_time).unwrap();
+            assert_eq!(code_at_step(RFC_SECRET, step_at(now)).unwrap(), expected);
+        }
+    }
+
+    // the RFC vectors are 8 digits, these are their last 6
+    totp_code_at! {
+        rfc_59: (59, "287082"),
+        rfc_1111111109: (1111111109, "081804"),
+        rfc_1111111111: (1111111111, "050471"),
+        rfc_1234567890: (1234567890, "005924"),
+        rfc_2000000000: (2000000000, "279037"),
+        rfc_20000000000: (20000000000, "353130"),
+    }
+
+    #[test]
+    fn totp_verify_allows_skew() {
+        let now = time::OffsetDateTime::from_unix_timestamp(1111111111).unwrap();
+        let step = step_at(now);
+        for offset in [-1, 0, 1] {
+            let code = code_at_step(RFC_SECRET, step + offset).unwrap();
+            assert_eq!(verify(RFC_SECRET, &code, now, None), Some(step + offset));
+        }
+        let code = code_at_step(RFC_SECRET, step + 2).unwrap();
+        assert_eq!(verify(RFC_SECRET, &code, now, None), None);
+    }
+
+    #[test]
+    fn totp_verify_rejects_replays() {
+        let now = time::OffsetDateTime::from_unix_timestamp(1111111111).unwrap();
+        let step = step_at(now);
+        let code = code_at_step(RFC_SECRET, step).unwrap();
+        assert_eq!(verify(RFC_SECRET, &code, now, Some(step)), None);
+        assert_eq!(verify(RFC_SECRET, &code, now, Some(step - 1)), Some(step));
+    }
+
+    #[test]
+    fn totp_recovery_code_hash_ignores_formatting() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 19);
+        assert_eq!(
+            hash_recovery_code(&code),
+            hash_recovery_code(&code.replace('-', "").to_uppercase())
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use deps::*;
+
+use crate::utils::*;
+use crate::*;
+
+use serde::Deserialize;
+use validator::Validate;
+
+use super::{Action, ApiKey};
+
+#[derive(Clone, Copy, Debug)]
+pub struct UpdateApiKey;
+
+#[derive(Debug, Deserialize, Validate, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Request {
+    #[serde(skip)]
+    pub auth_token: Option<std::sync::Arc<str>>,
+    #[serde(skip)]
+    pub user_id: Option<uuid::Uuid>,
+    #[serde(skip)]
+    pub id: Option<uuid::Uuid>,
+    #[validate(length(min = 1, max = 64))]
+    pub name: Option<String>,
+    #[validate(length(min = 1))]
+    pub scopes: Option<Vec<Action>>,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("not found at id: {id:?}")]
+    NotFound { id: uuid::Uuid },
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("invalid input: {issues:?}")]
+    InvalidInput {
+        #[from]
+        issues: ValidationErrors,
+    },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+pub type Response = ApiKey;
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for UpdateApiKey {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone().unwrap(),
+            resource: crate::auth::Resource::ApiKeys {
+                user_id: request.user_id.unwrap(),
+            },
+            action: crate::auth::Action::Write,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let (user_id, id) = (request.user_id.unwrap(), request.id.unwrap());
+        if accessing_user.user_id != user_id {
+            return Err(Error::AccessDenied);
+        }
+        validator::Validate::validate(&request).map_err(utils::ValidationErrors::from)?;
+        let scopes = request.scopes.map(|scopes| {
+            let mut scopes = scopes
+                .iter()
+                .map(|scope| scope.as_str().to_string())
+                .collect::<Vec<_>>();
+            scopes.sort();
+            scopes.dedup();
+            scopes
+        });
+        sqlx::query_as!(
+            super::ApiKeyRow,
+            r#"
+UPDATE api_keys
+SET name = COALESCE($3, name), scopes = COALESCE($4, scopes)
+WHERE id = $1 AND user_id = $2
+RETURNING id, user_id, name, prefix, scopes, expires_at, last_used_at, created_at, updated_at
+            "#,
+            &id,
+            &user_id,
+            request.name.as_deref(),
+            scopes.as_deref(),
+        )
+        .fetch_one(&ctx.db_pool)
+        .await
+        .map(ApiKey::from)
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => Error::NotFound { id },
+            _ => Error::Internal {
+                message: format!("db error: {err}"),
+            },
+        })
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            NotFound { .. } => Self::NOT_FOUND,
+            AccessDenied => Self::UNAUTHORIZED,
+            InvalidInput { .. } => Self::BAD_REQUEST,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for UpdateApiKey {
+    const METHOD: Method = Method::Patch;
+    const PATH: &'static str = "/users/:id/api-keys/:keyId";
+
+    type HttpRequest = (BearerToken, Path<(uuid::Uuid, uuid::Uuid)>, Json<Request>);
+
+    fn request(
+        (BearerToken(token), Path((user_id, id)), Json(req)): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            auth_token: Some(token),
+            user_id: Some(user_id),
+            id: Some(id),
+            ..req
+        })
+    }
+
+    fn response(resp: Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for UpdateApiKey {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str = "Rename one of the user's API keys or change its scopes.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        super::get_api_key::GetApiKey::success_examples()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Not found",
+                Error::NotFound {
+                    id: Default::default(),
+                },
+            ),
+            (
+                "Invalid input",
+                Error::InvalidInput {
+                    issues: {
+                        let mut issues = validator::ValidationErrors::new();
+                        issues.add(
+                            "name",
+                            validator::ValidationError {
+                                code: std::borrow::Cow::from("length"),
+                                message: None,
+                                params: [(std::borrow::Cow::from("value"), serde_json::json!(""))]
+                                    .into_iter()
+                                    .collect(),
+                            },
+                        );
+                        issues.into()
+                    },
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+    use crate::AuthenticatedEndpoint;
+
+    #[tokio::test]
+    async fn update_api_key_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let created = AuthenticatedEndpoint::handle(
+                &crate::auth::create_api_key::CreateApiKey,
+                &ctx.ctx(),
+                accessing(USER_01_ID),
+                crate::auth::create_api_key::Request {
+                    auth_token: Some(USER_01_SESSION.into()),
+                    user_id: Some(USER_01_ID),
+                    name: "CI".into(),
+                    scopes: vec![crate::auth::Action::Read],
+                    expires_at: None,
+                },
+            )
+            .await
+            .unwrap_or_log();
+            let updated = AuthenticatedEndpoint::handle(
+                &super::UpdateApiKey,
+                &ctx.ctx(),
+                accessing(USER_01_ID),
+                super::Request {
+                    auth_token: Some(USER_01_SESSION.into()),
+                    user_id: Some(USER_01_ID),
+                    id: Some(created.api_key.id),
+                    name: None,
+                    scopes: Some(vec![crate::auth::Action::Write, crate::auth::Action::Read]),
+                },
+            )
+            .await
+            .unwrap_or_log();
+            assert_eq!(updated.name, "CI");
+            assert_eq!(
+                updated.scopes,
+                vec![crate::auth::Action::Read, crate::auth::Action::Write]
+            );
+        }
+        ctx.close().await;
+    }
+}
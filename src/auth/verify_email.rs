@@ -0,0 +1,282 @@
+use deps::*;
+
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+pub struct VerifyEmail;
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Request {
+    /// As mailed on account creation or email change.
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, thiserror::Error, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase", tag = "error")]
+pub enum Error {
+    #[error("invalid or expired token")]
+    InvalidToken,
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+pub type Response = NoContent;
+
+/// Mail a verification token for `email` to `email`. Failures to send are
+/// only logged, the user can always request another by changing the email.
+#[tracing::instrument(skip(ctx))]
+pub async fn send_verification(
+    ctx: &crate::Context,
+    user_id: uuid::Uuid,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    let token = super::tokens::generate_token();
+    let expires_at = time::OffsetDateTime::now_utc()
+        .saturating_add(ctx.config.email_verification_token_lifespan);
+    sqlx::query!(
+        r#"
+INSERT INTO email_verification_tokens (token_hash, user_id, email, expires_at)
+VALUES ($1, $2, $3::TEXT::CITEXT, $4)
+        "#,
+        super::tokens::hash_token(&token),
+        &user_id,
+        email,
+        &expires_at,
+    )
+    .execute(&ctx.db_pool)
+    .await?;
+
+    if let Err(err) = ctx
+        .mailer
+        .send(crate::mail::Mail {
+            to: email.into(),
+            subject: "Verify your email".into(),
+            body: format!(
+                "Use the following token to verify your email. It expires at {expires_at}.\n\n{token}"
+            ),
+        })
+        .await
+    {
+        tracing::error!(%err, "unable to send email verification mail");
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl Endpoint for VerifyEmail {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let db_err = |err| Error::Internal {
+            message: format!("db error: {err}"),
+        };
+        let mut tx = ctx.db_pool.begin().await.map_err(db_err)?;
+        // deleting spends the token
+        let spent = sqlx::query!(
+            r#"
+DELETE FROM email_verification_tokens
+WHERE token_hash = $1
+RETURNING user_id, email::TEXT as "email!", expires_at
+            "#,
+            super::tokens::hash_token(&request.token),
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(db_err)?;
+        let spent = match spent {
+            Some(spent) if spent.expires_at > time::OffsetDateTime::now_utc() => spent,
+            Some(_) => {
+                tx.commit().await.map_err(db_err)?;
+                return Err(Error::InvalidToken);
+            }
+            None => return Err(Error::InvalidToken),
+        };
+        // tokens for an address the user has since moved away from are no good
+        let verified = sqlx::query!(
+            r#"
+UPDATE users
+SET email_verified_at = CURRENT_TIMESTAMP
+WHERE id = $1 AND email = $2::TEXT::CITEXT
+            "#,
+            &spent.user_id,
+            &spent.email,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(db_err)?;
+        tx.commit().await.map_err(db_err)?;
+        if verified.rows_affected() == 0 {
+            return Err(Error::InvalidToken);
+        }
+        Ok(NoContent)
+    }
+}
+
+impl HttpEndpoint for VerifyEmail {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/verify-email";
+    const SUCCESS_CODE: StatusCode = StatusCode::NO_CONTENT;
+
+    type HttpRequest = (Json<Request>,);
+
+    fn request((Json(req),): Self::HttpRequest) -> Result<Self::Request, Self::Error> {
+        Ok(req)
+    }
+
+    fn response(_: Self::Response) -> axum::response::Response {
+        Default::default()
+    }
+}
+
+impl DocumentedEndpoint for VerifyEmail {
+    const TAG: &'static Tag = &super::TAG;
+    const SUMMARY: &'static str = "Mark the email of a user verified using a verification token.";
+
+    fn errors() -> Vec<ErrorResponse<Self::Error>> {
+        vec![
+            ("Invalid or expired token", Error::InvalidToken),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            InvalidToken => Self::BAD_REQUEST,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    use axum::http;
+    use tower::ServiceExt;
+
+    async fn verify(ctx: &TestContext, token: &str) -> (http::StatusCode, serde_json::Value) {
+        let app = crate::auth::router().layer(axum::Extension(ctx.ctx()));
+        let resp = app
+            .oneshot(
+                http::Request::builder()
+                    .method("POST")
+                    .uri("/verify-email")
+                    .header(axum::http::header::CONTENT_TYPE, "application/json")
+                    .body(
+                        serde_json::to_vec(&serde_json::json!({ "token": token }))
+                            .unwrap()
+                            .into(),
+                    )
+                    .unwrap_or_log(),
+            )
+            .await
+            .unwrap_or_log();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body())
+            .await
+            .unwrap_or_log();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    async fn verified_at(ctx: &TestContext, id: uuid::Uuid) -> Option<time::OffsetDateTime> {
+        sqlx::query_scalar("SELECT email_verified_at FROM users WHERE id = $1")
+            .bind(id)
+            .fetch_one(&ctx.ctx().db_pool)
+            .await
+            .unwrap_or_log()
+    }
+
+    fn last_token(ctx: &TestContext) -> String {
+        let body = ctx.mailer.outbox.lock().last().unwrap().body.clone();
+        body.lines().last().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn verify_email_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            super::send_verification(&ctx.ctx(), USER_03_ID, USER_03_EMAIL)
+                .await
+                .unwrap_or_log();
+            assert_eq!(ctx.mailer.outbox.lock().last().unwrap().to, USER_03_EMAIL);
+            let token = last_token(&ctx);
+
+            let (status, body) = verify(&ctx, &token).await;
+            assert_eq!(status, http::StatusCode::NO_CONTENT, "{body:?}");
+            assert!(verified_at(&ctx, USER_03_ID).await.is_some());
+
+            // single use
+            let (status, body) = verify(&ctx, &token).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            check_json(
+                ("expected", &serde_json::json!({ "error": "invalidToken" })),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn verify_email_rejects_stale_address() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            super::send_verification(&ctx.ctx(), USER_03_ID, USER_03_EMAIL)
+                .await
+                .unwrap_or_log();
+            let token = last_token(&ctx);
+            sqlx::query("UPDATE users SET email = 'moved@away.com' WHERE id = $1")
+                .bind(USER_03_ID)
+                .execute(&ctx.ctx().db_pool)
+                .await
+                .unwrap_or_log();
+
+            let (status, _) = verify(&ctx, &token).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            assert!(verified_at(&ctx, USER_03_ID).await.is_none());
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn verify_email_rejects_expired() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            super::send_verification(&ctx.ctx(), USER_03_ID, USER_03_EMAIL)
+                .await
+                .unwrap_or_log();
+            let token = last_token(&ctx);
+            sqlx::query(
+                "UPDATE email_verification_tokens SET expires_at = CURRENT_TIMESTAMP - interval '1 minute'",
+            )
+            .execute(&ctx.ctx().db_pool)
+            .await
+            .unwrap_or_log();
+
+            let (status, _) = verify(&ctx, &token).await;
+            assert_eq!(status, http::StatusCode::BAD_REQUEST);
+            assert!(verified_at(&ctx, USER_03_ID).await.is_none());
+        }
+        ctx.close().await;
+    }
+}
@@ -0,0 +1,25 @@
+use deps::*;
+
+use template_rust_web_api::*;
+
+/// Sweeps expired sessions once and exits.
+fn main() {
+    dotenvy::dotenv().ok();
+    setup_tracing().unwrap();
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap_or_log()
+        .block_on(async {
+            let batch_size = std::env::var("SESSION_SWEEP_BATCH_SIZE")
+                .map(|size| size.parse().unwrap_or_log())
+                .unwrap_or(1000);
+            let db_url = std::env::var("DATABASE_URL").unwrap_or_log();
+            let db_pool = sqlx::PgPool::connect(&db_url).await.unwrap_or_log();
+            // the count gets logged by the sweeper itself
+            auth::sweeper::sweep_expired_sessions(&db_pool, batch_size)
+                .await
+                .unwrap_or_log();
+        })
+}
@@ -14,20 +14,230 @@ fn main() {
         .unwrap_or_log()
         .block_on(async {
             let config = Config {
-                pass_salt_hash: uuid::Uuid::new_v4().as_bytes().to_vec(),
-                argon2_conf: argon2::Config::default(),
-                auth_token_lifespan: time::Duration::new(
-                    std::env::var("AUTH_TOKEN_LIFESPAN_SECS")
-                        .unwrap_or_log()
-                        .parse()
-                        .unwrap_or_log(),
+                pass_pepper: std::env::var("PASS_PEPPER").ok().map(String::into_bytes),
+                argon2_conf: {
+                    let defaults = argon2::Config::default();
+                    argon2::Config {
+                        mem_cost: std::env::var("ARGON2_MEM_COST_KB")
+                            .map(|kb| kb.parse().unwrap_or_log())
+                            .unwrap_or(defaults.mem_cost),
+                        time_cost: std::env::var("ARGON2_TIME_COST")
+                            .map(|passes| passes.parse().unwrap_or_log())
+                            .unwrap_or(defaults.time_cost),
+                        lanes: std::env::var("ARGON2_LANES")
+                            .map(|lanes| lanes.parse().unwrap_or_log())
+                            .unwrap_or(defaults.lanes),
+                        ..defaults
+                    }
+                },
+                password_policy: {
+                    let defaults = auth::password_policy::PasswordPolicy::default();
+                    auth::password_policy::PasswordPolicy {
+                        min_length: std::env::var("PASSWORD_MIN_LENGTH")
+                            .map(|len| len.parse().unwrap_or_log())
+                            .unwrap_or(defaults.min_length),
+                        max_length: std::env::var("PASSWORD_MAX_LENGTH")
+                            .map(|len| len.parse().unwrap_or_log())
+                            .unwrap_or(defaults.max_length),
+                        min_entropy_bits: std::env::var("PASSWORD_MIN_ENTROPY_BITS")
+                            .map(|bits| bits.parse().unwrap_or_log())
+                            .unwrap_or(defaults.min_entropy_bits),
+                        breached: std::env::var("BREACHED_PASSWORDS_PATH").ok().map(|path| {
+                            std::sync::Arc::new(
+                                auth::password_policy::BreachedPasswords::load(path)
+                                    .unwrap_or_log(),
+                            )
+                        }),
+                        ..defaults
+                    }
+                },
+                access_token_lifespan: time::Duration::new(
+                    std::env::var("ACCESS_TOKEN_LIFESPAN_SECS")
+                        // the name used before refresh tokens were introduced
+                        .or_else(|_| std::env::var("AUTH_TOKEN_LIFESPAN_SECS"))
+                        .map(|secs| secs.parse().unwrap_or_log())
+                        .unwrap_or(60 * 60),
+                    0,
+                ),
+                refresh_token_lifespan: time::Duration::new(
+                    std::env::var("REFRESH_TOKEN_LIFESPAN_SECS")
+                        .map(|secs| secs.parse().unwrap_or_log())
+                        .unwrap_or(60 * 60 * 24 * 30),
+                    0,
+                ),
+                session_sweep_interval: time::Duration::new(
+                    std::env::var("SESSION_SWEEP_INTERVAL_SECS")
+                        .map(|secs| secs.parse().unwrap_or_log())
+                        .unwrap_or(60 * 10),
+                    0,
+                ),
+                session_sweep_batch_size: std::env::var("SESSION_SWEEP_BATCH_SIZE")
+                    .map(|size| size.parse().unwrap_or_log())
+                    .unwrap_or(1000),
+                password_reset_token_lifespan: time::Duration::new(
+                    std::env::var("PASSWORD_RESET_TOKEN_LIFESPAN_SECS")
+                        .map(|secs| secs.parse().unwrap_or_log())
+                        .unwrap_or(60 * 60),
+                    0,
+                ),
+                email_verification_token_lifespan: time::Duration::new(
+                    std::env::var("EMAIL_VERIFICATION_TOKEN_LIFESPAN_SECS")
+                        .map(|secs| secs.parse().unwrap_or_log())
+                        .unwrap_or(60 * 60 * 24),
+                    0,
+                ),
+                unverified_account_grace_period: std::env::var(
+                    "UNVERIFIED_ACCOUNT_GRACE_PERIOD_SECS",
+                )
+                .ok()
+                .map(|secs| time::Duration::new(secs.parse().unwrap_or_log(), 0)),
+                totp_issuer: std::env::var("TOTP_ISSUER").unwrap_or_else(|_| "web_api".into()),
+                totp_challenge_lifespan: time::Duration::new(
+                    std::env::var("TOTP_CHALLENGE_LIFESPAN_SECS")
+                        .map(|secs| secs.parse().unwrap_or_log())
+                        .unwrap_or(60 * 5),
+                    0,
+                ),
+                oidc_providers: std::env::var("OIDC_PROVIDERS_PATH")
+                    .map(|path| {
+                        auth::oidc::ProviderList::load(path)
+                            .unwrap_or_log()
+                            .providers
+                    })
+                    .unwrap_or_default(),
+                oidc_state_lifespan: time::Duration::new(
+                    std::env::var("OIDC_STATE_LIFESPAN_SECS")
+                        .map(|secs| secs.parse().unwrap_or_log())
+                        .unwrap_or(60 * 10),
                     0,
                 ),
+                login_lockout: {
+                    let defaults = auth::lockout::LockoutPolicy::default();
+                    auth::lockout::LockoutPolicy {
+                        threshold: std::env::var("LOGIN_LOCKOUT_THRESHOLD")
+                            .map(|count| count.parse().unwrap_or_log())
+                            .unwrap_or(defaults.threshold),
+                        ip_threshold: std::env::var("LOGIN_LOCKOUT_IP_THRESHOLD")
+                            .map(|count| count.parse().unwrap_or_log())
+                            .unwrap_or(defaults.ip_threshold),
+                        base_lockout: std::env::var("LOGIN_LOCKOUT_BASE_SECS")
+                            .map(|secs| time::Duration::new(secs.parse().unwrap_or_log(), 0))
+                            .unwrap_or(defaults.base_lockout),
+                        max_lockout: std::env::var("LOGIN_LOCKOUT_MAX_SECS")
+                            .map(|secs| time::Duration::new(secs.parse().unwrap_or_log(), 0))
+                            .unwrap_or(defaults.max_lockout),
+                        reset_after: std::env::var("LOGIN_LOCKOUT_RESET_SECS")
+                            .map(|secs| time::Duration::new(secs.parse().unwrap_or_log(), 0))
+                            .unwrap_or(defaults.reset_after),
+                        prune_interval: std::env::var("LOGIN_LOCKOUT_PRUNE_INTERVAL_SECS")
+                            .map(|secs| time::Duration::new(secs.parse().unwrap_or_log(), 0))
+                            .unwrap_or(defaults.prune_interval),
+                    }
+                },
+                deleted_user_retention: std::env::var("DELETED_USER_RETENTION_SECS")
+                    .ok()
+                    .map(|secs| time::Duration::new(secs.parse().unwrap_or_log(), 0)),
+                deleted_user_purge_interval: time::Duration::new(
+                    std::env::var("DELETED_USER_PURGE_INTERVAL_SECS")
+                        .map(|secs| secs.parse().unwrap_or_log())
+                        .unwrap_or(60 * 60),
+                    0,
+                ),
+                impersonation_lifespan: time::Duration::new(
+                    std::env::var("IMPERSONATION_LIFESPAN_SECS")
+                        .map(|secs| secs.parse().unwrap_or_log())
+                        .unwrap_or(60 * 15),
+                    0,
+                ),
+                jwt: std::env::var("JWT_CONFIG_PATH")
+                    .ok()
+                    .map(|path| auth::jwt::JwtConfig::load(path).unwrap_or_log()),
+                cursors: utils::CursorConfig {
+                    keys: match std::env::var("CURSOR_KEYS") {
+                        Ok(keys) => utils::CursorConfig::parse_keys(&keys).unwrap_or_log(),
+                        Err(_) => {
+                            tracing::warn!(
+                                "CURSOR_KEYS unset, list cursors won't survive restarts"
+                            );
+                            vec![utils::CursorKey::random()]
+                        }
+                    },
+                    max_age: std::env::var("CURSOR_MAX_AGE_SECS")
+                        .ok()
+                        .map(|secs| time::Duration::new(secs.parse().unwrap_or_log(), 0)),
+                },
             };
             let db_url = std::env::var("DATABASE_URL").unwrap_or_log();
             let db_pool = sqlx::PgPool::connect(&db_url).await.unwrap_or_log();
-            let ctx = Context { db_pool, config };
+            let policy: Box<dyn auth::policy::Policy> = match std::env::var("AUTH_POLICY_PATH") {
+                Ok(path) => Box::new(auth::policy::RuleSet::load(path).unwrap_or_log()),
+                Err(_) => Box::new(auth::policy::DefaultPolicy::new(db_pool.clone())),
+            };
+            let session_cache = auth::session_cache::SessionCache::new(
+                std::num::NonZeroUsize::new(10_000).unwrap(),
+                time::Duration::new(
+                    std::env::var("SESSION_CACHE_TTL_SECS")
+                        .map(|secs| secs.parse().unwrap_or_log())
+                        .unwrap_or(60),
+                    0,
+                ),
+            );
+            let session_cache = match std::env::var("REDIS_URL") {
+                Ok(url) => session_cache.with_redis(
+                    bb8_redis::bb8::Pool::builder()
+                        .build(bb8_redis::RedisConnectionManager::new(url).unwrap_or_log())
+                        .await
+                        .unwrap_or_log(),
+                ),
+                Err(_) => {
+                    tracing::warn!("REDIS_URL not set, using in-process session cache");
+                    session_cache
+                }
+            };
+            let http_client = reqwest::Client::new();
+            let mailer: std::sync::Arc<dyn mail::Mailer> = match std::env::var("MAIL_HTTP_URL") {
+                Ok(url) => std::sync::Arc::new(mail::HttpMailer {
+                    client: http_client.clone(),
+                    url: url.parse().unwrap_or_log(),
+                    api_key: std::env::var("MAIL_HTTP_API_KEY").ok(),
+                    from: std::env::var("MAIL_FROM").unwrap_or_log(),
+                }),
+                // mail carries reset and verification tokens, logging it is
+                // only fine on a dev box
+                Err(_) if cfg!(debug_assertions) => {
+                    tracing::warn!("MAIL_HTTP_URL not set, mail goes to the logs");
+                    std::sync::Arc::new(mail::LogMailer)
+                }
+                Err(err) => Err(err)
+                    .expect_or_log("MAIL_HTTP_URL must be set, release builds don't log mail"),
+            };
+            let ctx = Context {
+                db_pool,
+                config,
+                policy,
+                session_cache,
+                mailer,
+                clock: Box::new(clock::SystemClock),
+                http_client,
+            };
             let ctx = std::sync::Arc::new(ctx);
+            let _sweeper = auth::sweeper::spawn_session_sweeper(
+                ctx.clone(),
+                ctx.config.session_sweep_interval,
+                ctx.config.session_sweep_batch_size,
+            );
+            let _lockout_pruner =
+                auth::lockout::spawn_pruner(ctx.clone(), ctx.config.login_lockout.prune_interval);
+            let _purger = ctx.config.deleted_user_retention.map(|retention| {
+                user::purge::spawn_purger(
+                    ctx.clone(),
+                    ctx.config.deleted_user_purge_interval,
+                    retention,
+                )
+            });
+            let _revocation_sync = ctx.config.jwt.as_ref().map(|jwt| {
+                auth::jwt::spawn_revocation_sync(ctx.clone(), jwt.revocation_sync_interval)
+            });
             let app = axum::Router::new()
                 .merge(utoipa_swagger_ui::SwaggerUi::new("/swagger-ui/*tail").url(
                     "/api-doc/openapi.json",
@@ -53,7 +263,7 @@ fn main() {
             let address = std::net::SocketAddr::from((std::net::Ipv4Addr::UNSPECIFIED, 8080));
             tracing::info!("Server listening at {address:?}");
             axum::Server::bind(&address)
-                .serve(app.into_make_service())
+                .serve(app.into_make_service_with_connect_info::<std::net::SocketAddr>())
                 .await
         })
         .unwrap_or_log()
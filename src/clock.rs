@@ -0,0 +1,37 @@
+//! Source of the current time for logic that tests need to pin down.
+
+use deps::*;
+
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    fn now(&self) -> time::OffsetDateTime;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> time::OffsetDateTime {
+        time::OffsetDateTime::now_utc()
+    }
+}
+
+/// Reads the system clock until [`set`](FixedClock::set), after which it
+/// always returns the set time.
+#[derive(Debug, Clone, Default)]
+pub struct FixedClock {
+    pub fixed: std::sync::Arc<parking_lot::Mutex<Option<time::OffsetDateTime>>>,
+}
+
+impl FixedClock {
+    pub fn set(&self, now: time::OffsetDateTime) {
+        *self.fixed.lock() = Some(now);
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> time::OffsetDateTime {
+        self.fixed
+            .lock()
+            .unwrap_or_else(time::OffsetDateTime::now_utc)
+    }
+}
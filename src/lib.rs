@@ -7,7 +7,9 @@ use dylink;
 use deps::*;
 
 pub mod auth;
+pub mod clock;
 pub mod macros;
+pub mod mail;
 pub mod user;
 pub mod utils;
 
@@ -41,15 +43,58 @@ pub fn setup_tracing() -> eyre::Result<()> {
 
 #[derive(Debug)]
 pub struct Config {
-    pub pass_salt_hash: Vec<u8>,
+    /// Server-side secret all password hashes are keyed with. Hashes made
+    /// before it was set are upgraded as users authenticate.
+    pub pass_pepper: Option<Vec<u8>>,
+    /// Hashes made with weaker parameters are upgraded as users authenticate.
     pub argon2_conf: argon2::Config<'static>,
-    pub auth_token_lifespan: time::Duration,
+    /// Applies wherever passwords are set.
+    pub password_policy: auth::password_policy::PasswordPolicy,
+    pub access_token_lifespan: time::Duration,
+    pub refresh_token_lifespan: time::Duration,
+    pub session_sweep_interval: time::Duration,
+    pub session_sweep_batch_size: u32,
+    pub password_reset_token_lifespan: time::Duration,
+    pub email_verification_token_lifespan: time::Duration,
+    /// How long accounts may authenticate without verifying their email.
+    /// Unlimited if unset.
+    pub unverified_account_grace_period: Option<time::Duration>,
+    /// Used as the issuer in TOTP provisioning URIs.
+    pub totp_issuer: String,
+    /// How long the challenge token handed out by `Authenticate` to users
+    /// with two-factor authentication stays valid.
+    pub totp_challenge_lifespan: time::Duration,
+    /// OpenID Connect providers users can link identities at and
+    /// authenticate through.
+    pub oidc_providers: Vec<auth::oidc::Provider>,
+    /// How long a user has to complete the sign in at a provider.
+    pub oidc_state_lifespan: time::Duration,
+    /// Throttling of failed attempts at `Authenticate`.
+    pub login_lockout: auth::lockout::LockoutPolicy,
+    /// How long deleted users can be restored for before they're erased for
+    /// good. Kept forever if unset.
+    pub deleted_user_retention: Option<time::Duration>,
+    /// How often users past the retention get erased.
+    pub deleted_user_purge_interval: time::Duration,
+    /// How long sessions opened by admins to act as a user last. They can't
+    /// be refreshed.
+    pub impersonation_lifespan: time::Duration,
+    /// Issue signed JWTs as access tokens instead of opaque ones.
+    pub jwt: Option<auth::jwt::JwtConfig>,
+    /// Signing of the cursors of list endpoints.
+    pub cursors: utils::CursorConfig,
 }
 
 #[derive(Debug)]
 pub struct Context {
     pub db_pool: sqlx::postgres::PgPool,
     pub config: Config,
+    pub policy: Box<dyn auth::policy::Policy>,
+    pub session_cache: auth::session_cache::SessionCache,
+    pub mailer: std::sync::Arc<dyn mail::Mailer>,
+    pub clock: Box<dyn clock::Clock>,
+    /// For calls to OpenID Connect providers.
+    pub http_client: reqwest::Client,
 }
 
 pub type SharedContext = std::sync::Arc<Context>;
@@ -87,6 +132,10 @@ Notes:
                         type_name_raw::<SortingOrder>(),
                         <SortingOrder as utoipa::ToSchema>::schema(),
                     )
+                    .schema(
+                        type_name_raw::<ListTotal>(),
+                        <ListTotal as utoipa::ToSchema>::schema(),
+                    )
                     .schema(
                         type_name_raw::<ValidationErrors>(),
                         <utils::ValidationErrors as utoipa::ToSchema>::schema(),
@@ -141,12 +190,18 @@ pub trait AuthenticatedEndpoint: Send + Sync + 'static {
     type Response;
     type Error: From<auth::authorize::Error>;
 
+    /// Deny the endpoint to sessions opened through
+    /// [`Impersonate`](auth::impersonate::Impersonate), for actions only the
+    /// account holder should take.
+    const IMPERSONATION_FORBIDDEN: bool = false;
+
     fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request;
 
+    /// `accessing_user` carries the impersonator, if any, along with the user.
     async fn handle(
         &self,
         ctx: &crate::Context,
-        accessing_user: uuid::Uuid,
+        accessing_user: crate::auth::authorize::Response,
         request: Self::Request,
     ) -> Result<Self::Response, Self::Error>;
 }
@@ -172,9 +227,30 @@ where
     ) -> Result<Self::Response, Self::Error> {
         let accessing_user = {
             let auth_args = self.authorize_request(&request);
-            crate::auth::authorize::Authorize
+            let authorized = crate::auth::authorize::Authorize
                 .handle(ctx, auth_args)
-                .await?
+                .await?;
+            if let Some(impersonator_id) = authorized.impersonator_id {
+                let endpoint = std::any::type_name::<T>();
+                if T::IMPERSONATION_FORBIDDEN {
+                    tracing::info!(
+                        target: "audit",
+                        impersonator_id = %impersonator_id,
+                        user_id = %authorized.user_id,
+                        endpoint,
+                        "impersonated request denied"
+                    );
+                    return Err(crate::auth::authorize::Error::ImpersonationForbidden.into());
+                }
+                tracing::info!(
+                    target: "audit",
+                    impersonator_id = %impersonator_id,
+                    user_id = %authorized.user_id,
+                    endpoint,
+                    "impersonated request"
+                );
+            }
+            authorized
         };
         self.handle(ctx, accessing_user, request).await
     }
@@ -196,6 +272,11 @@ where
     fn request(params: Self::HttpRequest) -> Result<Self::Request, Self::Error>;
     fn response(resp: Self::Response) -> axum::response::Response;
 
+    /// Override to add headers to error responses.
+    fn error_response(err: Self::Error) -> axum::response::Response {
+        (Into::<StatusCode>::into(&err), response::Json(err)).into_response()
+    }
+
     /// This actally need not be a method but I guess it allows for easy behavior
     /// modification. We ought to probably move these to the `Handler` impl
     /// when they stabilize specialization
@@ -212,9 +293,7 @@ where
             };
             let req = match Self::request(req) {
                 Ok(val) => val,
-                Err(err) => {
-                    return (Into::<StatusCode>::into(&err), response::Json(err)).into_response()
-                }
+                Err(err) => return Self::error_response(err),
             };
             let Extension(ctx) =
                 match Extension::<crate::SharedContext>::from_request(&mut req_parts).await {
@@ -229,7 +308,7 @@ where
                     *resp.status_mut() = Self::SUCCESS_CODE;
                     resp
                 }
-                Err(err) => (Into::<StatusCode>::into(&err), response::Json(err)).into_response(),
+                Err(err) => Self::error_response(err),
             }
         })
     }
@@ -308,6 +387,9 @@ fn test_axum_path_paramter_list() {
 pub trait ToRefOrSchema {
     fn schema_name() -> &'static str;
     fn ref_or_schema() -> openapi::schema::RefOr<openapi::schema::Schema>;
+    /// The schema itself even when [`ref_or_schema`](Self::ref_or_schema)
+    /// refers to it, for breaking types up into query parameters.
+    fn inline_schema() -> openapi::schema::Schema;
 }
 
 impl<T> ToRefOrSchema for T
@@ -318,6 +400,10 @@ where
         T::schema().into()
     }
 
+    fn inline_schema() -> openapi::schema::Schema {
+        T::schema()
+    }
+
     fn schema_name() -> &'static str {
         type_name_raw::<T>()
     }
@@ -339,6 +425,10 @@ impl ToRefOrSchema for NoContent {
     fn ref_or_schema() -> openapi::schema::RefOr<openapi::schema::Schema> {
         panic!("this baby is special cased")
     }
+
+    fn inline_schema() -> openapi::schema::Schema {
+        panic!("this baby is special cased")
+    }
 }
 
 #[derive(educe::Educe, serde::Serialize, serde::Deserialize)]
@@ -366,6 +456,10 @@ where
         //     .into()
     }
 
+    fn inline_schema() -> openapi::schema::Schema {
+        T::schema()
+    }
+
     fn schema_name() -> &'static str {
         T::schema_name()
     }
@@ -431,6 +525,75 @@ impl DocumentedParameter for axum::extract::Path<uuid::Uuid> {
     }
 }
 
+impl DocumentedParameter for axum::extract::Path<String> {
+    fn to_openapi(_op_id: &str, path: &str) -> Vec<ParameterDoc> {
+        axum_path_parameter_list(path)
+            .into_iter()
+            .map(|name| {
+                openapi::path::ParameterBuilder::new()
+                    .name(name)
+                    .parameter_in(openapi::path::ParameterIn::Path)
+                    .required(openapi::Required::True)
+                    .schema(Some(
+                        openapi::schema::ObjectBuilder::new()
+                            .schema_type(openapi::SchemaType::String),
+                    ))
+                    .build()
+                    .into()
+            })
+            .collect()
+    }
+}
+
+/// String parameters for the parameters in `path`, formatted in order.
+fn string_path_parameters(
+    path: &str,
+    formats: &[Option<openapi::schema::SchemaFormat>],
+) -> Vec<ParameterDoc> {
+    let names = axum_path_parameter_list(path);
+    assert_eq!(
+        names.len(),
+        formats.len(),
+        "expected {} path parameters in {path}",
+        formats.len()
+    );
+    names
+        .into_iter()
+        .zip(formats.iter().cloned())
+        .map(|(name, format)| {
+            openapi::path::ParameterBuilder::new()
+                .name(name)
+                .parameter_in(openapi::path::ParameterIn::Path)
+                .required(openapi::Required::True)
+                .schema(Some(
+                    openapi::schema::ObjectBuilder::new()
+                        .schema_type(openapi::SchemaType::String)
+                        .format(format),
+                ))
+                .build()
+                .into()
+        })
+        .collect()
+}
+
+impl DocumentedParameter for axum::extract::Path<(uuid::Uuid, String)> {
+    fn to_openapi(_op_id: &str, path: &str) -> Vec<ParameterDoc> {
+        string_path_parameters(path, &[Some(openapi::schema::SchemaFormat::Uuid), None])
+    }
+}
+
+impl DocumentedParameter for axum::extract::Path<(uuid::Uuid, uuid::Uuid)> {
+    fn to_openapi(_op_id: &str, path: &str) -> Vec<ParameterDoc> {
+        string_path_parameters(
+            path,
+            &[
+                Some(openapi::schema::SchemaFormat::Uuid),
+                Some(openapi::schema::SchemaFormat::Uuid),
+            ],
+        )
+    }
+}
+
 impl<T> DocumentedParameter for axum::extract::Json<T>
 where
     T: ToRefOrSchema,
@@ -461,39 +624,39 @@ where
     }
 }
 
-// impl<T> DocumentedParameter for axum::extract::Query<T>
-// where
-//     T: utoipa::ToSchema,
-// {
-//     fn to_openapi(_op_id: &str, _path: &str) -> Vec<ParameterDoc> {
-//         match T::schema() {
-//             utoipa::openapi::Schema::Object(obj) => {
-//
-//             },
-//             utoipa::openapi::Schema::Array(_) => panic!("{} is an Array schema: not allowed as Query paramter", std::any::type_name::<T>()),
-//             utoipa::openapi::Schema::OneOf(_) => panic!("{} is an OneOf schema: not allowed as Query paramter", std::any::type_name::<T>()),
-//             _ => todo!(),
-//         }
-//         vec![utoipa::openapi::path::ParameterBuilder::new().schema({
-//             .schema(match T::ref_or_schema() {
-//                 utoipa::openapi::schema::RefOr::T(schema) => {
-//                     if T::schema_name() == "Request" {
-//                         schema.into()
-//                     } else {
-//                         utoipa::openapi::Ref::from_schema_name(T::schema_name().to_string())
-//                             .into()
-//                     }
-//                 }
-//                 ref_or => ref_or,
-//             })
-//         })
-//             // .name("body")
-//             // .parameter_in(utoipa::openapi::path::ParameterIn::Path)
-//             // .required(utoipa::openapi::Required::True)
-//             .build()
-//             .into()]
-//     }
-// }
+/// Each property of the object schema of `T` makes for a query parameter.
+impl<T> DocumentedParameter for axum::extract::Query<T>
+where
+    T: ToRefOrSchema,
+{
+    fn to_openapi(_op_id: &str, _path: &str) -> Vec<ParameterDoc> {
+        let object = match T::inline_schema() {
+            openapi::Schema::Object(object) => object,
+            _ => panic!(
+                "{} isn't an Object schema: not allowed as query parameters",
+                std::any::type_name::<T>()
+            ),
+        };
+        object
+            .properties
+            .into_iter()
+            .map(|(name, schema)| {
+                let required = if object.required.contains(&name) {
+                    openapi::Required::True
+                } else {
+                    openapi::Required::False
+                };
+                openapi::path::ParameterBuilder::new()
+                    .name(name)
+                    .parameter_in(openapi::path::ParameterIn::Query)
+                    .required(required)
+                    .schema(Some(schema))
+                    .build()
+                    .into()
+            })
+            .collect()
+    }
+}
 
 impl<T> DocumentedParameter for Option<T>
 where
@@ -559,6 +722,23 @@ where
     }
 }
 
+impl<T1, T2, T3, T4> DocumentedParameter for (T1, T2, T3, T4)
+where
+    T1: DocumentedParameter,
+    T2: DocumentedParameter,
+    T3: DocumentedParameter,
+    T4: DocumentedParameter,
+{
+    const HAS_BEARER: bool = T1::HAS_BEARER | T2::HAS_BEARER | T3::HAS_BEARER | T4::HAS_BEARER;
+    fn to_openapi(op_id: &str, path: &str) -> Vec<ParameterDoc> {
+        let mut vec = T1::to_openapi(op_id, path);
+        vec.append(&mut T2::to_openapi(op_id, path));
+        vec.append(&mut T3::to_openapi(op_id, path));
+        vec.append(&mut T4::to_openapi(op_id, path));
+        vec
+    }
+}
+
 /// (description, example)
 pub type ErrorResponse<Err> = (&'static str, Err);
 
@@ -910,3 +1090,43 @@ impl DocumentedParameter for BearerToken {
         vec![]
     }
 }
+
+/// Details about the client making the request. Extraction never fails, the
+/// fields are just left empty when the information isn't available.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    /// Only available when the server was started using
+    /// `into_make_service_with_connect_info`.
+    pub ip_addr: Option<std::net::IpAddr>,
+}
+
+#[async_trait::async_trait]
+impl<B> axum::extract::FromRequest<B> for ClientInfo
+where
+    B: Send,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let user_agent = req
+            .headers()
+            .get(axum::http::header::USER_AGENT)
+            .and_then(|val| val.to_str().ok())
+            .map(String::from);
+        let ip_addr = req
+            .extensions()
+            .get::<ConnectInfo<std::net::SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Ok(Self {
+            user_agent,
+            ip_addr,
+        })
+    }
+}
+
+impl DocumentedParameter for ClientInfo {
+    fn to_openapi(_op_id: &str, _path: &str) -> Vec<ParameterDoc> {
+        vec![]
+    }
+}
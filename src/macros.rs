@@ -19,6 +19,14 @@ macro_rules! alias_and_ref {
             fn ref_or_schema() -> openapi::schema::RefOr<openapi::schema::Schema> {
                 openapi::schema::Ref::from_schema_name(Self::schema_name()).into()
             }
+
+            fn inline_schema() -> openapi::schema::Schema {
+                <$alias_name as utoipa::ToSchema>::aliases()
+                    .into_iter()
+                    .find(|(name, _)| *name == Self::schema_name())
+                    .map(|(_, schema)| schema)
+                    .expect("alias not registered on the aliased type")
+            }
         }
     };
     ($aliased_type:ty, $alias_name:ident, $ref_name:ident, ser) => {
@@ -40,12 +48,20 @@ macro_rules! alias_and_ref {
             fn ref_or_schema() -> openapi::schema::RefOr<openapi::schema::Schema> {
                 openapi::schema::Ref::from_schema_name(Self::schema_name()).into()
             }
+
+            fn inline_schema() -> openapi::schema::Schema {
+                <$alias_name as utoipa::ToSchema>::aliases()
+                    .into_iter()
+                    .find(|(name, _)| *name == Self::schema_name())
+                    .map(|(_, schema)| schema)
+                    .expect("alias not registered on the aliased type")
+            }
         }
     };
     ($aliased_type:ty, $alias_name:ident, $ref_name:ident, de) => {
         pub type $alias_name = $aliased_type;
         #[derive(educe::Educe, serde::Deserialize)]
-        #[serde(crate = "serde")]
+        #[serde(crate = "serde", transparent)]
         #[educe(Deref)]
         pub struct $ref_name($alias_name);
         impl From<$alias_name> for $ref_name {
@@ -61,12 +77,20 @@ macro_rules! alias_and_ref {
             fn ref_or_schema() -> openapi::schema::RefOr<openapi::schema::Schema> {
                 openapi::schema::Ref::from_schema_name(Self::schema_name()).into()
             }
+
+            fn inline_schema() -> openapi::schema::Schema {
+                <$alias_name as utoipa::ToSchema>::aliases()
+                    .into_iter()
+                    .find(|(name, _)| *name == Self::schema_name())
+                    .map(|(_, schema)| schema)
+                    .expect("alias not registered on the aliased type")
+            }
         }
     };
     ($aliased_type:ty, $alias_name:ident, $ref_name:ident, ser, de) => {
         pub type $alias_name = $aliased_type;
         #[derive(educe::Educe, serde::Serialize, serde::Deserialize)]
-        #[serde(crate = "serde")]
+        #[serde(crate = "serde", transparent)]
         #[educe(Deref)]
         pub struct $ref_name($alias_name);
         impl From<$alias_name> for $ref_name {
@@ -82,6 +106,14 @@ macro_rules! alias_and_ref {
             fn ref_or_schema() -> openapi::schema::RefOr<openapi::schema::Schema> {
                 openapi::schema::Ref::from_schema_name(Self::schema_name()).into()
             }
+
+            fn inline_schema() -> openapi::schema::Schema {
+                <$alias_name as utoipa::ToSchema>::aliases()
+                    .into_iter()
+                    .find(|(name, _)| *name == Self::schema_name())
+                    .map(|(_, schema)| schema)
+                    .expect("alias not registered on the aliased type")
+            }
         }
     };
 }
@@ -96,7 +128,9 @@ macro_rules! impl_from_auth_err {
             fn from(err: $crate::auth::authorize::Error) -> Self {
                 use $crate::auth::authorize::Error;
                 match err {
-                    Error::Unauthorized | Error::InvalidToken => Self::AccessDenied,
+                    Error::Unauthorized | Error::InvalidToken | Error::ImpersonationForbidden => {
+                        Self::AccessDenied
+                    }
                     Error::Internal { message } => Self::Internal { message },
                 }
             }
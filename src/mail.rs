@@ -0,0 +1,169 @@
+//! Outgoing mail.
+
+use deps::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unable to send mail: {message:?}")]
+    Send { message: String },
+}
+
+#[async_trait::async_trait]
+pub trait Mailer: Send + Sync + std::fmt::Debug {
+    async fn send(&self, mail: Mail) -> Result<(), Error>;
+}
+
+/// Writes mail to the logs instead of sending it. Don't use in production,
+/// mail often contains secrets.
+#[derive(Debug, Clone, Default)]
+pub struct LogMailer;
+
+#[async_trait::async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: Mail) -> Result<(), Error> {
+        tracing::info!(to = %mail.to, subject = %mail.subject, body = %mail.body, "mail");
+        Ok(())
+    }
+}
+
+/// Hands mail to an HTTP relay, as a JSON `POST` of the sender, recipient,
+/// subject and text body, authenticated by a bearer key when there's one.
+#[derive(Debug, Clone)]
+pub struct HttpMailer {
+    pub client: reqwest::Client,
+    pub url: reqwest::Url,
+    pub api_key: Option<String>,
+    pub from: String,
+}
+
+#[async_trait::async_trait]
+impl Mailer for HttpMailer {
+    async fn send(&self, mail: Mail) -> Result<(), Error> {
+        let mut request = self.client.post(self.url.clone()).json(&serde_json::json!({
+            "from": self.from,
+            "to": mail.to,
+            "subject": mail.subject,
+            "text": mail.body,
+        }));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|err| Error::Send {
+                message: format!("{err}"),
+            })?;
+        Ok(())
+    }
+}
+
+/// Keeps sent mail around for inspection.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMailer {
+    pub outbox: std::sync::Arc<parking_lot::Mutex<Vec<Mail>>>,
+}
+
+#[async_trait::async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, mail: Mail) -> Result<(), Error> {
+        self.outbox.lock().push(mail);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn http_mailer_posts_mail() {
+        let received = std::sync::Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let listener = std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let url = format!("http://{}/send", listener.local_addr().unwrap());
+        let app = axum::Router::new().route(
+            "/send",
+            axum::routing::post({
+                let received = received.clone();
+                move |headers: axum::http::HeaderMap,
+                      axum::Json(body): axum::Json<serde_json::Value>| async move {
+                    received
+                        .lock()
+                        .push((headers[axum::http::header::AUTHORIZATION].clone(), body));
+                }
+            }),
+        );
+        let server = tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        let mailer = HttpMailer {
+            client: reqwest::Client::new(),
+            url: url.parse().unwrap(),
+            api_key: Some("hunter2".into()),
+            from: "noreply@example.com".into(),
+        };
+        mailer
+            .send(Mail {
+                to: "hex.queen@teen.dj".into(),
+                subject: "Hi".into(),
+                body: "Hello".into(),
+            })
+            .await
+            .unwrap_or_log();
+        server.abort();
+        let received = received.lock();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].0, "Bearer hunter2");
+        assert_eq!(
+            received[0].1,
+            serde_json::json!({
+                "from": "noreply@example.com",
+                "to": "hex.queen@teen.dj",
+                "subject": "Hi",
+                "text": "Hello",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn http_mailer_reports_rejections() {
+        let listener = std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let url = format!("http://{}/send", listener.local_addr().unwrap());
+        let app = axum::Router::new().route(
+            "/send",
+            axum::routing::post(|| async { axum::http::StatusCode::UNAUTHORIZED }),
+        );
+        let server = tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        let mailer = HttpMailer {
+            client: reqwest::Client::new(),
+            url: url.parse().unwrap(),
+            api_key: None,
+            from: "noreply@example.com".into(),
+        };
+        let result = mailer
+            .send(Mail {
+                to: "hex.queen@teen.dj".into(),
+                subject: "Hi".into(),
+                body: "Hello".into(),
+            })
+            .await;
+        server.abort();
+        assert!(matches!(result, Err(Error::Send { .. })), "{result:?}");
+    }
+}
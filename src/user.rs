@@ -21,9 +21,43 @@ pub struct User {
     #[schema(example = "hunter2")]
     pub username: String,
     pub pic_url: Option<String>,
+    /// In seconds since unix epoch in UTC. Unset until the current `email` is
+    /// verified.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp::option")]
+    pub email_verified_at: Option<time::OffsetDateTime>,
+}
+
+/// A [`User`] as archived by the deletion.
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct DeletedUser {
+    pub id: uuid::Uuid,
+    /// In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
+    pub created_at: time::OffsetDateTime,
+    /// In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
+    pub updated_at: time::OffsetDateTime,
+    /// In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp")]
+    pub deleted_at: time::OffsetDateTime,
+    #[schema(example = "alice@example.com")]
+    pub email: String,
+    #[schema(example = "hunter2")]
+    pub username: String,
+    pub pic_url: Option<String>,
+    /// In seconds since unix epoch in UTC.
+    #[schema(example = 1234567)]
+    #[serde(with = "time::serde::timestamp::option")]
+    pub email_verified_at: Option<time::OffsetDateTime>,
 }
 
 pub use list::UserSortingField;
+pub use list_deleted::DeletedUserSortingField;
 
 pub static USERNAME_REGEX: Lazy<regex::Regex> =
     Lazy::new(|| regex::Regex::new(r"^[a-zA-Z0-9]+([_-]?[a-zA-Z0-9])*$").unwrap());
@@ -33,10 +67,14 @@ pub const TAG: crate::Tag = crate::Tag {
     desc: "Manipulate User objects.",
 };
 
+mod change_password;
 mod create;
 mod delete;
 mod get;
 mod list;
+mod list_deleted;
+pub mod purge;
+mod restore;
 mod update;
 
 pub fn router() -> axum::Router {
@@ -46,6 +84,9 @@ pub fn router() -> axum::Router {
         .merge(EndpointWrapper::new(update::UpdateUser))
         .merge(EndpointWrapper::new(list::ListUsers))
         .merge(EndpointWrapper::new(delete::DeleteUser))
+        .merge(EndpointWrapper::new(change_password::ChangePassword))
+        .merge(EndpointWrapper::new(restore::RestoreUser))
+        .merge(EndpointWrapper::new(list_deleted::ListDeletedUsers))
 }
 
 pub fn components(
@@ -56,12 +97,20 @@ pub fn components(
     let builder = update::UpdateUser::components(builder);
     let builder = list::ListUsers::components(builder);
     let builder = delete::DeleteUser::components(builder);
+    let builder = change_password::ChangePassword::components(builder);
+    let builder = restore::RestoreUser::components(builder);
+    let builder = list_deleted::ListDeletedUsers::components(builder);
     builder
         .schema("User", <User as utoipa::ToSchema>::schema())
+        .schema("DeletedUser", <DeletedUser as utoipa::ToSchema>::schema())
         .schema(
             crate::utils::type_name_raw::<UserSortingField>(),
             <UserSortingField as utoipa::ToSchema>::schema(),
         )
+        .schema(
+            crate::utils::type_name_raw::<DeletedUserSortingField>(),
+            <DeletedUserSortingField as utoipa::ToSchema>::schema(),
+        )
         .schemas_from_iter(<list::ListUsersRequest as utoipa::ToSchema>::aliases())
         .schemas_from_iter(<list::ListUsersResponse as utoipa::ToSchema>::aliases())
 }
@@ -80,6 +129,14 @@ pub fn paths(builder: utoipa::openapi::PathsBuilder) -> utoipa::openapi::PathsBu
             crate::axum_path_str_to_openapi(delete::DeleteUser::PATH),
             delete::DeleteUser::path_item(),
         )
+        .path(
+            crate::axum_path_str_to_openapi(change_password::ChangePassword::PATH),
+            change_password::ChangePassword::path_item(),
+        )
+        .path(
+            crate::axum_path_str_to_openapi(restore::RestoreUser::PATH),
+            restore::RestoreUser::path_item(),
+        )
         .path(
             crate::axum_path_str_to_openapi(create::CreateUser::PATH),
             create::CreateUser::path_item(),
@@ -88,6 +145,10 @@ pub fn paths(builder: utoipa::openapi::PathsBuilder) -> utoipa::openapi::PathsBu
             crate::axum_path_str_to_openapi(list::ListUsers::PATH),
             list::ListUsers::path_item(),
         )
+        .path(
+            crate::axum_path_str_to_openapi(list_deleted::ListDeletedUsers::PATH),
+            list_deleted::ListDeletedUsers::path_item(),
+        )
 }
 
 // #[cfg(test)]
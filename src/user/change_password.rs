@@ -0,0 +1,453 @@
+use deps::*;
+
+use axum::extract::*;
+
+use crate::utils::*;
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ChangePassword;
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct Request {
+    #[serde(skip)]
+    pub auth_token: Option<std::sync::Arc<str>>,
+    #[serde(skip)]
+    pub user_id: Option<uuid::Uuid>,
+    #[serde(skip)]
+    pub client: Option<ClientInfo>,
+    pub current_password: String,
+    /// Has to satisfy the password policy.
+    pub new_password: String,
+    /// Sign out everywhere but the session making the request.
+    #[serde(default)]
+    pub revoke_other_sessions: bool,
+}
+
+#[derive(Debug, Serialize, thiserror::Error, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase", tag = "error")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("current password rejected")]
+    CurrentPasswordRejected,
+    /// The user only signs in through identity providers.
+    #[error("user has no password")]
+    NoPassword,
+    #[error("too many failed attempts, retry after {retry_after}s")]
+    #[serde(rename_all = "camelCase")]
+    TooManyAttempts {
+        /// In seconds.
+        retry_after: u64,
+    },
+    #[error("invalid input: {issues:?}")]
+    InvalidInput {
+        #[from]
+        issues: ValidationErrors,
+    },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+pub type Response = NoContent;
+
+#[async_trait::async_trait]
+impl AuthenticatedEndpoint for ChangePassword {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone().unwrap(),
+            resource: crate::auth::Resource::User {
+                id: request.user_id.unwrap(),
+            },
+            action: crate::auth::Action::Write,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx, request), fields(user_id = ?request.user_id))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let user_id = request.user_id.unwrap();
+        // not even admins, they don't know the current password
+        if accessing_user.user_id != user_id {
+            return Err(Error::AccessDenied);
+        }
+        let db_err = |err| Error::Internal {
+            message: format!("db error: {err}"),
+        };
+        let mut tx = ctx.db_pool.begin().await.map_err(db_err)?;
+        let current = sqlx::query!(
+            r#"
+SELECT pass_hash, username::TEXT as "username!", email::TEXT as "email!"
+FROM credentials
+JOIN users ON users.id = credentials.user_id
+WHERE user_id = $1
+FOR UPDATE OF credentials
+            "#,
+            &user_id,
+        )
+        .fetch_one(&mut tx)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => Error::NoPassword,
+            err => db_err(err),
+        })?;
+
+        // a stolen session shouldn't make for unlimited guesses
+        let lockout_keys = crate::auth::lockout::Keys::for_user(
+            user_id,
+            request.client.as_ref().and_then(|client| client.ip_addr),
+        );
+        let attempted_at = match crate::auth::lockout::record_attempt(ctx, &lockout_keys)
+            .await
+            .map_err(db_err)?
+        {
+            crate::auth::lockout::Attempt::Counted { at } => at,
+            crate::auth::lockout::Attempt::Locked { remaining } => {
+                return Err(Error::TooManyAttempts {
+                    retry_after: remaining.as_seconds_f64().ceil() as u64,
+                })
+            }
+        };
+        if let crate::auth::password::Verification::Rejected = crate::auth::password::verify(
+            &ctx.config,
+            &current.pass_hash,
+            &request.current_password,
+        ) {
+            return Err(Error::CurrentPasswordRejected);
+        }
+        crate::auth::lockout::record_success(ctx, &lockout_keys, attempted_at)
+            .await
+            .map_err(db_err)?;
+
+        let mut issues = validator::ValidationErrors::new();
+        ctx.config.password_policy.check(
+            &mut issues,
+            "new_password",
+            &request.new_password,
+            &[&current.username, &current.email],
+        );
+        if !issues.is_empty() {
+            return Err(ValidationErrors::from(issues).into());
+        }
+
+        sqlx::query!(
+            r#"
+UPDATE credentials
+SET pass_hash = $2
+WHERE user_id = $1
+            "#,
+            &user_id,
+            crate::auth::password::hash(&ctx.config, &request.new_password),
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(db_err)?;
+        // a reset requested with the old password in mind is moot
+        sqlx::query!(
+            r#"
+DELETE FROM password_reset_tokens
+WHERE user_id = $1
+            "#,
+            &user_id,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(db_err)?;
+        let revoked = if request.revoke_other_sessions {
+            sqlx::query_scalar!(
+                r#"
+DELETE FROM sessions
+WHERE user_id = $1 AND token != $2
+RETURNING token
+                "#,
+                &user_id,
+                &request.auth_token.as_ref().unwrap()[..],
+            )
+            .fetch_all(&mut tx)
+            .await
+            .map_err(db_err)?
+        } else {
+            vec![]
+        };
+        tx.commit().await.map_err(db_err)?;
+        tracing::info!(
+            target: "audit",
+            user_id = %user_id,
+            revoked_sessions = revoked.len(),
+            "password changed"
+        );
+        ctx.session_cache.invalidate(revoked).await;
+        Ok(NoContent)
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            AccessDenied => Self::UNAUTHORIZED,
+            CurrentPasswordRejected | NoPassword | InvalidInput { .. } => Self::BAD_REQUEST,
+            TooManyAttempts { .. } => Self::TOO_MANY_REQUESTS,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for ChangePassword {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/users/:id/password";
+    const SUCCESS_CODE: StatusCode = StatusCode::NO_CONTENT;
+
+    type HttpRequest = (BearerToken, ClientInfo, Path<uuid::Uuid>, Json<Request>);
+
+    fn request(
+        (BearerToken(token), client, Path(user_id), Json(req)): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(Request {
+            auth_token: Some(token),
+            user_id: Some(user_id),
+            client: Some(client),
+            ..req
+        })
+    }
+
+    fn response(_: Self::Response) -> axum::response::Response {
+        Default::default()
+    }
+
+    fn error_response(err: Self::Error) -> axum::response::Response {
+        let retry_after = match &err {
+            Error::TooManyAttempts { retry_after } => Some(*retry_after),
+            _ => None,
+        };
+        let mut resp = (StatusCode::from(&err), Json(err)).into_response();
+        if let Some(retry_after) = retry_after {
+            crate::auth::lockout::set_retry_after(&mut resp, retry_after);
+        }
+        resp
+    }
+}
+
+impl DocumentedEndpoint for ChangePassword {
+    const TAG: &'static Tag = &super::TAG;
+    const SUMMARY: &'static str = "Change the password of the accessing user.";
+
+    fn errors() -> Vec<ErrorResponse<Self::Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            ("Current password rejected", Error::CurrentPasswordRejected),
+            ("User has no password", Error::NoPassword),
+            (
+                "Too many failed attempts, see the `Retry-After` header",
+                Error::TooManyAttempts { retry_after: 30 },
+            ),
+            (
+                "Invalid input",
+                Error::InvalidInput {
+                    issues: {
+                        let mut issues = validator::ValidationErrors::new();
+                        issues.add("new_password", validator::ValidationError::new("breached"));
+                        issues.into()
+                    },
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+    use crate::Endpoint;
+
+    async fn authenticates(ctx: &TestContext, password: &str) -> bool {
+        crate::auth::authenticate::Authenticate
+            .handle(
+                &ctx.ctx(),
+                crate::auth::authenticate::Request {
+                    identifier: USER_01_USERNAME.into(),
+                    password: password.into(),
+                    device_label: None,
+                    client: None,
+                },
+            )
+            .await
+            .is_ok()
+    }
+
+    async fn session_valid(ctx: &TestContext, token: &str) -> bool {
+        crate::auth::authorize::Authorize
+            .handle(
+                &ctx.ctx(),
+                crate::auth::authorize::Request {
+                    auth_token: token.into(),
+                    resource: crate::auth::Resource::User { id: USER_01_ID },
+                    action: crate::auth::Action::Read,
+                },
+            )
+            .await
+            .is_ok()
+    }
+
+    crate::integration_table_tests! {
+        change_password_works: {
+            uri: format!("/users/{USER_01_ID}/password"),
+            method: "POST",
+            status: StatusCode::NO_CONTENT,
+            router: crate::user::router(),
+            body: serde_json::json!({
+                "currentPassword": "password",
+                "newPassword": "Tr0ub4dor&3",
+            }),
+            auth_token: USER_01_SESSION.into(),
+            extra_assertions: &|EAArgs { ctx, .. }| {
+                Box::pin(async move {
+                    assert!(!authenticates(ctx, "password").await);
+                    assert!(authenticates(ctx, "Tr0ub4dor&3").await);
+                    assert!(session_valid(ctx, USER_01_SESSION_2).await);
+                })
+            },
+        },
+        change_password_revokes_other_sessions: {
+            uri: format!("/users/{USER_01_ID}/password"),
+            method: "POST",
+            status: StatusCode::NO_CONTENT,
+            router: crate::user::router(),
+            body: serde_json::json!({
+                "currentPassword": "password",
+                "newPassword": "Tr0ub4dor&3",
+                "revokeOtherSessions": true,
+            }),
+            auth_token: USER_01_SESSION.into(),
+            extra_assertions: &|EAArgs { ctx, .. }| {
+                Box::pin(async move {
+                    assert!(session_valid(ctx, USER_01_SESSION).await);
+                    assert!(!session_valid(ctx, USER_01_SESSION_2).await);
+                })
+            },
+        },
+        change_password_rejects_wrong_current_password: {
+            uri: format!("/users/{USER_01_ID}/password"),
+            method: "POST",
+            status: StatusCode::BAD_REQUEST,
+            router: crate::user::router(),
+            body: serde_json::json!({
+                "currentPassword": "hunter2",
+                "newPassword": "Tr0ub4dor&3",
+            }),
+            check_json: serde_json::json!({
+                "error": "currentPasswordRejected",
+            }),
+            auth_token: USER_01_SESSION.into(),
+            extra_assertions: &|EAArgs { ctx, .. }| {
+                Box::pin(async move {
+                    assert!(authenticates(ctx, "password").await);
+                })
+            },
+        },
+        change_password_enforces_policy: {
+            uri: format!("/users/{USER_01_ID}/password"),
+            method: "POST",
+            status: StatusCode::BAD_REQUEST,
+            router: crate::user::router(),
+            body: serde_json::json!({
+                "currentPassword": "password",
+                "newPassword": "sabrina!!",
+            }),
+            check_json: serde_json::json!({
+                "error": "invalidInput",
+                "issues": {
+                    "new_password": [
+                        { "code": "personal_info" },
+                    ],
+                },
+            }),
+            auth_token: USER_01_SESSION.into(),
+        },
+        change_password_rejects_other_users: {
+            uri: format!("/users/{USER_01_ID}/password"),
+            method: "POST",
+            status: StatusCode::UNAUTHORIZED,
+            router: crate::user::router(),
+            body: serde_json::json!({
+                "currentPassword": "password",
+                "newPassword": "Tr0ub4dor&3",
+            }),
+            check_json: serde_json::json!({
+                "error": "accessDenied",
+            }),
+            auth_token: USER_04_SESSION.into(),
+        },
+    }
+
+    #[tokio::test]
+    async fn change_password_fails_without_credentials() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            // as for users that only ever signed in through identity providers
+            sqlx::query("DELETE FROM credentials WHERE user_id = $1")
+                .bind(USER_01_ID)
+                .execute(&ctx.ctx().db_pool)
+                .await
+                .unwrap_or_log();
+            let app = crate::user::router().layer(axum::Extension(ctx.ctx()));
+            let resp = app
+                .oneshot(
+                    http::Request::builder()
+                        .method("POST")
+                        .uri(format!("/users/{USER_01_ID}/password"))
+                        .header(
+                            axum::http::header::AUTHORIZATION,
+                            format!("Bearer {USER_01_SESSION}"),
+                        )
+                        .header(axum::http::header::CONTENT_TYPE, "application/json")
+                        .body(
+                            serde_json::to_vec(&serde_json::json!({
+                                "currentPassword": "password",
+                                "newPassword": "Tr0ub4dor&3",
+                            }))
+                            .unwrap()
+                            .into(),
+                        )
+                        .unwrap_or_log(),
+                )
+                .await
+                .unwrap_or_log();
+            assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+            let body = hyper::body::to_bytes(resp.into_body())
+                .await
+                .unwrap_or_log();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_log();
+            check_json(
+                ("expected", &serde_json::json!({ "error": "noPassword" })),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+}
@@ -16,7 +16,7 @@ pub struct Request {
     pub username: String,
     #[validate(email)]
     pub email: String,
-    #[validate(length(min = 8))]
+    /// Has to satisfy the password policy.
     pub password: String,
 }
 
@@ -50,13 +50,19 @@ impl Endpoint for CreateUser {
         ctx: &crate::Context,
         request: Self::Request,
     ) -> Result<Self::Response, Self::Error> {
-        validator::Validate::validate(&request).map_err(utils::ValidationErrors::from)?;
-        let pass_hash = argon2::hash_encoded(
-            request.password.as_bytes(),
-            &ctx.config.pass_salt_hash,
-            &ctx.config.argon2_conf,
-        )
-        .unwrap_or_log();
+        let mut issues = validator::Validate::validate(&request)
+            .err()
+            .unwrap_or_else(validator::ValidationErrors::new);
+        ctx.config.password_policy.check(
+            &mut issues,
+            "password",
+            &request.password,
+            &[&request.username, &request.email],
+        );
+        if !issues.is_empty() {
+            return Err(utils::ValidationErrors::from(issues).into());
+        }
+        let pass_hash = crate::auth::password::hash(&ctx.config, &request.password);
         let user = sqlx::query_as!(
             super::User,
             r#"
@@ -66,7 +72,8 @@ SELECT
     updated_at as "updated_at!",
     email::TEXT as "email!",
     username::TEXT as "username!",
-    pic_url
+    pic_url,
+    email_verified_at
 FROM create_user($1::TEXT::CITEXT, $2::TEXT::CITEXT, $3)
                 "#,
             &request.username,
@@ -93,7 +100,11 @@ FROM create_user($1::TEXT::CITEXT, $2::TEXT::CITEXT, $3)
                 message: format!("db error: {err}"),
             },
         })?;
-        // TODO: email notification, account activation
+        crate::auth::verify_email::send_verification(ctx, user.id, &user.email)
+            .await
+            .map_err(|err| Error::Internal {
+                message: format!("db error: {err}"),
+            })?;
         Ok(user.into())
     }
 }
@@ -138,6 +149,7 @@ impl DocumentedEndpoint for CreateUser {
             email: USER_01_EMAIL.into(),
             username: USER_01_USERNAME.into(),
             pic_url: Some("https:://example.com/picture.jpg".into()),
+            email_verified_at: Some(time::OffsetDateTime::now_utc()),
         }]
         .into_iter()
         .map(serde_json::to_value)
@@ -210,7 +222,7 @@ mod tests {
         serde_json::json!({
             "username": "whish_box12",
             "email": "multis@cream.mux",
-            "password": "lovebite",
+            "password": "lovebite!Rx9",
         })
     }
 
@@ -284,13 +296,6 @@ mod tests {
             },
             Some("username"),
         ),
-        rejects_too_short_passwords: (
-            Request {
-                password: "short".into(),
-                ..fixture_request()
-            },
-            Some("password"),
-        ),
         rejects_invalid_emails: (
             Request {
                 email: "invalid".into(),
@@ -337,11 +342,18 @@ mod tests {
                 Box::pin(async move {
                     let req_body_json = fixture_request_json();
                     let resp_body_json = response_json.unwrap();
+                    assert!(resp_body_json["emailVerifiedAt"].is_null());
+                    assert_eq!(
+                        ctx.mailer.outbox.lock()[0].to,
+                        req_body_json["email"].as_str().unwrap()
+                    );
                     // TODO: use super user token
                     let token = authenticate::Authenticate.handle(&ctx.ctx(), authenticate::Request{
                         identifier: req_body_json["username"].as_str().unwrap().into(),
-                        password: req_body_json["password"].as_str().unwrap().into()
-                    }).await.unwrap_or_log().token;
+                        password: req_body_json["password"].as_str().unwrap().into(),
+                        device_label: None,
+                        client: None,
+                    }).await.unwrap_or_log().into_tokens().unwrap().token;
 
                     let app = crate::user::router().layer(axum::Extension(ctx.ctx()));
                     let resp = app
@@ -369,6 +381,34 @@ mod tests {
                 })
             },
         },
+        fails_on_weak_passwords: {
+            status: http::StatusCode::BAD_REQUEST,
+            body: fixture_request_json().destructure_into_self(
+                serde_json::json!({ "password": "whish_box12" })
+            ),
+            check_json: serde_json::json!({
+                "error": "invalidInput",
+                "issues": {
+                    "password": [
+                        { "code": "personal_info" },
+                    ],
+                },
+            }),
+        },
+        fails_on_too_short_passwords: {
+            status: http::StatusCode::BAD_REQUEST,
+            body: fixture_request_json().destructure_into_self(
+                serde_json::json!({ "password": "Sh0r+!x" })
+            ),
+            check_json: serde_json::json!({
+                "error": "invalidInput",
+                "issues": {
+                    "password": [
+                        { "code": "length", "params": { "min": 8 } },
+                    ],
+                },
+            }),
+        },
         fails_if_username_occupied: {
             status: http::StatusCode::BAD_REQUEST,
             body: fixture_request_json().destructure_into_self(
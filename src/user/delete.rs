@@ -32,6 +32,8 @@ impl crate::AuthenticatedEndpoint for DeleteUser {
     type Response = Response;
     type Error = Error;
 
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
     fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
         crate::auth::authorize::Request {
             auth_token: request.auth_token.clone(),
@@ -44,23 +46,43 @@ impl crate::AuthenticatedEndpoint for DeleteUser {
     async fn handle(
         &self,
         ctx: &crate::Context,
-        _accessing_user: uuid::Uuid,
+        _accessing_user: crate::auth::authorize::Response,
         request: Self::Request,
     ) -> Result<Self::Response, Self::Error> {
         let id = request.id;
 
+        let mut tx = ctx.db_pool.begin().await.map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
+        let tokens = sqlx::query_scalar!(
+            r#"
+SELECT token
+FROM sessions
+WHERE user_id = $1
+            "#,
+            &id
+        )
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
         let was_deleted = sqlx::query!(
             r#"
 SELECT delete_user($1)
             "#,
             &id
         )
-        .fetch_one(&ctx.db_pool)
+        .fetch_one(&mut tx)
         .await
         .map_err(|err| Error::Internal {
             message: format!("db error: {err}"),
         })?;
+        tx.commit().await.map_err(|err| Error::Internal {
+            message: format!("db error: {err}"),
+        })?;
         tracing::trace!(?was_deleted);
+        ctx.session_cache.invalidate(tokens).await;
         Ok(NoContent)
     }
 }
@@ -169,12 +191,29 @@ mod tests {
                         .await
                         .unwrap_or_log();
                     assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+                    // sessions of deleted users are no longer served from the cache
+                    let app = crate::user::router().layer(axum::Extension(ctx.ctx()));
+                    let resp = app
+                        .oneshot(
+                            http::Request::builder()
+                                .method("GET")
+                                .uri(format!("/users/{USER_04_ID}"))
+                                .header(
+                                    axum::http::header::AUTHORIZATION,
+                                    format!("Bearer {USER_01_SESSION}"),
+                                )
+                                .body(Default::default())
+                                .unwrap_or_log(),
+                        )
+                        .await
+                        .unwrap_or_log();
+                    assert_eq!(resp.status(), http::StatusCode::UNAUTHORIZED);
                 })
             },
         },
         is_idempotent: {
             uri: format!("/users/{}", uuid::Uuid::new_v4()),
-            auth_token: USER_01_SESSION.into(), // FIXME: use super user session
+            auth_token: USER_04_SESSION.into(),
             status: StatusCode::NO_CONTENT,
         },
     }
@@ -48,7 +48,7 @@ impl crate::AuthenticatedEndpoint for GetUser {
     async fn handle(
         &self,
         ctx: &crate::Context,
-        _accessing_user: uuid::Uuid,
+        _accessing_user: crate::auth::authorize::Response,
         request: Self::Request,
     ) -> Result<Self::Response, Self::Error> {
         let id = request.id;
@@ -62,7 +62,8 @@ SELECT
     updated_at,
     email::TEXT as "email!",
     username::TEXT as "username!",
-    pic_url
+    pic_url,
+    email_verified_at
 FROM users
 WHERE id = $1::uuid
             "#,
@@ -123,6 +124,7 @@ impl DocumentedEndpoint for GetUser {
             email: USER_01_EMAIL.into(),
             username: USER_01_USERNAME.into(),
             pic_url: Some("https:://example.com/picture.jpg".into()),
+            email_verified_at: Some(time::OffsetDateTime::now_utc()),
         }]
         .into_iter()
         .map(serde_json::to_value)
@@ -198,7 +200,7 @@ mod tests {
         },
         fails_if_not_found: {
             uri: format!("/users/{}", uuid::Uuid::new_v4()),
-            auth_token: USER_01_SESSION.into(), // FIXME: use super user session
+            auth_token: USER_04_SESSION.into(),
             status: StatusCode::NOT_FOUND,
             check_json: serde_json::json!({
                 "error": "notFound",
@@ -27,6 +27,14 @@ impl SortingField for UserSortingField {
         }
         .into()
     }
+
+    #[inline]
+    fn sql_field_type(&self) -> &'static str {
+        match self {
+            Self::Username | Self::Email => "CITEXT",
+            Self::CreatedAt | Self::UpdatedAt => "TIMESTAMPTZ",
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -70,168 +78,74 @@ impl crate::AuthenticatedEndpoint for ListUsers {
     async fn handle(
         &self,
         ctx: &crate::Context,
-        _accessing_user: uuid::Uuid,
+        _accessing_user: crate::auth::authorize::Response,
         Request(request): Self::Request,
     ) -> Result<Self::Response, Self::Error> {
         validator::Validate::validate(&request).map_err(utils::ValidationErrors::from)?;
-        let (cursor_clause, sorting_field, sorting_order, filter) = request
-            .after_cursor
-            .map(|cursor| (true, cursor))
-            .or_else(|| request.before_cursor.map(|cursor| (false, cursor)))
-            .map(|(is_after, cursor)| {
-                let invalid_cursor_err = |msg| Error::InvalidInput {
-                    issues: {
-                        let mut issues = validator::ValidationErrors::new();
-                        let cursor_field = if is_after {
-                            "afterCursor"
-                        } else {
-                            "beforeCursor"
-                        };
-                        issues.add(
-                            cursor_field,
-                            validator::ValidationError {
-                                code: "invalid_cursor".into(),
-                                message: Some(msg),
-                                params: [(
-                                    std::borrow::Cow::from("value"),
-                                    serde_json::json!(cursor),
-                                )]
-                                .into_iter()
-                                .collect(),
-                            },
-                        );
-                        issues.into()
-                    },
-                };
-                let cursor: Cursor<serde_json::Value, UserSortingField> = cursor
-                    .parse()
-                    .map_err(|_| invalid_cursor_err("unable to decode cursor".into()))?;
-                // let op = match (cursor.order, is_after) {
-                //     (SortingOrder::Ascending, true) | (SortingOrder::Descending, false) => ">",
-                //     (SortingOrder::Ascending, false) | (SortingOrder::Descending, true) => "<",
-                // };
-                let op = if is_after { ">" } else { "<" };
-                // FIXME: sql injection
-                let clause = match cursor.field {
-                    UserSortingField::Username | UserSortingField::Email => {
-                        let value = cursor
-                            .value
-                            .as_str()
-                            .ok_or_else(|| invalid_cursor_err("nonsensical cursor".into()))?;
-                        let column = cursor.field.sql_field_name();
-                        format!("WHERE {column} {op} '{value}'")
-                    }
-                    UserSortingField::CreatedAt | UserSortingField::UpdatedAt => {
-                        let value = cursor
-                            .value
-                            .as_i64()
-                            .ok_or_else(|| invalid_cursor_err("nonsensical cursor".into()))?;
-                        let column = cursor.field.sql_field_name();
-                        format!("WHERE {column} {op} (TO_TIMESTAMP({value}) AT TIME ZONE 'UTC')")
-                    }
-                };
-                Ok::<_, Error>((clause.into(), cursor.field, cursor.order, cursor.filter))
-            })
-            .unwrap_or_else(|| {
-                Ok((
-                    std::borrow::Cow::from(""),
-                    request.sorting_field.unwrap_or(UserSortingField::CreatedAt),
-                    request.sorting_order.unwrap_or(SortingOrder::Descending),
-                    request.filter,
-                ))
-            })?;
-        let (sorting_field_str, sorting_order_str) =
-            (sorting_field.sql_field_name(), sorting_order.sql_key_word());
-        let limit = request.limit.unwrap_or(DEFAULT_LIST_LIMIT);
-        let results = sqlx::query(
-            format!(
-                r#"
-SELECT 
+        let keyset = Keyset::from_request(
+            request,
+            UserSortingField::CreatedAt,
+            SortingOrder::Descending,
+            &ctx.config.cursors,
+            ctx.clock.now(),
+        )?;
+        let push_from = |query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>| {
+            query.push(" FROM users WHERE TRUE");
+            if let Some(filter) = &keyset.filter {
+                query
+                    .push(" AND (username ILIKE '%' || ")
+                    .push_bind(filter.clone())
+                    .push(" || '%' OR email ILIKE '%' || ")
+                    .push_bind(filter.clone())
+                    .push(" || '%')");
+            }
+        };
+        let mut query = sqlx::QueryBuilder::new(
+            r#"
+SELECT
     id,
     created_at,
     updated_at,
     email::TEXT as "email!",
     username::TEXT as "username!",
-    pic_url
-FROM (
-    SELECT *
-    FROM users
-    WHERE cast($1 as text) IS NULL OR (
-        username ILIKE '%%' || $1 || '%%'
-        OR email ILIKE '%%' || $1 || '%%'
-    )
-    ORDER BY {sorting_field_str}, id {sorting_order_str}
-    NULLS LAST
-) as f
-{cursor_clause}
--- fetch one more to check if we have more data 
--- (counts are expensive or something)
-LIMIT $2 + 1 
-        "#
-            )
-            .as_str(),
-        )
-        .bind(filter.as_ref())
-        .bind(limit as i64)
-        .fetch_all(&ctx.db_pool)
-        .await;
-        match results {
-            Ok(results) => {
-                let more_rows_pending = results.len() == limit + 1;
-                let items = results
-                    .into_iter()
-                    .take(limit as _)
-                    .map(|row| {
-                        use sqlx::Row;
-                        Ok::<_, sqlx::Error>(User {
-                            id: row.try_get("id")?,
-                            created_at: row.try_get("created_at")?,
-                            updated_at: row.try_get("updated_at")?,
-                            username: row.try_get("username!")?,
-                            email: row.try_get("email!")?,
-                            pic_url: row.try_get("pic_url")?,
-                        })
+    pic_url,
+    email_verified_at
+            "#,
+        );
+        keyset.push_key_columns(&mut query);
+        push_from(&mut query);
+        query.push(" AND ");
+        keyset.push_condition(&mut query);
+        keyset.push_order_and_limit(&mut query);
+        let mut page = query
+            .build()
+            .fetch_all(&ctx.db_pool)
+            .await
+            .and_then(|rows| {
+                keyset.paginate(rows, &ctx.config.cursors, ctx.clock.now(), |row| {
+                    use sqlx::Row;
+                    Ok(User {
+                        id: row.try_get("id")?,
+                        created_at: row.try_get("created_at")?,
+                        updated_at: row.try_get("updated_at")?,
+                        username: row.try_get("username!")?,
+                        email: row.try_get("email!")?,
+                        pic_url: row.try_get("pic_url")?,
+                        email_verified_at: row.try_get("email_verified_at")?,
                     })
-                    .collect::<Result<Vec<_>, _>>()
-                    .map_err(|err| Error::Internal {
-                        message: format!("row mapping err: {err}"),
-                    })?;
-                let cursor = if more_rows_pending {
-                    Some(
-                        Cursor {
-                            value: {
-                                let last = items.last().unwrap();
-                                match sorting_field {
-                                    UserSortingField::Username => serde_json::json!(last.username),
-                                    UserSortingField::Email => serde_json::json!(last.email),
-                                    UserSortingField::CreatedAt => {
-                                        serde_json::json!(last.created_at.unix_timestamp())
-                                    }
-                                    UserSortingField::UpdatedAt => {
-                                        serde_json::json!(last.updated_at.unix_timestamp())
-                                    }
-                                }
-                            },
-                            field: sorting_field,
-                            order: sorting_order,
-                            filter,
-                        }
-                        .to_encoded_str(),
-                    )
-                } else {
-                    None
-                };
-                Ok(ListUsersResponse { cursor, items }.into())
-            }
-            Err(sqlx::Error::RowNotFound) => Ok(ListUsersResponse {
-                cursor: None,
-                items: vec![],
-            }
-            .into()),
-            Err(err) => Err(Error::Internal {
+                })
+            })
+            .map_err(|err| Error::Internal {
                 message: format!("db err: {err}"),
-            }),
-        }
+            })?;
+        page.total =
+            keyset
+                .total(&ctx.db_pool, push_from)
+                .await
+                .map_err(|err| Error::Internal {
+                    message: format!("db err: {err}"),
+                })?;
+        Ok(page.into())
     }
 }
 
@@ -250,11 +164,17 @@ impl HttpEndpoint for ListUsers {
     const METHOD: Method = Method::Get;
     const PATH: &'static str = "/users";
 
-    type HttpRequest = (BearerToken, Json<Request>);
+    /// The JSON body, when there's one, takes precedence over the query
+    /// string for clients still sending their parameters that way.
+    type HttpRequest = (BearerToken, Query<Request>, Option<Json<Request>>);
 
     fn request(
-        (BearerToken(token), Json(Request(request))): Self::HttpRequest,
+        (BearerToken(token), Query(Request(query)), body): Self::HttpRequest,
     ) -> Result<Self::Request, Self::Error> {
+        let request = match body {
+            Some(Json(Request(body))) => body,
+            None => query,
+        };
         Ok(ListUsersRequest {
             auth_token: Some(token),
             ..request
@@ -273,7 +193,11 @@ impl DocumentedEndpoint for ListUsers {
     fn success_examples() -> Vec<serde_json::Value> {
         use crate::user::testing::*;
         [ListUsersResponse {
-            cursor: None,
+            next_cursor: None,
+            prev_cursor: None,
+            has_more: false,
+            limit: DEFAULT_LIST_LIMIT,
+            total: None,
             items: vec![
                 User {
                     id: Default::default(),
@@ -282,6 +206,7 @@ impl DocumentedEndpoint for ListUsers {
                     email: USER_01_EMAIL.into(),
                     username: USER_01_USERNAME.into(),
                     pic_url: Some("https:://example.com/picture.jpg".into()),
+                    email_verified_at: Some(time::OffsetDateTime::now_utc()),
                 },
                 User {
                     id: Default::default(),
@@ -290,6 +215,7 @@ impl DocumentedEndpoint for ListUsers {
                     email: USER_02_EMAIL.into(),
                     username: USER_02_USERNAME.into(),
                     pic_url: None,
+                    email_verified_at: None,
                 },
             ],
         }]
@@ -391,6 +317,7 @@ mod tests {
                 filter: None,
                 sorting_field: None,
                 sorting_order: None,
+                include_total: None,
             },
             Some("__all__"),
         ),
@@ -435,7 +362,7 @@ mod tests {
 
     list_users_integ! {
         works: {
-            auth_token: USER_01_SESSION.into(),
+            auth_token: USER_04_SESSION.into(),
             status: StatusCode::OK,
             body: fixture_request_json().destructure_into_self(serde_json::json!({
                 "limit": 2,
@@ -445,7 +372,7 @@ mod tests {
                 Box::pin(async move {
                     let resp_body_json = response_json.unwrap();
                     assert_eq!(resp_body_json["items"].as_array().unwrap().len(), 2);
-                    assert!(resp_body_json["cursor"].as_str().is_some());
+                    assert!(resp_body_json["nextCursor"].as_str().is_some());
                     let app = crate::user::router().layer(axum::Extension(ctx.ctx()));
                     let resp = app
                         .oneshot(
@@ -454,13 +381,13 @@ mod tests {
                                 .uri("/users")
                                 .header(
                                     http::header::AUTHORIZATION,
-                                    format!("Bearer {USER_01_SESSION}"),
+                                    format!("Bearer {USER_04_SESSION}"),
                                 )
                                 .header(axum::http::header::CONTENT_TYPE, "application/json")
                                 .body(
                                     serde_json::to_vec(
                                         &serde_json::json!({
-                                            "afterCursor": resp_body_json["cursor"]
+                                            "afterCursor": resp_body_json["nextCursor"]
                                                                 .as_str()
                                                                 .unwrap()
                                         })
@@ -480,11 +407,395 @@ mod tests {
                         "{resp_body_json:?}\n{body:?}"
                     );
                     assert!(
-                        body["cursor"].is_null(),
+                        body["nextCursor"].is_null(),
                         "{resp_body_json:?}\n{body:?}"
                     );
                 })
             },
         },
+        fails_if_not_admin: {
+            auth_token: USER_01_SESSION.into(),
+            status: StatusCode::UNAUTHORIZED,
+            body: fixture_request_json(),
+            check_json: serde_json::json!({
+                "error": "accessDenied",
+            }),
+        },
+    }
+
+    async fn list_users_with_query(
+        ctx: &TestContext,
+        query: &[(&str, &str)],
+    ) -> (StatusCode, serde_json::Value) {
+        let mut url = reqwest::Url::parse("http://localhost/users").unwrap();
+        url.query_pairs_mut().extend_pairs(query);
+        let app = crate::user::router().layer(axum::Extension(ctx.ctx()));
+        let resp = app
+            .oneshot(
+                http::Request::builder()
+                    .method("GET")
+                    .uri(format!("/users?{}", url.query().unwrap()))
+                    .header(
+                        http::header::AUTHORIZATION,
+                        format!("Bearer {USER_04_SESSION}"),
+                    )
+                    .body(Default::default())
+                    .unwrap_or_log(),
+            )
+            .await
+            .unwrap_or_log();
+        let (head, body) = resp.into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap_or_log();
+        (
+            head.status,
+            serde_json::from_slice(&body).unwrap_or_default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn list_users_takes_query_parameters() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (status, body) = list_users_with_query(
+                &ctx,
+                &[
+                    ("limit", "2"),
+                    ("sortingField", "username"),
+                    ("sortingOrder", "ascending"),
+                ],
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            let usernames = |body: &serde_json::Value| {
+                body["items"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|item| item["username"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                usernames(&body),
+                vec![USER_02_USERNAME.to_string(), USER_03_USERNAME.into()]
+            );
+
+            // base64 cursors need the encoding
+            let cursor = body["nextCursor"].as_str().unwrap().to_string();
+            let (status, body) = list_users_with_query(&ctx, &[("afterCursor", &cursor)]).await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            assert_eq!(
+                usernames(&body),
+                vec![USER_01_USERNAME.to_string(), USER_04_USERNAME.into()]
+            );
+
+            let (status, body) = list_users_with_query(&ctx, &[("filter", USER_02_USERNAME)]).await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            assert_eq!(usernames(&body), vec![USER_02_USERNAME.to_string()]);
+
+            let (status, _) = list_users_with_query(&ctx, &[("limit", "0")]).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn list_users_binds_cursor_values() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            // as if the signing key leaked
+            let cursor = Cursor {
+                value: ("zzz' OR TRUE --".to_string(), uuid::Uuid::nil().to_string()),
+                field: UserSortingField::Username,
+                order: SortingOrder::Ascending,
+                filter: None,
+                inclusive: false,
+            }
+            .sign(&ctx.ctx().config.cursors, time::OffsetDateTime::now_utc());
+            let (status, body) = list_users_with_query(&ctx, &[("afterCursor", &cursor)]).await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            assert_eq!(body["items"], serde_json::json!([]), "{body:?}");
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn list_users_links_empty_pages_back() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            // sorts before every user
+            let cursor = Cursor {
+                value: (String::new(), uuid::Uuid::nil().to_string()),
+                field: UserSortingField::Username,
+                order: SortingOrder::Ascending,
+                filter: None,
+                inclusive: false,
+            }
+            .sign(&ctx.ctx().config.cursors, time::OffsetDateTime::now_utc());
+            let (status, body) =
+                list_users_with_query(&ctx, &[("limit", "2"), ("beforeCursor", &cursor)]).await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            assert_eq!(body["items"], serde_json::json!([]), "{body:?}");
+            assert!(body["prevCursor"].is_null(), "{body:?}");
+
+            let cursor = body["nextCursor"]
+                .as_str()
+                .expect("no nextCursor")
+                .to_string();
+            let (status, body) =
+                list_users_with_query(&ctx, &[("limit", "2"), ("afterCursor", &cursor)]).await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({
+                        "items": [
+                            { "username": USER_02_USERNAME },
+                            { "username": USER_03_USERNAME },
+                        ]
+                    }),
+                ),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+
+    /// Pages through all the users forwards, then back from the last page.
+    async fn walk_users(
+        ctx: &TestContext,
+        limit: &str,
+        sorting: &[(&str, &str)],
+    ) -> (Vec<String>, Vec<String>) {
+        let usernames = |body: &serde_json::Value| {
+            body["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["username"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        let mut query = vec![("limit", limit)];
+        query.extend_from_slice(sorting);
+        let (status, mut body) = list_users_with_query(ctx, &query).await;
+        assert_eq!(status, StatusCode::OK, "{body:?}");
+        assert!(body["prevCursor"].is_null(), "{body:?}");
+        let mut forwards = usernames(&body);
+        while let Some(cursor) = body["nextCursor"].as_str().map(String::from) {
+            (_, body) =
+                list_users_with_query(ctx, &[("limit", limit), ("afterCursor", &cursor)]).await;
+            forwards.extend(usernames(&body));
+        }
+        let mut backwards = usernames(&body);
+        while let Some(cursor) = body["prevCursor"].as_str().map(String::from) {
+            (_, body) =
+                list_users_with_query(ctx, &[("limit", limit), ("beforeCursor", &cursor)]).await;
+            assert!(body["nextCursor"].is_string(), "{body:?}");
+            backwards.splice(0..0, usernames(&body));
+        }
+        (forwards, backwards)
+    }
+
+    #[tokio::test]
+    async fn list_users_pages_both_ways() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let ascending = vec![
+                USER_02_USERNAME.to_string(),
+                USER_03_USERNAME.into(),
+                USER_01_USERNAME.into(),
+                USER_04_USERNAME.into(),
+            ];
+            let descending = ascending.iter().cloned().rev().collect::<Vec<_>>();
+            for limit in ["1", "3", "4"] {
+                let (forwards, backwards) = walk_users(
+                    &ctx,
+                    limit,
+                    &[("sortingField", "username"), ("sortingOrder", "ascending")],
+                )
+                .await;
+                assert_eq!(forwards, ascending, "limit {limit}");
+                assert_eq!(backwards, ascending, "limit {limit}");
+
+                let (forwards, backwards) = walk_users(
+                    &ctx,
+                    limit,
+                    &[("sortingField", "username"), ("sortingOrder", "descending")],
+                )
+                .await;
+                assert_eq!(forwards, descending, "limit {limit}");
+                assert_eq!(backwards, descending, "limit {limit}");
+
+                let (forwards, backwards) = walk_users(
+                    &ctx,
+                    limit,
+                    &[
+                        ("sortingField", "createdAt"),
+                        ("sortingOrder", "descending"),
+                    ],
+                )
+                .await;
+                assert_eq!(forwards.len(), ascending.len(), "limit {limit}");
+                assert_eq!(backwards, forwards, "limit {limit}");
+            }
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn list_users_rejects_tampered_and_expired_cursors() {
+        let ctx = TestContext::with_config(crate::function!(), |config| {
+            config.cursors.max_age = Some(time::Duration::hours(1));
+        })
+        .await;
+        {
+            let now = time::OffsetDateTime::now_utc();
+            ctx.clock.set(now);
+            let (status, body) = list_users_with_query(&ctx, &[("limit", "1")]).await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            let cursor = body["nextCursor"].as_str().unwrap().to_string();
+
+            let (payload, _) = cursor.rsplit_once(':').unwrap();
+            let tampered = format!("{payload}:{}", base64::encode([0u8; 32]));
+            let (status, body) = list_users_with_query(&ctx, &[("afterCursor", &tampered)]).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({
+                        "error": "invalidInput",
+                        "issues": {
+                            "afterCursor": [{
+                                "code": "invalid_cursor",
+                                "message": "bad cursor signature",
+                            }]
+                        }
+                    }),
+                ),
+                ("response", &body),
+            );
+
+            ctx.clock.set(now + time::Duration::hours(2));
+            let (status, body) = list_users_with_query(&ctx, &[("afterCursor", &cursor)]).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(
+                body["issues"]["afterCursor"][0]["message"], "cursor expired",
+                "{body:?}"
+            );
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn list_users_includes_totals_on_request() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let (status, body) = list_users_with_query(&ctx, &[]).await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({
+                        "hasMore": false,
+                        "limit": crate::utils::DEFAULT_LIST_LIMIT,
+                        "total": null,
+                    }),
+                ),
+                ("response", &body),
+            );
+
+            let (status, body) =
+                list_users_with_query(&ctx, &[("limit", "1"), ("includeTotal", "true")]).await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({
+                        "hasMore": true,
+                        "limit": 1,
+                        "total": { "count": 4, "estimated": false },
+                    }),
+                ),
+                ("response", &body),
+            );
+
+            // the total isn't affected by the cursor but by the filter it carries
+            let (_, body) = list_users_with_query(
+                &ctx,
+                &[
+                    ("limit", "1"),
+                    ("filter", "ie"),
+                    ("sortingField", "username"),
+                    ("sortingOrder", "ascending"),
+                ],
+            )
+            .await;
+            let (status, body) = list_users_with_query(
+                &ctx,
+                &[
+                    ("afterCursor", body["nextCursor"].as_str().unwrap()),
+                    ("includeTotal", "true"),
+                ],
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({
+                        "hasMore": false,
+                        "total": { "count": 2, "estimated": false },
+                        "items": [{ "username": USER_04_USERNAME }],
+                    }),
+                ),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn list_users_estimates_large_totals() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let db_pool = &ctx.ctx().db_pool;
+            sqlx::query(
+                r#"
+INSERT INTO users (username, email)
+SELECT 'user' || i, 'user' || i || '@example.com'
+FROM generate_series(1, 20000) as i
+                "#,
+            )
+            .execute(db_pool)
+            .await
+            .unwrap_or_log();
+            sqlx::query("ANALYZE users")
+                .execute(db_pool)
+                .await
+                .unwrap_or_log();
+
+            let (status, body) = list_users_with_query(&ctx, &[("includeTotal", "true")]).await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            assert_eq!(body["total"]["estimated"], true, "{body:?}");
+            let count = body["total"]["count"].as_i64().unwrap();
+            assert!((18_000..22_000).contains(&count), "{body:?}");
+
+            // filtered down below the limit
+            let (status, body) = list_users_with_query(
+                &ctx,
+                &[("filter", USER_01_USERNAME), ("includeTotal", "true")],
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{body:?}");
+            check_json(
+                (
+                    "expected",
+                    &serde_json::json!({
+                        "total": { "count": 1, "estimated": false },
+                    }),
+                ),
+                ("response", &body),
+            );
+        }
+        ctx.close().await;
     }
 }
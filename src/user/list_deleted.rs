@@ -0,0 +1,415 @@
+use deps::*;
+
+use axum::extract::*;
+
+use crate::utils::*;
+use crate::*;
+
+use super::DeletedUser;
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub enum DeletedUserSortingField {
+    DeletedAt,
+}
+
+impl SortingField for DeletedUserSortingField {
+    #[inline]
+    fn sql_field_name(&self) -> String {
+        match self {
+            Self::DeletedAt => "deleted_at",
+        }
+        .into()
+    }
+
+    #[inline]
+    fn sql_field_type(&self) -> &'static str {
+        match self {
+            Self::DeletedAt => "TIMESTAMPTZ",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ListDeletedUsers;
+
+crate::alias_and_ref!(
+    ListRequest<DeletedUserSortingField>,
+    ListDeletedUsersRequest,
+    Request,
+    de
+);
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("invalid input: {issues:?}")]
+    InvalidInput {
+        #[from]
+        issues: ValidationErrors,
+    },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+crate::alias_and_ref!(
+    ListResponse<DeletedUser>,
+    ListDeletedUsersResponse,
+    Response,
+    ser
+);
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for ListDeletedUsers {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone().unwrap(),
+            resource: crate::auth::Resource::DeletedUsers,
+            action: crate::auth::Action::Read,
+        }
+    }
+
+    // #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        _accessing_user: crate::auth::authorize::Response,
+        Request(request): Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        validator::Validate::validate(&request).map_err(utils::ValidationErrors::from)?;
+        let keyset = Keyset::from_request(
+            request,
+            DeletedUserSortingField::DeletedAt,
+            SortingOrder::Descending,
+            &ctx.config.cursors,
+            ctx.clock.now(),
+        )?;
+        let push_from = |query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>| {
+            query.push(" FROM users_deleted WHERE TRUE");
+            if let Some(filter) = &keyset.filter {
+                query
+                    .push(" AND (username ILIKE '%' || ")
+                    .push_bind(filter.clone())
+                    .push(" || '%' OR email ILIKE '%' || ")
+                    .push_bind(filter.clone())
+                    .push(" || '%')");
+            }
+        };
+        let mut query = sqlx::QueryBuilder::new(
+            r#"
+SELECT
+    id,
+    created_at,
+    updated_at,
+    deleted_at,
+    email::TEXT as email,
+    username::TEXT as username,
+    pic_url,
+    email_verified_at
+            "#,
+        );
+        keyset.push_key_columns(&mut query);
+        push_from(&mut query);
+        query.push(" AND ");
+        keyset.push_condition(&mut query);
+        keyset.push_order_and_limit(&mut query);
+        let mut page = query
+            .build()
+            .fetch_all(&ctx.db_pool)
+            .await
+            .and_then(|rows| {
+                keyset.paginate(rows, &ctx.config.cursors, ctx.clock.now(), |row| {
+                    use sqlx::Row;
+                    Ok(DeletedUser {
+                        id: row.try_get("id")?,
+                        created_at: row.try_get("created_at")?,
+                        updated_at: row.try_get("updated_at")?,
+                        deleted_at: row.try_get("deleted_at")?,
+                        email: row.try_get("email")?,
+                        username: row.try_get("username")?,
+                        pic_url: row.try_get("pic_url")?,
+                        email_verified_at: row.try_get("email_verified_at")?,
+                    })
+                })
+            })
+            .map_err(|err| Error::Internal {
+                message: format!("db err: {err}"),
+            })?;
+        page.total =
+            keyset
+                .total(&ctx.db_pool, push_from)
+                .await
+                .map_err(|err| Error::Internal {
+                    message: format!("db err: {err}"),
+                })?;
+        Ok(page.into())
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            InvalidInput { .. } => Self::BAD_REQUEST,
+            AccessDenied => Self::UNAUTHORIZED,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for ListDeletedUsers {
+    const METHOD: Method = Method::Get;
+    const PATH: &'static str = "/deleted-users";
+
+    /// The JSON body, when there's one, takes precedence over the query string.
+    type HttpRequest = (BearerToken, Query<Request>, Option<Json<Request>>);
+
+    fn request(
+        (BearerToken(token), Query(Request(query)), body): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        let request = match body {
+            Some(Json(Request(body))) => body,
+            None => query,
+        };
+        Ok(ListDeletedUsersRequest {
+            auth_token: Some(token),
+            ..request
+        }
+        .into())
+    }
+
+    fn response(Response(resp): Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for ListDeletedUsers {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str = "List the deleted users that can still be restored.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        use crate::user::testing::*;
+        [ListDeletedUsersResponse {
+            next_cursor: None,
+            prev_cursor: None,
+            has_more: false,
+            limit: DEFAULT_LIST_LIMIT,
+            total: None,
+            items: vec![DeletedUser {
+                id: Default::default(),
+                created_at: time::OffsetDateTime::now_utc(),
+                updated_at: time::OffsetDateTime::now_utc(),
+                deleted_at: time::OffsetDateTime::now_utc(),
+                email: USER_01_EMAIL.into(),
+                username: USER_01_USERNAME.into(),
+                pic_url: Some("https:://example.com/picture.jpg".into()),
+                email_verified_at: Some(time::OffsetDateTime::now_utc()),
+            }],
+        }]
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Invalid input",
+                Error::InvalidInput {
+                    issues: {
+                        let mut issues = validator::ValidationErrors::new();
+                        issues.add(
+                            "limit",
+                            validator::ValidationError {
+                                code: std::borrow::Cow::from("range"),
+                                message: None,
+                                params: [(std::borrow::Cow::from("value"), serde_json::json!(0))]
+                                    .into_iter()
+                                    .collect(),
+                            },
+                        );
+                        issues.into()
+                    },
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::list_deleted::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+    use crate::Endpoint;
+
+    async fn delete_users(ctx: &TestContext) {
+        for id in [USER_01_ID, USER_02_ID, USER_03_ID] {
+            sqlx::query("SELECT delete_user($1)")
+                .bind(id)
+                .execute(&ctx.ctx().db_pool)
+                .await
+                .unwrap_or_log();
+        }
+    }
+
+    async fn list(
+        ctx: &TestContext,
+        request: ListDeletedUsersRequest,
+    ) -> Result<ListDeletedUsersResponse, Error> {
+        Endpoint::handle(
+            &ListDeletedUsers,
+            &ctx.ctx(),
+            ListDeletedUsersRequest {
+                auth_token: Some(USER_04_SESSION.into()),
+                ..request
+            }
+            .into(),
+        )
+        .await
+        .map(|Response(resp)| resp)
+    }
+
+    fn request() -> ListDeletedUsersRequest {
+        ListDeletedUsersRequest {
+            auth_token: None,
+            limit: None,
+            after_cursor: None,
+            before_cursor: None,
+            filter: None,
+            sorting_field: None,
+            sorting_order: None,
+            include_total: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn list_deleted_users_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            delete_users(&ctx).await;
+            let first = list(
+                &ctx,
+                ListDeletedUsersRequest {
+                    limit: Some(2),
+                    sorting_order: Some(SortingOrder::Ascending),
+                    ..request()
+                },
+            )
+            .await
+            .unwrap_or_log();
+            assert_eq!(first.items.len(), 2);
+            let second = list(
+                &ctx,
+                ListDeletedUsersRequest {
+                    after_cursor: first.next_cursor,
+                    ..request()
+                },
+            )
+            .await
+            .unwrap_or_log();
+            assert_eq!(second.items.len(), 1);
+            assert!(second.next_cursor.is_none());
+
+            let back = list(
+                &ctx,
+                ListDeletedUsersRequest {
+                    before_cursor: second.prev_cursor,
+                    ..request()
+                },
+            )
+            .await
+            .unwrap_or_log();
+            assert_eq!(
+                back.items.iter().map(|user| user.id).collect::<Vec<_>>(),
+                first.items.iter().map(|user| user.id).collect::<Vec<_>>()
+            );
+            assert!(back.prev_cursor.is_none());
+
+            let mut ids = first
+                .items
+                .iter()
+                .chain(second.items.iter())
+                .map(|user| user.id)
+                .collect::<Vec<_>>();
+            assert!(first.items[0].deleted_at <= first.items[1].deleted_at);
+            assert!(first.items[1].deleted_at <= second.items[0].deleted_at);
+            ids.sort();
+            let mut expected = vec![USER_01_ID, USER_02_ID, USER_03_ID];
+            expected.sort();
+            assert_eq!(ids, expected);
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn list_deleted_users_filters() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            delete_users(&ctx).await;
+            for filter in [USER_02_USERNAME, USER_02_EMAIL] {
+                let resp = list(
+                    &ctx,
+                    ListDeletedUsersRequest {
+                        filter: Some(filter.into()),
+                        ..request()
+                    },
+                )
+                .await
+                .unwrap_or_log();
+                assert_eq!(
+                    resp.items.iter().map(|user| user.id).collect::<Vec<_>>(),
+                    vec![USER_02_ID]
+                );
+                assert_eq!(resp.items[0].username, USER_02_USERNAME);
+            }
+        }
+        ctx.close().await;
+    }
+
+    crate::integration_table_tests! {
+        list_deleted_users_is_empty_without_deletions: {
+            uri: "/deleted-users",
+            method: "GET",
+            status: StatusCode::OK,
+            router: crate::user::router(),
+            body: serde_json::json!({}),
+            check_json: serde_json::json!({
+                "nextCursor": null,
+                "prevCursor": null,
+                "items": [],
+            }),
+            auth_token: USER_04_SESSION.into(),
+        },
+        list_deleted_users_fails_if_not_admin: {
+            uri: "/deleted-users",
+            method: "GET",
+            status: StatusCode::UNAUTHORIZED,
+            router: crate::user::router(),
+            body: serde_json::json!({}),
+            check_json: serde_json::json!({
+                "error": "accessDenied",
+            }),
+            auth_token: USER_01_SESSION.into(),
+        },
+    }
+}
@@ -0,0 +1,102 @@
+//! Erases deleted users for good once the retention window has passed.
+
+use deps::*;
+
+/// Erases users deleted before `retention` ago for good, archived
+/// credentials, roles, sessions and secrets included. Returns the number of
+/// users erased.
+#[tracing::instrument(skip(ctx))]
+pub async fn purge_deleted_users(
+    ctx: &crate::Context,
+    retention: time::Duration,
+) -> Result<u64, sqlx::Error> {
+    let purged = sqlx::query_scalar!(
+        r#"
+SELECT purge_deleted_users($1) as "purged!"
+        "#,
+        ctx.clock.now() - retention,
+    )
+    .fetch_one(&ctx.db_pool)
+    .await? as u64;
+    if purged > 0 {
+        tracing::info!(target: "audit", purged, "purged deleted users");
+    }
+    Ok(purged)
+}
+
+/// Purges every `interval` until the returned handle is aborted.
+pub fn spawn_purger(
+    ctx: crate::SharedContext,
+    interval: time::Duration,
+    retention: time::Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(
+            interval
+                .try_into()
+                .expect("purge interval must not be negative"),
+        );
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = purge_deleted_users(&ctx, retention).await {
+                tracing::error!(%err, "error purging deleted users");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+
+    #[tokio::test]
+    async fn purge_deleted_users_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let db_pool = &ctx.ctx().db_pool;
+            for id in [USER_01_ID, USER_02_ID] {
+                sqlx::query("SELECT delete_user($1)")
+                    .bind(id)
+                    .execute(db_pool)
+                    .await
+                    .unwrap_or_log();
+            }
+            sqlx::query(
+                "UPDATE users_deleted SET deleted_at = CURRENT_TIMESTAMP - '40 days'::INTERVAL WHERE id = $1",
+            )
+            .bind(USER_01_ID)
+            .execute(db_pool)
+            .await
+            .unwrap_or_log();
+
+            ctx.clock
+                .set(time::OffsetDateTime::now_utc() + time::Duration::days(20));
+            let purged = super::purge_deleted_users(&ctx.ctx(), time::Duration::days(30))
+                .await
+                .unwrap_or_log();
+            assert_eq!(purged, 1);
+
+            for (table, column) in [
+                ("users_deleted", "id"),
+                ("credentials_deleted", "user_id"),
+                ("sessions_deleted", "user_id"),
+            ] {
+                let remaining = sqlx::query_scalar::<_, uuid::Uuid>(&format!(
+                    "SELECT DISTINCT {column} FROM {table}"
+                ))
+                .fetch_all(db_pool)
+                .await
+                .unwrap_or_log();
+                assert!(!remaining.contains(&USER_01_ID), "{table}");
+                if table != "sessions_deleted" {
+                    assert!(remaining.contains(&USER_02_ID), "{table}");
+                }
+            }
+        }
+        ctx.close().await;
+    }
+}
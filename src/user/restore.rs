@@ -0,0 +1,434 @@
+use deps::*;
+
+use axum::extract::*;
+
+use crate::*;
+
+use super::User;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RestoreUser;
+
+#[derive(Debug)]
+pub struct Request {
+    pub auth_token: std::sync::Arc<str>,
+    pub id: uuid::Uuid,
+}
+
+#[derive(Debug, thiserror::Error, serde::Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", tag = "error", rename_all = "camelCase")]
+pub enum Error {
+    #[error("no deleted user at id: {id:?}")]
+    NotFound { id: uuid::Uuid },
+    #[error("acess denied")]
+    AccessDenied,
+    #[error("username occupied: {username:?}")]
+    UsernameOccupied { username: String },
+    #[error("email occupied: {email:?}")]
+    EmailOccupied { email: String },
+    #[error("internal server error: {message:?}")]
+    Internal { message: String },
+}
+
+crate::impl_from_auth_err!(Error);
+
+pub type Response = Ref<super::User>;
+
+#[async_trait::async_trait]
+impl crate::AuthenticatedEndpoint for RestoreUser {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
+        crate::auth::authorize::Request {
+            auth_token: request.auth_token.clone(),
+            resource: crate::auth::Resource::DeletedUsers,
+            action: crate::auth::Action::Write,
+        }
+    }
+
+    #[tracing::instrument(skip(ctx))]
+    async fn handle(
+        &self,
+        ctx: &crate::Context,
+        accessing_user: crate::auth::authorize::Response,
+        request: Self::Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let id = request.id;
+        let db_err = |err| Error::Internal {
+            message: format!("db error: {err}"),
+        };
+        let mut tx = ctx.db_pool.begin().await.map_err(db_err)?;
+        let archived = sqlx::query!(
+            r#"
+SELECT username::TEXT as "username!", email::TEXT as "email!"
+FROM users_deleted
+WHERE id = $1
+ORDER BY deleted_at DESC
+LIMIT 1
+FOR UPDATE
+            "#,
+            &id
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(db_err)?;
+        let archived = if let Some(archived) = archived {
+            archived
+        } else {
+            return Err(Error::NotFound { id });
+        };
+        let user = sqlx::query_as!(
+            User,
+            r#"
+SELECT
+    id as "id!",
+    created_at as "created_at!",
+    updated_at as "updated_at!",
+    email::TEXT as "email!",
+    username::TEXT as "username!",
+    pic_url,
+    email_verified_at
+FROM restore_user($1)
+            "#,
+            &id
+        )
+        .fetch_one(&mut tx)
+        .await
+        .map_err(|err| match &err {
+            sqlx::Error::Database(boxed) if boxed.constraint().is_some() => {
+                match boxed.constraint().unwrap() {
+                    "unique_users_username" => Error::UsernameOccupied {
+                        username: archived.username.clone(),
+                    },
+                    "unique_users_email" => Error::EmailOccupied {
+                        email: archived.email.clone(),
+                    },
+                    _ => db_err(err),
+                }
+            }
+            _ => db_err(err),
+        })?;
+        tx.commit().await.map_err(db_err)?;
+        tracing::info!(target: "audit", user_id = %id, restored_by = %accessing_user.user_id, "user restored");
+        Ok(user.into())
+    }
+}
+
+impl From<&Error> for axum::http::StatusCode {
+    fn from(err: &Error) -> Self {
+        use Error::*;
+        match err {
+            NotFound { .. } => Self::NOT_FOUND,
+            AccessDenied => Self::UNAUTHORIZED,
+            UsernameOccupied { .. } | EmailOccupied { .. } => Self::BAD_REQUEST,
+            Internal { .. } => Self::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl HttpEndpoint for RestoreUser {
+    const METHOD: Method = Method::Post;
+    const PATH: &'static str = "/users/:id/restore";
+
+    type HttpRequest = (BearerToken, Path<uuid::Uuid>);
+
+    fn request(
+        (BearerToken(token), Path(id)): Self::HttpRequest,
+    ) -> Result<Self::Request, Self::Error> {
+        Ok(self::Request {
+            auth_token: token,
+            id,
+        })
+    }
+
+    fn response(Ref(resp): Self::Response) -> axum::response::Response {
+        Json(resp).into_response()
+    }
+}
+
+impl DocumentedEndpoint for RestoreUser {
+    const TAG: &'static crate::Tag = &super::TAG;
+    const SUMMARY: &'static str =
+        "Bring back a deleted user. Their sessions, roles and identities since linked elsewhere aren't restored.";
+
+    fn success_examples() -> Vec<serde_json::Value> {
+        super::get::GetUser::success_examples()
+    }
+
+    fn errors() -> Vec<ErrorResponse<Error>> {
+        use crate::user::testing::*;
+        vec![
+            ("Access denied", Error::AccessDenied),
+            (
+                "Not found",
+                Error::NotFound {
+                    id: Default::default(),
+                },
+            ),
+            (
+                "Username taken since the deletion",
+                Error::UsernameOccupied {
+                    username: USER_01_USERNAME.into(),
+                },
+            ),
+            (
+                "Email taken since the deletion",
+                Error::EmailOccupied {
+                    email: USER_01_EMAIL.into(),
+                },
+            ),
+            (
+                "Internal server error",
+                Error::Internal {
+                    message: "internal server error".to_string(),
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use crate::auth::testing::*;
+    use crate::user::testing::*;
+    use crate::utils::testing::*;
+    use crate::Endpoint;
+
+    async fn delete_user_01(ctx: &TestContext) {
+        crate::user::delete::DeleteUser
+            .handle(
+                &ctx.ctx(),
+                crate::user::delete::Request {
+                    auth_token: USER_04_SESSION.into(),
+                    id: USER_01_ID,
+                },
+            )
+            .await
+            .unwrap_or_log();
+    }
+
+    async fn restore_user_01(ctx: &TestContext) -> Result<super::Response, super::Error> {
+        super::RestoreUser
+            .handle(
+                &ctx.ctx(),
+                super::Request {
+                    auth_token: USER_04_SESSION.into(),
+                    id: USER_01_ID,
+                },
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn restore_user_works() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            delete_user_01(&ctx).await;
+            let user = restore_user_01(&ctx).await.unwrap_or_log().0;
+            assert_eq!(user.id, USER_01_ID);
+            assert_eq!(user.username, USER_01_USERNAME);
+            assert_eq!(user.email, USER_01_EMAIL);
+
+            crate::auth::authenticate::Authenticate
+                .handle(
+                    &ctx.ctx(),
+                    crate::auth::authenticate::Request {
+                        identifier: USER_01_USERNAME.into(),
+                        password: "password".into(),
+                        device_label: None,
+                        client: None,
+                    },
+                )
+                .await
+                .unwrap_or_log();
+            let archived =
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users_deleted WHERE id = $1")
+                    .bind(USER_01_ID)
+                    .fetch_one(&ctx.ctx().db_pool)
+                    .await
+                    .unwrap_or_log();
+            assert_eq!(archived, 0);
+            // sessions from before the deletion stay dead
+            assert!(crate::auth::authorize::Authorize
+                .handle(
+                    &ctx.ctx(),
+                    crate::auth::authorize::Request {
+                        auth_token: USER_01_SESSION.into(),
+                        resource: crate::auth::Resource::User { id: USER_01_ID },
+                        action: crate::auth::Action::Read,
+                    },
+                )
+                .await
+                .is_err());
+
+            // and can be deleted all over again
+            delete_user_01(&ctx).await;
+            restore_user_01(&ctx).await.unwrap_or_log();
+        }
+        ctx.close().await;
+    }
+
+    async fn count_rows(ctx: &TestContext, table: &str) -> i64 {
+        sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM {table} WHERE user_id = $1"))
+            .bind(USER_01_ID)
+            .fetch_one(&ctx.ctx().db_pool)
+            .await
+            .unwrap_or_log()
+    }
+
+    async fn key_works(ctx: &TestContext, key: &str) -> bool {
+        crate::auth::authorize::Authorize
+            .handle(
+                &ctx.ctx(),
+                crate::auth::authorize::Request {
+                    auth_token: key.into(),
+                    resource: crate::auth::Resource::User { id: USER_01_ID },
+                    action: crate::auth::Action::Read,
+                },
+            )
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn restore_user_brings_back_secrets() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            let key = crate::AuthenticatedEndpoint::handle(
+                &crate::auth::create_api_key::CreateApiKey,
+                &ctx.ctx(),
+                accessing(USER_01_ID),
+                crate::auth::create_api_key::Request {
+                    auth_token: Some(USER_01_SESSION.into()),
+                    user_id: Some(USER_01_ID),
+                    name: "CI".into(),
+                    scopes: vec![crate::auth::Action::Read],
+                    expires_at: None,
+                },
+            )
+            .await
+            .unwrap_or_log()
+            .key;
+            for query in [
+                "INSERT INTO totp_secrets (user_id, secret, confirmed_at) VALUES ($1, 'JBSWY3DPEHPK3PXP', CURRENT_TIMESTAMP)",
+                "INSERT INTO totp_recovery_codes (user_id, code_hash) VALUES ($1, 'hash')",
+                "INSERT INTO user_identities (user_id, provider, subject) VALUES ($1, 'mock', 'sub-01')",
+                "INSERT INTO user_identities (user_id, provider, subject) VALUES ($1, 'mock', 'sub-taken')",
+            ] {
+                sqlx::query(query)
+                    .bind(USER_01_ID)
+                    .execute(&ctx.ctx().db_pool)
+                    .await
+                    .unwrap_or_log();
+            }
+
+            delete_user_01(&ctx).await;
+            assert!(!key_works(&ctx, &key).await);
+            for table in [
+                "totp_secrets",
+                "totp_recovery_codes",
+                "api_keys",
+                "user_identities",
+            ] {
+                assert_eq!(count_rows(&ctx, table).await, 0, "{table}");
+            }
+            // linked to someone else in the meantime
+            sqlx::query(
+                "INSERT INTO user_identities (user_id, provider, subject) VALUES ($1, 'mock', 'sub-taken')",
+            )
+            .bind(USER_02_ID)
+            .execute(&ctx.ctx().db_pool)
+            .await
+            .unwrap_or_log();
+
+            restore_user_01(&ctx).await.unwrap_or_log();
+            assert!(key_works(&ctx, &key).await);
+            for (table, restored, archived) in [
+                ("totp_secrets", 1, 0),
+                ("totp_recovery_codes", 1, 0),
+                ("api_keys", 1, 0),
+                ("user_identities", 1, 1),
+            ] {
+                assert_eq!(count_rows(&ctx, table).await, restored, "{table}");
+                assert_eq!(
+                    count_rows(&ctx, &format!("{table}_deleted")).await,
+                    archived,
+                    "{table}"
+                );
+            }
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn restore_user_rejects_taken_usernames() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            delete_user_01(&ctx).await;
+            sqlx::query(
+                "INSERT INTO users (username, email) VALUES ($1, 'someone.else@example.com')",
+            )
+            .bind(USER_01_USERNAME)
+            .execute(&ctx.ctx().db_pool)
+            .await
+            .unwrap_or_log();
+            assert!(matches!(
+                restore_user_01(&ctx).await,
+                Err(super::Error::UsernameOccupied { username }) if username == USER_01_USERNAME
+            ));
+            let archived =
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users_deleted WHERE id = $1")
+                    .bind(USER_01_ID)
+                    .fetch_one(&ctx.ctx().db_pool)
+                    .await
+                    .unwrap_or_log();
+            assert_eq!(archived, 1);
+        }
+        ctx.close().await;
+    }
+
+    #[tokio::test]
+    async fn restore_user_rejects_taken_emails() {
+        let ctx = TestContext::new(crate::function!()).await;
+        {
+            delete_user_01(&ctx).await;
+            sqlx::query("INSERT INTO users (username, email) VALUES ('someone_else', $1)")
+                .bind(USER_01_EMAIL)
+                .execute(&ctx.ctx().db_pool)
+                .await
+                .unwrap_or_log();
+            assert!(matches!(
+                restore_user_01(&ctx).await,
+                Err(super::Error::EmailOccupied { .. })
+            ));
+        }
+        ctx.close().await;
+    }
+
+    crate::integration_table_tests! {
+        restore_user_fails_on_live_users: {
+            uri: format!("/users/{USER_02_ID}/restore"),
+            method: "POST",
+            status: StatusCode::NOT_FOUND,
+            router: crate::user::router(),
+            check_json: serde_json::json!({
+                "error": "notFound",
+            }),
+            auth_token: USER_04_SESSION.into(),
+        },
+        restore_user_is_for_admins: {
+            uri: format!("/users/{USER_02_ID}/restore"),
+            method: "POST",
+            status: StatusCode::UNAUTHORIZED,
+            router: crate::user::router(),
+            check_json: serde_json::json!({
+                "error": "accessDenied",
+            }),
+            auth_token: USER_01_SESSION.into(),
+        },
+    }
+}
@@ -22,15 +22,21 @@ pub struct Request {
     pub email: Option<String>,
     #[validate(url)]
     pub pic_url: Option<String>,
-    #[validate(length(min = 8))]
+    /// Not accepted, use `POST /users/{id}/password` instead.
+    #[validate(custom = "password_moved")]
     pub password: Option<String>,
 }
+
+/// Passwords used to be changed here, don't pretend to still do so.
+fn password_moved(_: &str) -> Result<(), validator::ValidationError> {
+    let mut issue = validator::ValidationError::new("moved");
+    issue.add_param("endpoint".into(), &"POST /users/{id}/password");
+    Err(issue)
+}
+
 impl Request {
     fn is_empty(&self) -> bool {
-        self.username.is_none()
-            && self.email.is_none()
-            && self.pic_url.is_none()
-            && self.password.is_none()
+        self.username.is_none() && self.email.is_none() && self.pic_url.is_none()
     }
 }
 
@@ -64,6 +70,9 @@ impl AuthenticatedEndpoint for UpdateUser {
     type Response = Response;
     type Error = Error;
 
+    // a changed email is a password reset away from taking over the account
+    const IMPERSONATION_FORBIDDEN: bool = true;
+
     fn authorize_request(&self, request: &Self::Request) -> crate::auth::authorize::Request {
         crate::auth::authorize::Request {
             auth_token: request.auth_token.clone().unwrap(),
@@ -78,7 +87,7 @@ impl AuthenticatedEndpoint for UpdateUser {
     async fn handle(
         &self,
         ctx: &crate::Context,
-        accessing_user: uuid::Uuid,
+        accessing_user: crate::auth::authorize::Response,
         request: Self::Request,
     ) -> Result<Self::Response, Self::Error> {
         validator::Validate::validate(&request).map_err(utils::ValidationErrors::from)?;
@@ -99,15 +108,7 @@ impl AuthenticatedEndpoint for UpdateUser {
                 user::get::Error::Internal { message } => Error::Internal { message },
             });
         }
-        let pass_hash = request.password.map(|pass| {
-            argon2::hash_encoded(
-                pass.as_bytes(),
-                &ctx.config.pass_salt_hash,
-                &ctx.config.argon2_conf,
-            )
-            .unwrap_or_log()
-        });
-        let null_str = "NULL".into();
+        let email_changed = request.email.is_some();
         let user = sqlx::query_as!(
             super::User,
             r#"
@@ -117,20 +118,19 @@ SELECT
     updated_at as "updated_at!",
     email::TEXT as "email!",
     username::TEXT as "username!",
-    pic_url
+    pic_url,
+    email_verified_at
 FROM update_user(
     $1,
     $2::TEXT::CITEXT, 
     $3::TEXT::CITEXT, 
-    $4,
-    $5
+    $4
 )
                 "#,
             &request.user_id.unwrap(),
-            &request.username.as_ref().unwrap_or(&null_str),
-            &request.email.as_ref().unwrap_or(&null_str),
-            &request.pic_url.as_ref().unwrap_or(&null_str),
-            &pass_hash.as_ref().unwrap_or(&null_str)
+            request.username.as_deref(),
+            request.email.as_deref(),
+            request.pic_url.as_deref(),
         )
         .fetch_one(&ctx.db_pool)
         .await
@@ -155,7 +155,14 @@ FROM update_user(
                 message: format!("db error: {err}"),
             },
         })?;
-        // TODO: email notification, account activation
+        // `update_user` only resets verification if the email actually changed
+        if email_changed && user.email_verified_at.is_none() {
+            crate::auth::verify_email::send_verification(ctx, user.id, &user.email)
+                .await
+                .map_err(|err| Error::Internal {
+                    message: format!("db error: {err}"),
+                })?;
+        }
         Ok(user.into())
     }
 }
@@ -207,6 +214,7 @@ impl DocumentedEndpoint for UpdateUser {
             email: USER_01_EMAIL.into(),
             username: USER_01_USERNAME.into(),
             pic_url: Some("https:://example.com/picture.jpg".into()),
+            email_verified_at: Some(time::OffsetDateTime::now_utc()),
         }]
         .into_iter()
         .map(serde_json::to_value)
@@ -287,8 +295,8 @@ mod tests {
             user_id: None,
             username: None,
             email: None,
-            password: None,
             pic_url: None,
+            password: None,
         }
     }
 
@@ -296,7 +304,6 @@ mod tests {
         serde_json::json!({
             "username": "whish_box",
             "email": "multis@cream.mux",
-            "password": "lovebite",
             "picUrl": "http://i.will.neve.eva/eva.leave.im.80ies.soul.babe",
         })
     }
@@ -336,13 +343,6 @@ mod tests {
             },
             Some("username"),
         ),
-        rejects_too_short_passwords: (
-            Request {
-                password: Some("short".into()),
-                ..fixture_request_empty()
-            },
-            Some("password"),
-        ),
         rejects_invalid_emails: (
             Request {
                 email: Some("invalid".into()),
@@ -357,6 +357,13 @@ mod tests {
             },
             Some("pic_url"),
         ),
+        rejects_passwords: (
+            Request {
+                password: Some("Tr0ub4dor&3".into()),
+                ..fixture_request_empty()
+            },
+            Some("password"),
+        ),
     }
 
     macro_rules! update_user_integ {
@@ -398,7 +405,7 @@ mod tests {
             auth_token: USER_01_SESSION.into(),
             status: http::StatusCode::OK,
             body: fixture_request_json(),
-            check_json: fixture_request_json().remove_keys_from_obj(&["password"]),
+            check_json: fixture_request_json(),
             extra_assertions: &|EAArgs { ctx, response_json, .. }| {
                 Box::pin(async move {
                     let req_body_json = fixture_request_json();
@@ -408,6 +415,13 @@ mod tests {
                         resp_body_json["updatedAt"].as_i64().unwrap() >
                         resp_body_json["createdAt"].as_i64().unwrap()
                     );
+                    // new email needs verifying
+                    assert!(resp_body_json["emailVerifiedAt"].is_null());
+                    {
+                        let outbox = ctx.mailer.outbox.lock();
+                        assert_eq!(outbox.len(), 1);
+                        assert_eq!(outbox[0].to, req_body_json["email"].as_str().unwrap());
+                    }
                     let app = crate::user::router().layer(axum::Extension(ctx.ctx()));
                     let resp = app
                         .oneshot(
@@ -429,13 +443,25 @@ mod tests {
                     let body = serde_json::from_slice(&body).unwrap_or_log();
                     tracing::info!(?body);
                     check_json(
-                        ("expected", &req_body_json.remove_keys_from_obj(&["password"])),
+                        ("expected", &req_body_json),
                         ("response", &body),
                     );
                 })
             },
             print_response: true,
         },
+        keeps_verification_if_email_unchanged: {
+            uri: format!("/users/{USER_01_ID}"),
+            auth_token: USER_01_SESSION.into(),
+            status: http::StatusCode::OK,
+            body: serde_json::json!({ "email": USER_01_EMAIL }),
+            extra_assertions: &|EAArgs { ctx, response_json, .. }| {
+                Box::pin(async move {
+                    assert!(response_json.unwrap()["emailVerifiedAt"].is_number());
+                    assert!(ctx.mailer.outbox.lock().is_empty());
+                })
+            },
+        },
         fails_if_username_occupied: {
             uri: format!("/users/{USER_01_ID}"),
             auth_token: USER_01_SESSION.into(),
@@ -458,9 +484,24 @@ mod tests {
                 "error": "emailOccupied"
             }),
         },
+        fails_if_password_given: {
+            uri: format!("/users/{USER_01_ID}"),
+            auth_token: USER_01_SESSION.into(),
+            status: http::StatusCode::BAD_REQUEST,
+            body: serde_json::json!({ "password": "Tr0ub4dor&3" }),
+            check_json: serde_json::json!({
+                "error": "invalidInput",
+                "issues": {
+                    "password": [{
+                        "code": "moved",
+                        "params": { "endpoint": "POST /users/{id}/password" },
+                    }],
+                },
+            }),
+        },
         fails_if_not_found: {
             uri: format!("/users/{}", uuid::Uuid::new_v4()),
-            auth_token: USER_01_SESSION.into(), // FIXME: use super user session
+            auth_token: USER_04_SESSION.into(),
             status: StatusCode::NOT_FOUND,
             body: fixture_request_json(),
             check_json: serde_json::json!({
@@ -4,6 +4,8 @@ mod list_request;
 pub use validation_errs::*;
 mod validation_errs;
 
+#[cfg(test)]
+pub mod mock_oidc;
 #[cfg(test)]
 pub mod testing;
 
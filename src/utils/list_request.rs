@@ -1,10 +1,19 @@
 use deps::*;
 
-use crate::user::{User, UserSortingField};
+use crate::auth::{Session, SessionSortingField};
+use crate::user::{DeletedUser, DeletedUserSortingField, User, UserSortingField};
 use serde::{Deserialize, Serialize};
 
+/// The columns a list can be sorted by. Implementing this is all it takes
+/// for a list to be paginated through a [`Keyset`].
 pub trait SortingField {
+    /// Column name and SQL type of a unique column breaking the ties between
+    /// rows with equal sorting field values.
+    const TIE_BREAKER: (&'static str, &'static str) = ("id", "UUID");
+
     fn sql_field_name(&self) -> String;
+    /// Cursor values get cast to this before being compared with the column.
+    fn sql_field_type(&self) -> &'static str;
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, utoipa::ToSchema)]
@@ -25,10 +34,18 @@ impl SortingOrder {
 
 pub const DEFAULT_LIST_LIMIT: usize = 25;
 
+/// Totals the query planner estimates beyond this many rows aren't counted
+/// exactly.
+pub const EXACT_TOTAL_LIMIT: f64 = 10_000.;
+
 #[derive(Debug, Serialize, Deserialize, validator::Validate, utoipa::ToSchema)]
 #[serde(crate = "serde", rename_all = "camelCase")]
 #[validate(schema(function = "validate_list_req"))]
-#[aliases(ListUsersRequest = ListRequest<UserSortingField>)]
+#[aliases(
+    ListUsersRequest = ListRequest<UserSortingField>,
+    ListSessionsRequest = ListRequest<SessionSortingField>,
+    ListDeletedUsersRequest = ListRequest<DeletedUserSortingField>
+)]
 pub struct ListRequest<S>
 where
     S: SortingField + Clone + Copy + Serialize,
@@ -42,6 +59,8 @@ where
     pub filter: Option<String>,
     pub sorting_field: Option<S>,
     pub sorting_order: Option<SortingOrder>,
+    /// Count the items across all pages, see `total` in the response.
+    pub include_total: Option<bool>,
 }
 
 fn validate_list_req<S>(req: &ListRequest<S>) -> Result<(), validator::ValidationError>
@@ -101,15 +120,36 @@ where
 
 #[derive(Debug, Serialize, utoipa::ToSchema)]
 #[serde(crate = "serde", rename_all = "camelCase")]
-#[aliases(ListUsersResponse = ListResponse<User>)]
+#[aliases(
+    ListUsersResponse = ListResponse<User>,
+    ListSessionsResponse = ListResponse<Session>,
+    ListDeletedUsersResponse = ListResponse<DeletedUser>
+)]
 pub struct ListResponse<T>
 where
     T: utoipa::ToSchema,
 {
-    pub cursor: Option<String>,
+    /// Pass as `afterCursor` for the following page. Absent on the last page.
+    pub next_cursor: Option<String>,
+    /// Pass as `beforeCursor` for the preceding page. Absent on the first page.
+    pub prev_cursor: Option<String>,
+    /// Whether there's more past this page in the direction it was paged in.
+    pub has_more: bool,
+    /// The page size in effect.
+    pub limit: usize,
+    /// Only if asked for with `includeTotal`.
+    pub total: Option<ListTotal>,
     pub items: Vec<T>,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, utoipa::ToSchema)]
+#[serde(crate = "serde", rename_all = "camelCase")]
+pub struct ListTotal {
+    pub count: i64,
+    /// Large lists get the query planner's estimate instead of a count.
+    pub estimated: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(crate = "serde", rename_all = "camelCase")]
 pub struct Cursor<T, S>
@@ -120,51 +160,712 @@ where
     pub field: S,
     pub order: SortingOrder,
     pub filter: Option<String>,
+    /// The cursor row itself belongs on the page too.
+    #[serde(default)]
+    pub inclusive: bool,
+}
+
+const CURSOR_VERSION: usize = 3;
+
+/// Signs the cursors handed out to clients, who could otherwise forge the
+/// sorting field values and filters they carry.
+#[derive(Debug, Clone)]
+pub struct CursorConfig {
+    /// The first key signs, all of them verify. Retired keys should be kept
+    /// around for as long as clients are expected to hold on to cursors.
+    pub keys: Vec<CursorKey>,
+    /// Cursors older than this are rejected. They don't expire if unset.
+    pub max_age: Option<time::Duration>,
+}
+
+impl CursorConfig {
+    /// Parses whitespace separated `kid:secret` pairs. There has to be at
+    /// least one, there'd be nothing to sign with otherwise.
+    pub fn parse_keys(keys: &str) -> Result<Vec<CursorKey>, CursorKeyParseError> {
+        let keys = keys
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<CursorKey>, _>>()?;
+        if keys.is_empty() {
+            return Err(CursorKeyParseError::NoKeys);
+        }
+        Ok(keys)
+    }
+}
+
+#[derive(Clone)]
+pub struct CursorKey {
+    pub kid: String,
+    pub secret: Vec<u8>,
+}
+
+impl std::fmt::Debug for CursorKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CursorKey")
+            .field("kid", &self.kid)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CursorKey {
+    /// For when no key is configured. Cursors then don't survive restarts
+    /// or work across instances.
+    pub fn random() -> Self {
+        Self {
+            kid: "random".into(),
+            secret: rand::random::<[u8; 32]>().to_vec(),
+        }
+    }
+
+    fn mac(&self, signed: &str) -> hmac::Hmac<sha2::Sha256> {
+        use hmac::Mac;
+        let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(&self.secret)
+            .expect("HMAC takes keys of any size");
+        mac.update(signed.as_bytes());
+        mac
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CursorKeyParseError {
+    #[error("cursor keys are expected as `kid:secret`")]
+    Malformed,
+    #[error("no cursor keys given")]
+    NoKeys,
+}
+
+/// Parses `kid:secret`.
+impl std::str::FromStr for CursorKey {
+    type Err = CursorKeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((kid, secret)) if !kid.is_empty() && !secret.is_empty() => Ok(Self {
+                kid: kid.into(),
+                secret: secret.as_bytes().to_vec(),
+            }),
+            _ => Err(CursorKeyParseError::Malformed),
+        }
+    }
 }
 
-const CURSOR_VERSION: usize = 1;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum CursorError {
+    #[error("malformed cursor")]
+    Malformed,
+    #[error("cursor from an incompatible version")]
+    VersionMismatch,
+    #[error("bad cursor signature")]
+    BadSignature,
+    #[error("cursor expired")]
+    Expired,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "serde")]
+struct CursorPayload<C> {
+    /// Seconds since unix epoch.
+    iat: i64,
+    cursor: C,
+}
 
 impl<T, S> Cursor<T, S>
 where
     S: Serialize + SortingField + Clone + Copy,
     T: Serialize,
 {
-    pub fn to_encoded_str(&self) -> String {
+    /// `{version}:{kid}:{payload}:{signature}` with the payload being the
+    /// brotli compressed JSON and both in base64.
+    pub fn sign(&self, config: &CursorConfig, issued_at: time::OffsetDateTime) -> String {
+        use hmac::Mac;
         use std::io::Write;
-        // let mut out = format!("{CURSOR_VERSION}:");
+        let key = config
+            .keys
+            .first()
+            .expect("no cursor signing key configured");
         let mut out = Vec::new();
         {
-            std::write!(&mut out, "{CURSOR_VERSION}:").unwrap_or_log();
+            std::write!(&mut out, "{CURSOR_VERSION}:{}:", key.kid).unwrap_or_log();
             let mut b64_w = base64::write::EncoderWriter::new(&mut out, base64::STANDARD);
             let mut brotli_w = brotli::CompressorWriter::new(&mut b64_w, 4096, 5, 21);
-            serde_json::to_writer(&mut brotli_w, &self).unwrap_or_log();
+            serde_json::to_writer(
+                &mut brotli_w,
+                &CursorPayload {
+                    iat: issued_at.unix_timestamp(),
+                    cursor: self,
+                },
+            )
+            .unwrap_or_log();
         }
-        String::from_utf8(out).unwrap_or_log()
+        let mut out = String::from_utf8(out).unwrap_or_log();
+        let signature = key.mac(&out).finalize().into_bytes();
+        out.push(':');
+        out.push_str(&base64::encode_config(signature, base64::STANDARD));
+        out
     }
 }
 
-impl<T, S> std::str::FromStr for Cursor<T, S>
-where
-    T: serde::de::DeserializeOwned,
-    S: SortingField + Clone + Copy + serde::de::DeserializeOwned,
-{
-    type Err = ();
+/// A cursor as received from a client, yet to be [verified](Self::verify).
+#[derive(Debug)]
+pub struct SignedCursor {
+    kid: String,
+    /// Everything up to the signature.
+    signed: String,
+    payload: String,
+    signature: Vec<u8>,
+}
+
+impl std::str::FromStr for SignedCursor {
+    type Err = CursorError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (ver_str, payload_str) = s.split_once(':').ok_or(())?;
-        let version: usize = ver_str.parse().map_err(|_| ())?;
+        let (signed, signature) = s.rsplit_once(':').ok_or(CursorError::Malformed)?;
+        let mut parts = signed.splitn(3, ':');
+        let version: usize = parts
+            .next()
+            .and_then(|ver_str| ver_str.parse().ok())
+            .ok_or(CursorError::Malformed)?;
         if version != CURSOR_VERSION {
-            return Err(());
-        }
-        // let mut cursor = std::io::Cursor::new(payload_str);
-        // let mut b64_r = base64::read::DecoderReader::new(&mut cursor, base64::STANDARD);
-        // let mut brotil_r = brotli::CompressorReader::new(&mut b64_r, 4096, 5, 21);
-        // serde_json::from_reader(&mut brotil_r).map_err(|err| tracing::error!(?err))
-        let compressed = base64::decode_config(payload_str, base64::STANDARD).map_err(|_| ())?;
-        let mut cursor = std::io::Cursor::new(&compressed);
+            return Err(CursorError::VersionMismatch);
+        }
+        match (parts.next(), parts.next()) {
+            (Some(kid), Some(payload)) => Ok(Self {
+                kid: kid.into(),
+                signed: signed.into(),
+                payload: payload.into(),
+                signature: base64::decode_config(signature, base64::STANDARD)
+                    .map_err(|_| CursorError::Malformed)?,
+            }),
+            _ => Err(CursorError::Malformed),
+        }
+    }
+}
+
+impl SignedCursor {
+    pub fn verify<T, S>(
+        &self,
+        config: &CursorConfig,
+        now: time::OffsetDateTime,
+    ) -> Result<Cursor<T, S>, CursorError>
+    where
+        T: serde::de::DeserializeOwned,
+        S: SortingField + Clone + Copy + serde::de::DeserializeOwned,
+    {
+        use hmac::Mac;
+        // keys that were rotated out count as bad signatures too
+        let key = config
+            .keys
+            .iter()
+            .find(|key| key.kid == self.kid)
+            .ok_or(CursorError::BadSignature)?;
+        key.mac(&self.signed)
+            .verify_slice(&self.signature)
+            .map_err(|_| CursorError::BadSignature)?;
+        let compressed = base64::decode_config(&self.payload, base64::STANDARD)
+            .map_err(|_| CursorError::Malformed)?;
         let mut json = Vec::new();
-        brotli::BrotliDecompress(&mut cursor, &mut json).map_err(|_| ())?;
-        tracing::info!("{}", std::str::from_utf8(&json).unwrap_or_log());
-        serde_json::from_slice(&json[..]).map_err(|_| ())
+        brotli::BrotliDecompress(&mut std::io::Cursor::new(&compressed), &mut json)
+            .map_err(|_| CursorError::Malformed)?;
+        let payload: CursorPayload<Cursor<T, S>> =
+            serde_json::from_slice(&json[..]).map_err(|_| CursorError::Malformed)?;
+        if let Some(max_age) = config.max_age {
+            let issued_at = time::OffsetDateTime::from_unix_timestamp(payload.iat)
+                .map_err(|_| CursorError::Malformed)?;
+            if issued_at + max_age < now {
+                return Err(CursorError::Expired);
+            }
+        }
+        Ok(payload.cursor)
+    }
+}
+
+/// Which side of the cursor row a page lies on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorDirection {
+    After,
+    Before,
+}
+
+impl CursorDirection {
+    /// The [`ListRequest`] field the cursor came in, for error reporting.
+    #[inline]
+    pub fn field_name(&self) -> &'static str {
+        match self {
+            Self::After => "afterCursor",
+            Self::Before => "beforeCursor",
+        }
+    }
+}
+
+/// The textual sorting field and tie breaker values of a row.
+pub type KeysetValue = (String, String);
+
+/// Keyset pagination over the `(field, tie breaker)` tuple of a
+/// [`SortingField`]. Cursor values only ever reach the database as bound
+/// parameters and the column names are those declared by the sorting field.
+///
+/// Queries are assembled by the list endpoints along the lines of:
+///
+/// ```sql
+/// SELECT <columns>, <push_key_columns>
+/// FROM <table>
+/// WHERE <filter> AND <push_condition>
+/// <push_order_and_limit>
+/// ```
+#[derive(Debug, Clone)]
+pub struct Keyset<S> {
+    pub field: S,
+    pub order: SortingOrder,
+    pub filter: Option<String>,
+    pub limit: usize,
+    pub cursor: Option<(CursorDirection, KeysetValue)>,
+    /// The cursor row itself belongs on the page too.
+    pub inclusive: bool,
+    pub include_total: bool,
+}
+
+impl<S> Keyset<S>
+where
+    S: SortingField + Clone + Copy + Serialize + serde::de::DeserializeOwned,
+{
+    /// Continues from the cursor of a [validated](validator::Validate)
+    /// request or starts at the top of the requested sorting.
+    pub fn from_request(
+        request: ListRequest<S>,
+        default_field: S,
+        default_order: SortingOrder,
+        cursors: &CursorConfig,
+        now: time::OffsetDateTime,
+    ) -> Result<Self, super::ValidationErrors> {
+        let limit = request.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+        let include_total = request.include_total.unwrap_or(false);
+        let cursor = request
+            .after_cursor
+            .map(|cursor| (CursorDirection::After, cursor))
+            .or_else(|| {
+                request
+                    .before_cursor
+                    .map(|cursor| (CursorDirection::Before, cursor))
+            });
+        let (direction, cursor_str) = if let Some(cursor) = cursor {
+            cursor
+        } else {
+            return Ok(Self {
+                field: request.sorting_field.unwrap_or(default_field),
+                order: request.sorting_order.unwrap_or(default_order),
+                filter: request.filter,
+                limit,
+                cursor: None,
+                inclusive: false,
+                include_total,
+            });
+        };
+        let cursor: Cursor<KeysetValue, S> = cursor_str
+            .parse::<SignedCursor>()
+            .and_then(|cursor| cursor.verify(cursors, now))
+            .map_err(|err| {
+                let mut issues = validator::ValidationErrors::new();
+                issues.add(
+                    direction.field_name(),
+                    validator::ValidationError {
+                        code: "invalid_cursor".into(),
+                        message: Some(err.to_string().into()),
+                        params: [(
+                            std::borrow::Cow::from("value"),
+                            serde_json::json!(cursor_str),
+                        )]
+                        .into_iter()
+                        .collect(),
+                    },
+                );
+                super::ValidationErrors::from(issues)
+            })?;
+        Ok(Self {
+            field: cursor.field,
+            order: cursor.order,
+            filter: cursor.filter,
+            limit,
+            cursor: Some((direction, cursor.value)),
+            inclusive: cursor.inclusive,
+            include_total,
+        })
+    }
+
+    /// Selects the values [`paginate`](Self::paginate) builds cursors from.
+    /// Pushes a leading comma.
+    pub fn push_key_columns(&self, query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>) {
+        query.push(format_args!(
+            ", {}::TEXT AS keyset_field, {}::TEXT AS keyset_tie_breaker",
+            self.field.sql_field_name(),
+            S::TIE_BREAKER.0
+        ));
+    }
+
+    /// Pushes a condition matching the rows on the requested side of the
+    /// cursor, `TRUE` without one.
+    pub fn push_condition(&self, query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>) {
+        let (direction, (field_value, tie_breaker_value)) = if let Some(cursor) = &self.cursor {
+            cursor
+        } else {
+            query.push("TRUE");
+            return;
+        };
+        let op = match (self.order, direction, self.inclusive) {
+            (SortingOrder::Ascending, CursorDirection::After, false)
+            | (SortingOrder::Descending, CursorDirection::Before, false) => ">",
+            (SortingOrder::Ascending, CursorDirection::After, true)
+            | (SortingOrder::Descending, CursorDirection::Before, true) => ">=",
+            (SortingOrder::Ascending, CursorDirection::Before, false)
+            | (SortingOrder::Descending, CursorDirection::After, false) => "<",
+            (SortingOrder::Ascending, CursorDirection::Before, true)
+            | (SortingOrder::Descending, CursorDirection::After, true) => "<=",
+        };
+        let (tie_breaker, tie_breaker_type) = S::TIE_BREAKER;
+        query
+            .push(format_args!(
+                "({}, {tie_breaker}) {op} (CAST(",
+                self.field.sql_field_name()
+            ))
+            .push_bind(field_value.clone())
+            .push(format_args!(" AS {}), CAST(", self.field.sql_field_type()))
+            .push_bind(tie_breaker_value.clone())
+            .push(format_args!(" AS {tie_breaker_type}))"));
+    }
+
+    /// Pages before the cursor are fetched in reverse, starting from the
+    /// cursor, and put back in order by [`paginate`](Self::paginate). Fetches
+    /// one row more than the limit to tell if there are more pages.
+    pub fn push_order_and_limit(&self, query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>) {
+        let order = match (self.order, self.direction()) {
+            (SortingOrder::Ascending, CursorDirection::After)
+            | (SortingOrder::Descending, CursorDirection::Before) => "asc",
+            (SortingOrder::Ascending, CursorDirection::Before)
+            | (SortingOrder::Descending, CursorDirection::After) => "desc",
+        };
+        query
+            .push(format_args!(
+                " ORDER BY {} {order}, {} {order} LIMIT ",
+                self.field.sql_field_name(),
+                S::TIE_BREAKER.0
+            ))
+            .push_bind(self.limit as i64 + 1);
+    }
+
+    /// Pages without a cursor are the first.
+    #[inline]
+    pub fn direction(&self) -> CursorDirection {
+        self.cursor
+            .as_ref()
+            .map(|(direction, _)| *direction)
+            .unwrap_or(CursorDirection::After)
+    }
+
+    /// Cuts the rows down to the page, in the requested sorting, and links
+    /// it up with the pages next to it.
+    pub fn paginate<T>(
+        &self,
+        mut rows: Vec<sqlx::postgres::PgRow>,
+        cursors: &CursorConfig,
+        now: time::OffsetDateTime,
+        item: impl FnMut(sqlx::postgres::PgRow) -> Result<T, sqlx::Error>,
+    ) -> Result<ListResponse<T>, sqlx::Error>
+    where
+        T: utoipa::ToSchema,
+    {
+        use sqlx::Row;
+        let more_rows_pending = rows.len() > self.limit;
+        rows.truncate(self.limit);
+        let direction = self.direction();
+        if direction == CursorDirection::Before {
+            rows.reverse();
+        }
+        let cursor = |value: KeysetValue, inclusive: bool| {
+            Cursor {
+                value,
+                field: self.field,
+                order: self.order,
+                filter: self.filter.clone(),
+                inclusive,
+            }
+            .sign(cursors, now)
+        };
+        let row_cursor = |row: Option<&sqlx::postgres::PgRow>| {
+            row.map(|row| {
+                Ok::<_, sqlx::Error>(cursor(
+                    (
+                        row.try_get::<String, _>("keyset_field")?,
+                        row.try_get::<String, _>("keyset_tie_breaker")?,
+                    ),
+                    false,
+                ))
+            })
+            .transpose()
+        };
+        // the side facing the request's cursor leads back through that same
+        // cursor, flipped to take in whatever the request left out, so that
+        // empty pages don't strand the client
+        let anchor_cursor = self
+            .cursor
+            .as_ref()
+            .map(|(_, value)| cursor(value.clone(), !self.inclusive));
+        let (next_cursor, prev_cursor) = match direction {
+            CursorDirection::After => (
+                if more_rows_pending {
+                    row_cursor(rows.last())?
+                } else {
+                    None
+                },
+                anchor_cursor,
+            ),
+            CursorDirection::Before => (
+                anchor_cursor,
+                if more_rows_pending {
+                    row_cursor(rows.first())?
+                } else {
+                    None
+                },
+            ),
+        };
+        Ok(ListResponse {
+            next_cursor,
+            prev_cursor,
+            has_more: more_rows_pending,
+            limit: self.limit,
+            total: None,
+            items: rows.into_iter().map(item).collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// The total if it was asked for. `from` pushes the `FROM` and `WHERE`
+    /// clauses of the list query, without the cursor condition.
+    pub async fn total(
+        &self,
+        db: &sqlx::PgPool,
+        from: impl Fn(&mut sqlx::QueryBuilder<'_, sqlx::Postgres>),
+    ) -> Result<Option<ListTotal>, sqlx::Error> {
+        use sqlx::Row;
+        if !self.include_total {
+            return Ok(None);
+        }
+        // counting is a scan over all the matching rows
+        let mut query = sqlx::QueryBuilder::new("EXPLAIN (FORMAT JSON) SELECT 1");
+        from(&mut query);
+        let plan: serde_json::Value = query.build().fetch_one(db).await?.try_get(0)?;
+        let estimate = plan[0]["Plan"]["Plan Rows"].as_f64().unwrap_or_default();
+        if estimate > EXACT_TOTAL_LIMIT {
+            return Ok(Some(ListTotal {
+                count: estimate as i64,
+                estimated: true,
+            }));
+        }
+        let mut query = sqlx::QueryBuilder::new("SELECT COUNT(*)");
+        from(&mut query);
+        Ok(Some(ListTotal {
+            count: query.build().fetch_one(db).await?.try_get(0)?,
+            estimated: false,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::*;
+
+    use super::*;
+    use crate::user::UserSortingField;
+
+    fn username_keyset(cursor: Option<(CursorDirection, KeysetValue)>) -> Keyset<UserSortingField> {
+        Keyset {
+            field: UserSortingField::Username,
+            order: SortingOrder::Descending,
+            filter: None,
+            limit: 10,
+            cursor,
+            inclusive: false,
+            include_total: false,
+        }
+    }
+
+    #[test]
+    fn keyset_binds_cursor_values() {
+        let mut query = sqlx::QueryBuilder::new("SELECT *");
+        let keyset = username_keyset(Some((
+            CursorDirection::After,
+            (
+                "'; DROP TABLE users; --".into(),
+                uuid::Uuid::nil().to_string(),
+            ),
+        )));
+        keyset.push_key_columns(&mut query);
+        query.push(" FROM users WHERE ");
+        keyset.push_condition(&mut query);
+        keyset.push_order_and_limit(&mut query);
+        assert_eq!(
+            query.sql(),
+            "SELECT *, username::TEXT AS keyset_field, id::TEXT AS keyset_tie_breaker \
+            FROM users \
+            WHERE (username, id) < (CAST($1 AS CITEXT), CAST($2 AS UUID)) \
+            ORDER BY username desc, id desc LIMIT $3"
+        );
+    }
+
+    #[test]
+    fn keyset_reverses_before_cursor() {
+        let mut query = sqlx::QueryBuilder::new("");
+        let keyset = username_keyset(Some((CursorDirection::Before, ("a".into(), "b".into()))));
+        keyset.push_condition(&mut query);
+        keyset.push_order_and_limit(&mut query);
+        assert_eq!(
+            query.sql(),
+            "(username, id) > (CAST($1 AS CITEXT), CAST($2 AS UUID)) \
+            ORDER BY username asc, id asc LIMIT $3"
+        );
+
+        let mut query = sqlx::QueryBuilder::new("");
+        username_keyset(None).push_condition(&mut query);
+        assert_eq!(query.sql(), "TRUE");
+    }
+
+    #[test]
+    fn keyset_takes_in_inclusive_cursor_rows() {
+        let mut query = sqlx::QueryBuilder::new("");
+        let keyset = Keyset {
+            inclusive: true,
+            ..username_keyset(Some((CursorDirection::After, ("a".into(), "b".into()))))
+        };
+        keyset.push_condition(&mut query);
+        assert_eq!(
+            query.sql(),
+            "(username, id) <= (CAST($1 AS CITEXT), CAST($2 AS UUID))"
+        );
+    }
+
+    #[test]
+    fn keyset_rejects_garbled_cursors() {
+        let request: ListRequest<UserSortingField> =
+            serde_json::from_value(serde_json::json!({ "beforeCursor": "1:garbage" })).unwrap();
+        let err = Keyset::from_request(
+            request,
+            UserSortingField::CreatedAt,
+            SortingOrder::Ascending,
+            &cursor_config(&["new:secret"]),
+            time::OffsetDateTime::now_utc(),
+        )
+        .unwrap_err();
+        let err = serde_json::to_value(&err).unwrap();
+        assert_eq!(
+            err["beforeCursor"][0]["message"],
+            CursorError::VersionMismatch.to_string(),
+            "{err:?}"
+        );
+    }
+
+    fn cursor_config(keys: &[&str]) -> CursorConfig {
+        CursorConfig {
+            keys: keys.iter().map(|key| key.parse().unwrap()).collect(),
+            max_age: Some(time::Duration::hours(1)),
+        }
+    }
+
+    #[test]
+    fn cursor_config_requires_keys() {
+        let keys = CursorConfig::parse_keys(" key:new\nkey:old ").unwrap();
+        assert_eq!(
+            keys.iter().map(|key| &key.kid[..]).collect::<Vec<_>>(),
+            vec!["key", "key"]
+        );
+        assert!(matches!(
+            CursorConfig::parse_keys(" \t"),
+            Err(CursorKeyParseError::NoKeys)
+        ));
+        assert!(matches!(
+            CursorConfig::parse_keys("key:new nokid"),
+            Err(CursorKeyParseError::Malformed)
+        ));
+    }
+
+    fn signed_cursor(config: &CursorConfig, issued_at: time::OffsetDateTime) -> String {
+        Cursor {
+            value: ("archie".to_string(), uuid::Uuid::nil().to_string()),
+            field: UserSortingField::Username,
+            order: SortingOrder::Ascending,
+            filter: Some("a".into()),
+            inclusive: false,
+        }
+        .sign(config, issued_at)
+    }
+
+    fn verify(
+        cursor: &str,
+        config: &CursorConfig,
+        now: time::OffsetDateTime,
+    ) -> Result<Cursor<KeysetValue, UserSortingField>, CursorError> {
+        cursor.parse::<SignedCursor>()?.verify(config, now)
+    }
+
+    #[test]
+    fn cursors_verify_across_key_rotation() {
+        let now = time::OffsetDateTime::now_utc();
+        let cursor = signed_cursor(&cursor_config(&["old:secret"]), now);
+        let rotated = cursor_config(&["new:terces", "old:secret"]);
+        let verified = verify(&cursor, &rotated, now).unwrap();
+        assert_eq!(verified.value.0, "archie");
+        assert_eq!(verified.filter.as_deref(), Some("a"));
+        assert_eq!(
+            verify(&cursor, &cursor_config(&["new:terces"]), now).unwrap_err(),
+            CursorError::BadSignature
+        );
+        // same kid, different secret
+        assert_eq!(
+            verify(&cursor, &cursor_config(&["old:terces"]), now).unwrap_err(),
+            CursorError::BadSignature
+        );
+    }
+
+    #[test]
+    fn cursors_reject_tampering() {
+        let now = time::OffsetDateTime::now_utc();
+        let config = cursor_config(&["key:secret"]);
+        let cursor = signed_cursor(&config, now);
+        let forged = Cursor {
+            value: ("' OR TRUE --".to_string(), uuid::Uuid::nil().to_string()),
+            field: UserSortingField::Username,
+            order: SortingOrder::Ascending,
+            filter: None,
+            inclusive: false,
+        }
+        .sign(&cursor_config(&["key:guessed"]), now);
+        let (forged_payload, _) = forged.rsplit_once(':').unwrap();
+        let (_, signature) = cursor.rsplit_once(':').unwrap();
+        assert_eq!(
+            verify(&format!("{forged_payload}:{signature}"), &config, now).unwrap_err(),
+            CursorError::BadSignature
+        );
+        assert_eq!(
+            verify(&format!("{cursor}A"), &config, now).unwrap_err(),
+            CursorError::Malformed
+        );
+        assert_eq!(
+            verify("garbage", &config, now).unwrap_err(),
+            CursorError::Malformed
+        );
+        assert_eq!(
+            verify(&format!("2{}", &cursor[1..]), &config, now).unwrap_err(),
+            CursorError::VersionMismatch
+        );
+    }
+
+    #[test]
+    fn cursors_expire() {
+        let issued_at = time::OffsetDateTime::now_utc();
+        let mut config = cursor_config(&["key:secret"]);
+        let cursor = signed_cursor(&config, issued_at);
+        let later = issued_at + time::Duration::hours(2);
+        assert_eq!(
+            verify(&cursor, &config, later).unwrap_err(),
+            CursorError::Expired
+        );
+        config.max_age = None;
+        assert!(verify(&cursor, &config, later).is_ok());
     }
 }
@@ -51,6 +51,12 @@ pub type ExtraAssertions<'c, 'f> = dyn Fn(ExtraAssertionAgs<'c>) -> LocalBoxFutu
 
 pub struct TestContext {
     pub test_name: String,
+    /// Holds mail sent through the [`Context`].
+    pub mailer: crate::mail::MemoryMailer,
+    /// The clock of the [`Context`].
+    pub clock: crate::clock::FixedClock,
+    /// Configured as the `mock` OpenID Connect provider.
+    pub oidc_issuer: super::mock_oidc::MockOidcIssuer,
     ctx: Option<SharedContext>,
     // clean_up_closure: Option<Box<dyn FnOnce(Context) -> ()>>,
     clean_up_closure: Option<Box<dyn FnOnce(Context) -> futures::future::BoxFuture<'static, ()>>>,
@@ -58,14 +64,44 @@ pub struct TestContext {
 
 impl TestContext {
     pub async fn new(test_name: &'static str) -> Self {
+        Self::with_config(test_name, |_| {}).await
+    }
+
+    /// Lets tests adjust the defaults of [`new`](TestContext::new).
+    pub async fn with_config(
+        test_name: &'static str,
+        configure: impl FnOnce(&mut crate::Config),
+    ) -> Self {
         setup_tracing_once();
-        let test_name = test_name.replace("::tests::", "").replace("::", "_");
+        let test_name = database_name(test_name);
 
-        let config = crate::Config {
-            pass_salt_hash: b"sea brine".to_vec(),
+        let oidc_issuer = super::mock_oidc::MockOidcIssuer::start().await;
+        let mut config = crate::Config {
+            pass_pepper: None,
             argon2_conf: argon2::Config::default(),
-            auth_token_lifespan: time::Duration::seconds_f64(60. * 60. * 24. * 30.),
+            password_policy: Default::default(),
+            access_token_lifespan: time::Duration::seconds_f64(60. * 60.),
+            refresh_token_lifespan: time::Duration::seconds_f64(60. * 60. * 24. * 30.),
+            session_sweep_interval: time::Duration::minutes(10),
+            session_sweep_batch_size: 1000,
+            password_reset_token_lifespan: time::Duration::hours(1),
+            email_verification_token_lifespan: time::Duration::days(1),
+            unverified_account_grace_period: Some(time::Duration::days(7)),
+            totp_issuer: "web_api".into(),
+            totp_challenge_lifespan: time::Duration::minutes(5),
+            oidc_providers: vec![oidc_issuer.provider()],
+            oidc_state_lifespan: time::Duration::minutes(10),
+            login_lockout: Default::default(),
+            deleted_user_retention: None,
+            deleted_user_purge_interval: time::Duration::hours(1),
+            impersonation_lifespan: time::Duration::minutes(15),
+            jwt: None,
+            cursors: crate::utils::CursorConfig {
+                keys: vec!["test:secret".parse().unwrap()],
+                max_age: None,
+            },
         };
+        configure(&mut config);
 
         use sqlx::prelude::*;
         let opts = sqlx::postgres::PgConnectOptions::default()
@@ -126,15 +162,31 @@ impl TestContext {
             .await
             .expect("Failed to add test data");
 
-        let ctx = Context { db_pool, config };
+        let mailer = crate::mail::MemoryMailer::default();
+        let clock = crate::clock::FixedClock::default();
+        let ctx = Context {
+            mailer: std::sync::Arc::new(mailer.clone()),
+            clock: Box::new(clock.clone()),
+            http_client: reqwest::Client::new(),
+            policy: Box::new(crate::auth::policy::DefaultPolicy::new(db_pool.clone())),
+            session_cache: crate::auth::session_cache::SessionCache::new(
+                std::num::NonZeroUsize::new(1024).unwrap(),
+                time::Duration::minutes(1),
+            ),
+            db_pool,
+            config,
+        };
         Self {
             test_name: test_name.clone(), // someone needs it downwind
+            mailer,
+            clock,
+            oidc_issuer,
             ctx: Some(std::sync::Arc::new(ctx)),
             clean_up_closure: Some(Box::new(move |ctx| {
                 Box::pin(async move {
                     ctx.db_pool.close().await;
                     connection
-                        .execute(&format!(r###"DROP DATABASE {test_name}"###)[..])
+                        .execute(&format!(r###"DROP DATABASE {test_name} WITH (FORCE)"###)[..])
                         .await
                         .expect("Failed to drop test database.");
                 })
@@ -150,7 +202,7 @@ impl TestContext {
     pub async fn close(mut self) {
         let ctx = self.ctx.take().unwrap_or_log();
         let ctx = std::sync::Arc::<_>::try_unwrap(ctx).unwrap_or_log();
-        (self.clean_up_closure.take().unwrap())(ctx);
+        (self.clean_up_closure.take().unwrap())(ctx).await;
     }
     /* pub async fn new_with_service<F>(
         test_name: &'static str,
@@ -170,6 +222,20 @@ impl TestContext {
     } */
 }
 
+/// Postgres cuts identifiers off at 63 bytes, longer names get a hash of
+/// the full test path in their last bytes to keep them apart.
+fn database_name(test_name: &str) -> String {
+    const MAX_LEN: usize = 63;
+    let name = test_name.replace("::tests::", "").replace("::", "_");
+    if name.len() <= MAX_LEN {
+        return name;
+    }
+    use sha2::Digest;
+    let hash = sha2::Sha256::digest(test_name.as_bytes());
+    let hash = data_encoding::HEXLOWER.encode(&hash[..8]);
+    format!("{}_{hash}", &name[..MAX_LEN - hash.len() - 1])
+}
+
 impl Drop for TestContext {
     fn drop(&mut self) {
         if self.ctx.is_some() {